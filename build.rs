@@ -1,3 +1,14 @@
+use chrono::Utc;
+
 fn main() {
-    println!("cargo:rustc-link-arg=-Tlinkall.x");
+    // The host build only runs the tests, it has nothing to link against the chip's memory map
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("none") {
+        println!("cargo:rustc-link-arg=-Tlinkall.x");
+        // esp-hal brings the Xtensa startup code itself
+        if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("xtensa") {
+            println!("cargo:rustc-link-arg=-nostartfiles");
+        }
+    }
+    // The wall clock starts from the compile time until it is set on the device
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", Utc::now().timestamp());
 }
@@ -1,7 +1,8 @@
 use embedded_graphics::{prelude::Point, primitives::{line::Line, Rectangle}};
 use embedded_graphics::prelude::*;
 use embedded_graphics::geometry::AnchorPoint;
-use crate::constants::MAX_ANIMATIONS;
+use embedded_graphics::pixelcolor::Rgb565;
+use crate::constants::{CELEBRATION_FRAMES, MAX_ANIMATIONS};
 
 #[derive(Debug, Copy, Clone)]
 pub struct AnimationState {
@@ -19,6 +20,7 @@ impl Default for AnimationState {
 #[derive(Debug, Clone, Copy)]
 pub enum Animation {
     Cursor(CursorMove),
+    Celebrate(Celebration),
     Empty
 }
 
@@ -31,6 +33,7 @@ impl AnimationEvent for Animation {
     fn get_frame(&mut self) -> FrameType {
         match self {
             Self::Cursor(cursor_data) => cursor_data.get_frame(),
+            Self::Celebrate(celebration) => celebration.get_frame(),
             Self::Empty => FrameType::Empty
         }
     }
@@ -38,6 +41,7 @@ impl AnimationEvent for Animation {
     fn frame_data(&self) -> &FrameData {
         match self {
             Self::Cursor(cursor_data) => &cursor_data.frame_data,
+            Self::Celebrate(celebration) => &celebration.frame_data,
             Self::Empty => {
                 &FrameData {
                     frame_index: 0,
@@ -51,6 +55,8 @@ impl AnimationEvent for Animation {
 #[derive(Debug, Clone, Copy)]
 pub enum FrameType {
     Rectangle(Rectangle),
+    // Solid area drawn straight over whatever is on screen
+    Fill(Rectangle, Rgb565),
     Empty
 }
 
@@ -106,6 +112,42 @@ impl CursorMove {
     }
 }
 
+// Flashes an area between two colours and finishes on the first one
+#[derive(Debug, Clone, Copy)]
+pub struct Celebration {
+    pub area: Rectangle,
+    pub color: Rgb565,
+    pub flash_color: Rgb565,
+    pub frame_data: FrameData
+}
+
+impl Celebration {
+    pub const fn new(area: Rectangle, color: Rgb565, flash_color: Rgb565) -> Self {
+        Self {
+            area,
+            color,
+            flash_color,
+            frame_data: FrameData {
+                frame_index: 0,
+                frame_count: CELEBRATION_FRAMES
+            }
+        }
+    }
+
+    pub fn get_frame(&mut self) -> FrameType {
+        let index = self.frame_data.frame_index;
+        if index >= self.frame_data.frame_count {
+            return FrameType::Empty
+        }
+        self.frame_data.frame_index += 1;
+
+        // Four frames per flash, and the last frame always leaves the area in its own colour
+        let flashing = (index / 4) % 2 == 0 && index + 1 < self.frame_data.frame_count;
+        let color = if flashing { self.flash_color } else { self.color };
+        FrameType::Fill(self.area, color)
+    }
+}
+
 pub struct AnimatedSprite {
     frames: [u16; 30],
     frame_index: usize,
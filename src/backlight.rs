@@ -0,0 +1,114 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant};
+
+use crate::{constants::{BACKLIGHT_FADE, BACKLIGHT_WAKE_FADE}, settings::Settings};
+
+const ZERO: Duration = Duration::from_ticks(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BacklightLevel {
+    pub percent: u8,
+    // How long getting there takes
+    pub fade: Duration
+}
+
+impl BacklightLevel {
+    pub const OFF: Self = Self { percent: 0, fade: ZERO };
+}
+
+pub type BacklightNotifier = Signal<CriticalSectionRawMutex, BacklightLevel>;
+
+// Straight ramp from one level to another
+#[derive(Debug, Clone, Copy)]
+pub struct Fade {
+    from: u8,
+    to: u8,
+    started: Instant,
+    length: Duration
+}
+
+impl Fade {
+    pub const fn steady(percent: u8, now: Instant) -> Self {
+        Self { from: percent, to: percent, started: now, length: ZERO }
+    }
+
+    // Starts from wherever this fade has got to, so a new level halfway through doesn't jump
+    pub fn towards(&self, level: BacklightLevel, now: Instant) -> Self {
+        Self { from: self.level(now), to: level.percent, started: now, length: level.fade }
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= self.length
+    }
+
+    pub fn level(&self, now: Instant) -> u8 {
+        if self.is_done(now) {
+            return self.to
+        }
+        let elapsed = now.saturating_duration_since(self.started).as_ticks() as i64;
+        let span = i64::from(self.to) - i64::from(self.from);
+        (i64::from(self.from) + span * elapsed / self.length.as_ticks() as i64) as u8
+    }
+}
+
+/*
+ * Picks the backlight level from the settings, the hour and the time since the last input.
+ * Takes the current Instant like the other policies so it can be driven without hardware.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Dimmer {
+    last_input: Instant,
+    // Level last handed to the backlight
+    sent: Option<u8>
+}
+
+impl Dimmer {
+    pub const fn new(now: Instant) -> Self {
+        Self { last_input: now, sent: None }
+    }
+
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+
+    // For when something else switched the backlight, so the next update sends the level again
+    pub fn reset(&mut self) {
+        self.sent = None;
+    }
+
+    fn dimmed(&self, settings: &Settings, now: Instant) -> bool {
+        settings.dim_after.is_some_and(|after| now.saturating_duration_since(self.last_input) >= after)
+    }
+
+    pub fn target(&self, settings: &Settings, hour: u8, now: Instant) -> u8 {
+        let mut percent = settings.brightness;
+        if let Some(night) = settings.night.filter(|night| night.covers(hour)) {
+            percent = percent.min(night.brightness);
+        }
+        if self.dimmed(settings, now) {
+            percent = percent.min(settings.dim_brightness);
+        }
+        percent
+    }
+
+    pub fn until_dim(&self, settings: &Settings, now: Instant) -> Option<Duration> {
+        let after = settings.dim_after?;
+        after
+            .checked_sub(now.saturating_duration_since(self.last_input))
+            .filter(|left| *left > ZERO)
+    }
+
+    // Returns the level to fade to whenever the target changes; brightening is quick so input wakes the screen at once
+    pub fn update(&mut self, settings: &Settings, hour: u8, now: Instant) -> Option<BacklightLevel> {
+        let percent = self.target(settings, hour, now);
+        if self.sent == Some(percent) {
+            return None
+        }
+        let fade = match self.sent {
+            Some(sent) if percent < sent => BACKLIGHT_FADE,
+            _ => BACKLIGHT_WAKE_FADE
+        };
+        self.sent = Some(percent);
+        Some(BacklightLevel { percent, fade })
+    }
+}
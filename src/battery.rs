@@ -0,0 +1,255 @@
+use crate::constants::BATTERY_HYSTERESIS;
+
+// Anything that can measure the cell voltage, in millivolts
+pub trait BatteryVoltage {
+    fn read_millivolts(&mut self) -> Option<u32>;
+}
+
+// One ADC conversion of the pin's voltage in millivolts, WouldBlock until it's done
+pub trait PinVoltage {
+    fn read_pin(&mut self) -> nb::Result<u16, ()>;
+}
+
+/*
+ * Cell voltage through a resistor divider in front of an ADC pin.
+ * `divider` is the factor the divider scales the voltage down by, 2 for two equal resistors.
+ */
+pub struct DividedVoltage<P> {
+    pin: P,
+    divider: u32
+}
+
+impl<P: PinVoltage> DividedVoltage<P> {
+    pub const fn new(pin: P, divider: u32) -> Self {
+        Self { pin, divider }
+    }
+}
+
+impl<P: PinVoltage> BatteryVoltage for DividedVoltage<P> {
+    fn read_millivolts(&mut self) -> Option<u32> {
+        loop {
+            match self.pin.read_pin() {
+                Ok(millivolts) => return Some(u32::from(millivolts) * self.divider),
+                Err(nb::Error::WouldBlock) => continue,
+                Err(nb::Error::Other(())) => return None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    Discharging,
+    Charging,
+    Full
+}
+
+/*
+ * Resting voltage of a single LiPo cell against the charge left, highest first.
+ * Between two points the percentage is interpolated.
+ */
+const DISCHARGE_CURVE: [(u32, u8); 12] = [
+    (4200, 100),
+    (4110, 90),
+    (4020, 80),
+    (3950, 70),
+    (3870, 60),
+    (3840, 50),
+    (3800, 40),
+    (3770, 30),
+    (3730, 20),
+    (3690, 10),
+    (3610, 5),
+    (3300, 0)
+];
+
+pub fn percent_for(millivolts: u32) -> u8 {
+    let (highest, full) = DISCHARGE_CURVE[0];
+    if millivolts >= highest {
+        return full
+    }
+    for pair in DISCHARGE_CURVE.windows(2) {
+        let ((upper_mv, upper), (lower_mv, lower)) = (pair[0], pair[1]);
+        if millivolts >= lower_mv {
+            let span = u32::from(upper - lower);
+            return lower + ((millivolts - lower_mv) * span / (upper_mv - lower_mv)) as u8
+        }
+    }
+    0
+}
+
+// Exponential moving average, so one noisy sample or a load spike barely moves the reading
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VoltageFilter {
+    millivolts: Option<u32>
+}
+
+impl VoltageFilter {
+    // Each sample moves the average by 1/2^SHIFT of the difference
+    const SHIFT: u32 = 3;
+
+    pub const fn new() -> Self {
+        Self { millivolts: None }
+    }
+
+    pub fn update(&mut self, sample: u32) -> u32 {
+        let filtered = match self.millivolts {
+            Some(average) if sample >= average => average + ((sample - average) >> Self::SHIFT),
+            Some(average) => average - ((average - sample) >> Self::SHIFT),
+            None => sample
+        };
+        self.millivolts = Some(filtered);
+        filtered
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    pub percent: u8,
+    pub charge: ChargeState
+}
+
+/*
+ * Filtered voltage to the percentage on screen.
+ * The shown value only follows once it's BATTERY_HYSTERESIS points off, and never rises
+ * while discharging, so it doesn't flicker between two values or climb back after a load.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatteryGauge {
+    filter: VoltageFilter,
+    shown: Option<u8>
+}
+
+impl BatteryGauge {
+    pub const fn new() -> Self {
+        Self { filter: VoltageFilter::new(), shown: None }
+    }
+
+    pub fn update(&mut self, millivolts: u32, charge: ChargeState) -> BatteryStatus {
+        let percent = percent_for(self.filter.update(millivolts));
+        let shown = match (self.shown, charge) {
+            (_, ChargeState::Full) => 100,
+            (None, _) => percent,
+            (Some(shown), ChargeState::Discharging) if percent < shown && shown - percent >= BATTERY_HYSTERESIS => percent,
+            (Some(shown), ChargeState::Charging) if percent.abs_diff(shown) >= BATTERY_HYSTERESIS => percent,
+            (Some(shown), _) => shown
+        };
+        self.shown = Some(shown);
+        BatteryStatus { percent: shown, charge }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enough samples of one voltage for the filter to stop moving
+    fn settle(gauge: &mut BatteryGauge, millivolts: u32, charge: ChargeState) -> u8 {
+        (0..64).map(|_| gauge.update(millivolts, charge).percent).last().unwrap()
+    }
+
+    struct ScriptedAdc {
+        results: &'static [nb::Result<u16, ()>],
+        reads: usize
+    }
+
+    impl PinVoltage for ScriptedAdc {
+        fn read_pin(&mut self) -> nb::Result<u16, ()> {
+            self.reads += 1;
+            self.results[self.reads - 1]
+        }
+    }
+
+    #[test]
+    fn curve_points_and_the_ends() {
+        for (millivolts, percent) in DISCHARGE_CURVE {
+            assert_eq!(percent_for(millivolts), percent);
+        }
+        assert_eq!(percent_for(4350), 100);
+        assert_eq!(percent_for(3100), 0);
+        assert_eq!(percent_for(0), 0);
+    }
+
+    #[test]
+    fn between_points_is_interpolated() {
+        assert_eq!(percent_for(4155), 95);
+        assert_eq!(percent_for(3820), 45);
+        assert_eq!(percent_for(3455), 2);
+        // Rounds down, so a cell only just above a point shows that point
+        assert_eq!(percent_for(3841), 50);
+    }
+
+    #[test]
+    fn filter_takes_the_first_sample_then_an_eighth_of_each_change() {
+        let mut filter = VoltageFilter::new();
+        assert_eq!(filter.update(4000), 4000);
+        assert_eq!(filter.update(4080), 4010);
+        assert_eq!(filter.update(3920), 3999);
+        // Less than 8 mV off doesn't move it at all
+        assert_eq!(filter.update(4006), 3999);
+        assert_eq!(filter.update(3992), 3999);
+    }
+
+    #[test]
+    fn filter_rides_out_a_spike() {
+        let mut filter = VoltageFilter::new();
+        filter.update(3900);
+        let dipped = filter.update(3500);
+        assert_eq!(dipped, 3850);
+        assert!(percent_for(dipped) >= 50);
+    }
+
+    #[test]
+    fn discharging_only_drops_by_the_hysteresis() {
+        let mut gauge = BatteryGauge::new();
+        assert_eq!(gauge.update(3840, ChargeState::Discharging).percent, 50);
+        // Settles at 49%, one point down
+        assert_eq!(settle(&mut gauge, 3830, ChargeState::Discharging), 50);
+        assert_eq!(settle(&mut gauge, 3810, ChargeState::Discharging), 44);
+        assert_eq!(settle(&mut gauge, 3770, ChargeState::Discharging), 32);
+    }
+
+    #[test]
+    fn discharging_never_climbs_back() {
+        let mut gauge = BatteryGauge::new();
+        gauge.update(3770, ChargeState::Discharging);
+        // The cell recovering after a load is lifted
+        assert_eq!(settle(&mut gauge, 3950, ChargeState::Discharging), 30);
+    }
+
+    #[test]
+    fn charging_follows_either_way() {
+        let mut gauge = BatteryGauge::new();
+        assert_eq!(gauge.update(3770, ChargeState::Charging).percent, 30);
+        // Stops where the last step left it, one point short of where the filter settles
+        assert_eq!(settle(&mut gauge, 3950, ChargeState::Charging), 68);
+        assert_eq!(settle(&mut gauge, 3870, ChargeState::Charging), 62);
+        // Within the hysteresis it holds
+        assert_eq!(settle(&mut gauge, 3880, ChargeState::Charging), 62);
+    }
+
+    #[test]
+    fn full_shows_a_hundred() {
+        let mut gauge = BatteryGauge::new();
+        let status = gauge.update(4100, ChargeState::Full);
+        assert_eq!(status, BatteryStatus { percent: 100, charge: ChargeState::Full });
+        // Coming off the charger it drops to what the cell says
+        assert_eq!(settle(&mut gauge, 4100, ChargeState::Discharging), 88);
+    }
+
+    #[test]
+    fn divider_scales_the_pin_up_and_waits_for_the_conversion() {
+        let mut battery = DividedVoltage::new(ScriptedAdc {
+            results: &[Err(nb::Error::WouldBlock), Err(nb::Error::WouldBlock), Ok(1900)],
+            reads: 0
+        }, 2);
+        assert_eq!(battery.read_millivolts(), Some(3800));
+        assert_eq!(battery.pin.reads, 3);
+    }
+
+    #[test]
+    fn a_failed_conversion_is_no_reading() {
+        let mut battery = DividedVoltage::new(ScriptedAdc { results: &[Err(nb::Error::Other(()))], reads: 0 }, 2);
+        assert_eq!(battery.read_millivolts(), None);
+    }
+}
@@ -4,17 +4,22 @@
 
 use embassy_executor::Spawner;
 use esp_backtrace as _;
-use esp_hal::gpio;
-use esp_hal::{clock::CpuClock, gpio::Input};
+use esp_hal::clock::CpuClock;
 use esp_hal::timer::timg::TimerGroup;
 use embedded_graphics::{
     prelude::*,
     pixelcolor::Rgb565
 };
-use pitft_async::clock_util::{DoubleTimerSession, SessionNotifier};
-use pitft_async::{button::Button, clock_util::SessionState, tft::TFT};
+use pitft_async::session::{DoubleTimerSession, SessionNotifier};
+use pitft_async::{board::{self, Board, LightSleep}, button::ButtonId, tft::TFT};
 use log::info;
-use pitft_async::error::Result;
+use pitft_async::error::{Error, Result};
+use pitft_async::presets::PresetStore;
+use pitft_async::constants::PANEL_SLEEP_SETTLE;
+use embassy_futures::select::{select, Either};
+use embassy_time::Timer;
+use esp_hal::ledc::{LSGlobalClkSource, Ledc};
+use static_cell::StaticCell;
 
 #[derive(Debug)]
 pub enum Never {}
@@ -26,32 +31,24 @@ async fn main(spawner: Spawner) -> ! {
 }
 
 async fn inner_main(spawner: Spawner) -> Result<Never> {
+    // The library logs through the log macros, at the level ESP_LOG was set to when building
+    esp_println::logger::init_logger_from_env();
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
-    let peripherals = esp_hal::init(config);
-    let cs = peripherals.GPIO2;
-    let dc = peripherals.GPIO4;
-    let mosi = peripherals.GPIO7;
-    let miso = peripherals.GPIO5;
-    let sclk = peripherals.GPIO6;
-    let rst = peripherals.GPIO3;
-    let input = Input::new(peripherals.GPIO1, gpio::Pull::Down);
+    let board = Board::new(esp_hal::init(config));
+    info!("Board: {}", board.name);
 
     // create TFT struct with direct display control
-    let mut tft = TFT::new(
-        peripherals.SPI2, 
-        sclk, 
-        miso, 
-        mosi, 
-        cs, 
-        rst, 
-        dc);
-    let mut button = Button::new(input);
+    let mut tft = TFT::new(board.spi, board.display);
+    // Boards without a main button start and stop the timers with the encoder switch instead
+    let (mut button, encoder_switch) = match (board.button, board.encoder_switch) {
+        (Some(button), switch) => (button, switch),
+        (None, Some(switch)) => (switch, None),
+        (None, None) => return Err(Error::NoButton)
+    };
     esp_println::println!("Initialized Button!");
 
-    let mut state = SessionState::default();
-
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let timg0 = TimerGroup::new(board.timg0);
     esp_hal_embassy::init(timg0.timer0);
 
     tft.clear(Rgb565::BLACK);
@@ -60,9 +57,57 @@ async fn inner_main(spawner: Spawner) -> Result<Never> {
     tft.initialize_scene();
 
     static SESSION_NOTIFIER: SessionNotifier = DoubleTimerSession::notifier();
-    let mut session = DoubleTimerSession::new(tft, spawner, &SESSION_NOTIFIER)?;
+    // The session boots into the last used preset with the picker open
+    let presets = PresetStore::new(board::preset_storage());
+    let session = DoubleTimerSession::new(tft, spawner, &SESSION_NOTIFIER, presets)?;
+    if let Some(encoder) = board.encoder {
+        session.attach_encoder(encoder, spawner)?;
+    }
+    if let Some(switch) = encoder_switch {
+        session.attach_encoder_switch(switch, spawner)?;
+    }
+    session.attach_console(board.console, spawner)?;
+    if let Some(touch) = board.touch {
+        session.attach_touch(touch, spawner)?;
+    }
+    static LEDC: StaticCell<Ledc<'static>> = StaticCell::new();
+    let ledc = LEDC.init(Ledc::new(board.ledc));
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    let ledc: &'static Ledc<'static> = ledc;
+    if let Some(backlight) = board.backlight {
+        session.attach_backlight(ledc, backlight, spawner)?;
+    }
+    if let Some(buzzer) = board.buzzer {
+        session.attach_buzzer(ledc, buzzer, spawner)?;
+    }
+    if let Some(battery) = board.battery {
+        session.attach_battery(battery, board.charger, spawner)?;
+    }
+    let mut sleep = LightSleep::new(board.lpwr);
     loop {
         esp_println::println!("im in da embussy :3");
-        state = state.execute(&mut session, &mut button).await;
+        match select(button.press_duration(), session.wait_for_sleep()).await {
+            Either::First(press) => session.send_press(ButtonId::Main, press).await,
+            Either::Second(()) => {
+                // Lets the render task blank the panel first, a press meanwhile calls the sleep off
+                let armed = select(button.press_duration(), async {
+                    Timer::after(PANEL_SLEEP_SETTLE).await;
+                    session.arm_wake_sources().await
+                }).await;
+                match armed {
+                    Either::First(press) => {
+                        session.disarm_wake_sources();
+                        session.send_press(ButtonId::Main, press).await;
+                    }
+                    Either::Second(false) => session.disarm_wake_sources(),
+                    Either::Second(true) => {
+                        // The press that wakes the chip is swallowed, press_duration waits for its release first
+                        let missed = sleep.until_woken(&mut button);
+                        session.disarm_wake_sources();
+                        session.woke(missed).await;
+                    }
+                }
+            }
+        }
     }
 }
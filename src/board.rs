@@ -0,0 +1,384 @@
+use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcChannel, AdcConfig, AdcPin, Attenuation},
+    gpio::{AnalogPin, AnyPin, Input, Pin, Pull, WakeEvent},
+    i2c::master::I2c,
+    peripherals::{Peripherals, ADC1, LEDC, LPWR, SPI2, TIMG0},
+    rtc_cntl::{sleep::GpioWakeupSource, Rtc},
+    usb_serial_jtag::UsbSerialJtag,
+    Async
+};
+use embassy_time::{Duration, Instant};
+
+use esp_storage::FlashStorage;
+
+use crate::{battery::{BatteryVoltage, ChargeState, DividedVoltage, PinVoltage}, button::Button, encoder::Encoder, presets::{Partition, STORE_SIZE}};
+
+/*
+ * Each board is a description below, picked with one of the board-* cargo features.
+ * A description hands out the same Board, so bringing up a new revision only means adding one.
+ */
+#[cfg(not(any(
+    feature = "board-breadboard",
+    feature = "board-double-timer",
+    feature = "board-pocketdigi-rev0",
+    feature = "board-pocketdigi-rev1")))]
+compile_error!("select a board with one of the board-* features");
+
+// The breadboard is the default, so picking another board needs --no-default-features
+#[cfg(any(
+    all(feature = "board-breadboard", any(feature = "board-double-timer", feature = "board-pocketdigi-rev0", feature = "board-pocketdigi-rev1")),
+    all(feature = "board-double-timer", any(feature = "board-pocketdigi-rev0", feature = "board-pocketdigi-rev1")),
+    all(feature = "board-pocketdigi-rev0", feature = "board-pocketdigi-rev1")))]
+compile_error!("more than one board-* feature is selected, add --no-default-features when picking a board other than the breadboard");
+
+pub type BoardButton = Button<Input<'static>>;
+pub type BoardEncoder = Encoder<Input<'static>, Input<'static>>;
+pub type TouchBus = I2c<'static, Async>;
+// The touch controller's INT line, held low while the panel is touched
+pub struct TouchInterrupt(Input<'static>);
+
+pub struct TouchPins {
+    pub bus: TouchBus,
+    pub interrupt: TouchInterrupt
+}
+
+// Every board has the chip's own USB port for flashing, logs and the serial console
+pub type BoardConsole = UsbSerialJtag<'static, Async>;
+
+// For parts a board doesn't have, so their Option can only ever be None
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotFitted {}
+
+pub struct DisplayPins {
+    pub sclk: AnyPin,
+    pub miso: AnyPin,
+    pub mosi: AnyPin,
+    pub cs: AnyPin,
+    pub dc: AnyPin,
+    // None when the panel's reset is tied to the chip's EN line
+    pub rst: Option<AnyPin>
+}
+
+// Status outputs of the charger IC, pulled low while active
+pub struct ChargerPins {
+    pub charging: Input<'static>,
+    pub done: Option<Input<'static>>
+}
+
+impl ChargerPins {
+    pub fn state(&self) -> ChargeState {
+        if self.done.as_ref().is_some_and(|done| done.is_low()) {
+            ChargeState::Full
+        } else if self.charging.is_low() {
+            ChargeState::Charging
+        } else {
+            ChargeState::Discharging
+        }
+    }
+}
+
+impl BatteryVoltage for NotFitted {
+    fn read_millivolts(&mut self) -> Option<u32> {
+        match *self {}
+    }
+}
+
+// An ADC1 pin read with the chip's calibration, for a battery divider
+pub struct AdcPinVoltage<P> {
+    adc: Adc<'static, ADC1>,
+    pin: AdcPin<P, ADC1, AdcCalCurve<ADC1>>
+}
+
+impl<P: AdcChannel + AnalogPin> AdcPinVoltage<P> {
+    pub fn new(adc: ADC1, pin: P) -> Self {
+        let mut config = AdcConfig::new();
+        let pin = config.enable_pin_with_cal::<_, AdcCalCurve<ADC1>>(pin, Attenuation::_11dB);
+        Self { adc: Adc::new(adc, config), pin }
+    }
+}
+
+impl<P: AdcChannel> PinVoltage for AdcPinVoltage<P> {
+    fn read_pin(&mut self) -> nb::Result<u16, ()> {
+        self.adc.read_oneshot(&mut self.pin)
+    }
+}
+
+// What a board with a divider on an ADC1 pin sets its BatteryChannel to
+pub type AdcBattery<P> = DividedVoltage<AdcPinVoltage<P>>;
+
+/*
+ * Light sleep only wakes on a pin's level, so each input is armed for the level it isn't at.
+ * Waiting on a pin again takes the wakeup back, so the tasks disarm their pins before they do.
+ */
+impl BoardButton {
+    pub fn wake_on_press(&mut self, enable: bool) {
+        let event = if self.is_active_high() { WakeEvent::HighLevel } else { WakeEvent::LowLevel };
+        self.pin().wakeup_enable(enable, event).unwrap();
+    }
+}
+
+impl BoardEncoder {
+    // Any movement changes the level of one of the two pins
+    pub fn wake_on_turn(&mut self, enable: bool) {
+        let (pin_a, pin_b) = self.pins();
+        for pin in [pin_a, pin_b] {
+            let event = if pin.is_high() { WakeEvent::LowLevel } else { WakeEvent::HighLevel };
+            pin.wakeup_enable(enable, event).unwrap();
+        }
+    }
+}
+
+impl TouchInterrupt {
+    pub fn wake_on_touch(&mut self, enable: bool) {
+        self.0.wakeup_enable(enable, WakeEvent::LowLevel).unwrap();
+    }
+}
+
+// Light sleep with the main button and whatever the input tasks armed as wake sources
+pub struct LightSleep {
+    rtc: Rtc<'static>
+}
+
+impl LightSleep {
+    pub fn new(lpwr: LPWR) -> Self {
+        Self { rtc: Rtc::new(lpwr) }
+    }
+
+    /*
+     * Blocks until a wake source fires.
+     * The embassy timer stops along with the CPU, so this returns how far Instant fell behind the RTC,
+     * which keeps counting through light sleep.
+     */
+    pub fn until_woken(&mut self, button: &mut BoardButton) -> Duration {
+        let instant_before = Instant::now();
+        let rtc_before = self.rtc.time_since_boot();
+
+        button.wake_on_press(true);
+        self.rtc.sleep_light(&[&GpioWakeupSource::new()]);
+        button.wake_on_press(false);
+
+        let slept = Duration::from_micros((self.rtc.time_since_boot() - rtc_before).to_micros());
+        let counted = Instant::now().saturating_duration_since(instant_before);
+        slept.checked_sub(counted).unwrap_or(Duration::from_ticks(0))
+    }
+}
+
+// Sector partitions.csv reserves for the presets, the same on every board
+const PRESET_PARTITION: u32 = 0x3F_0000;
+const _: () = assert!(STORE_SIZE <= 0x1000);
+
+pub type PresetStorage = Partition<FlashStorage, STORE_SIZE>;
+
+// FlashStorage erases and rewrites whole sectors itself, so the store can write anywhere in it
+pub fn preset_storage() -> PresetStorage {
+    Partition::new(FlashStorage::new(), PRESET_PARTITION)
+}
+
+// Everything the firmware takes from the chip, already set up as inputs where that applies
+pub struct Board {
+    pub name: &'static str,
+    pub spi: SPI2,
+    pub display: DisplayPins,
+    pub button: Option<BoardButton>,
+    pub encoder: Option<BoardEncoder>,
+    pub encoder_switch: Option<BoardButton>,
+    pub touch: Option<TouchPins>,
+    pub backlight: Option<AnyPin>,
+    // Piezo, none of the boards have one fitted yet
+    pub buzzer: Option<AnyPin>,
+    pub battery: Option<BatteryChannel>,
+    pub charger: Option<ChargerPins>,
+    pub console: BoardConsole,
+    pub ledc: LEDC,
+    pub lpwr: LPWR,
+    pub timg0: TIMG0
+}
+
+impl Board {
+    pub fn new(peripherals: Peripherals) -> Self {
+        take(peripherals)
+    }
+}
+
+#[cfg(feature = "board-breadboard")]
+pub use breadboard::*;
+#[cfg(feature = "board-double-timer")]
+pub use double_timer::*;
+#[cfg(feature = "board-pocketdigi-rev0")]
+pub use pocketdigi_rev0::*;
+#[cfg(feature = "board-pocketdigi-rev1")]
+pub use pocketdigi_rev1::*;
+
+/*
+ * ESP32-C3 devkit on a breadboard.
+ * The encoder switch is on GPIO9, which is also the boot strap pin:
+ * holding it down while resetting enters the ROM download mode.
+ * The touch controller's I2C bus would take GPIO8 and GPIO18, the latter being the native USB D- line,
+ * so it's only wired up with the breadboard-touch feature. That costs the serial console and USB logs,
+ * flash and log over the UART bridge instead. Its INT line then goes on GPIO19, the D+ line USB no longer needs.
+ */
+#[cfg(feature = "board-breadboard")]
+mod breadboard {
+    use super::*;
+    #[cfg(feature = "breadboard-touch")]
+    use esp_hal::i2c::master::Config as I2cConfig;
+
+    pub type BatteryChannel = NotFitted;
+
+    pub(super) fn take(peripherals: Peripherals) -> Board {
+        Board {
+            name: "breadboard",
+            spi: peripherals.SPI2,
+            display: DisplayPins {
+                sclk: peripherals.GPIO6.degrade(),
+                miso: peripherals.GPIO5.degrade(),
+                mosi: peripherals.GPIO7.degrade(),
+                cs: peripherals.GPIO2.degrade(),
+                dc: peripherals.GPIO4.degrade(),
+                rst: Some(peripherals.GPIO3.degrade())
+            },
+            button: Some(Button::new(Input::new(peripherals.GPIO1, Pull::Down))),
+            encoder: Some(Encoder::new(
+                Input::new(peripherals.GPIO0, Pull::Up),
+                Input::new(peripherals.GPIO10, Pull::Up))),
+            encoder_switch: Some(Button::active_low(Input::new(peripherals.GPIO9, Pull::Up))),
+            #[cfg(feature = "breadboard-touch")]
+            touch: Some(TouchPins {
+                bus: I2c::new(peripherals.I2C0, I2cConfig::default())
+                    .unwrap()
+                    .with_sda(peripherals.GPIO8)
+                    .with_scl(peripherals.GPIO18)
+                    .into_async(),
+                interrupt: TouchInterrupt(Input::new(peripherals.GPIO19, Pull::Up))
+            }),
+            #[cfg(not(feature = "breadboard-touch"))]
+            touch: None,
+            backlight: None,
+            buzzer: None,
+            battery: None,
+            charger: None,
+            console: UsbSerialJtag::new(peripherals.USB_DEVICE).into_async(),
+            ledc: peripherals.LEDC,
+            lpwr: peripherals.LPWR,
+            timg0: peripherals.TIMG0
+        }
+    }
+}
+
+/*
+ * Double timer PCB draft, ESP32-C3-MINI-1.
+ * One button on GPIO9 with an external pull-up and no encoder.
+ * GPIO0 drives the RGB LED, GPIO2 is only strapped high.
+ */
+#[cfg(feature = "board-double-timer")]
+mod double_timer {
+    use super::*;
+
+    pub type BatteryChannel = NotFitted;
+
+    pub(super) fn take(peripherals: Peripherals) -> Board {
+        Board {
+            name: "double timer",
+            spi: peripherals.SPI2,
+            display: DisplayPins {
+                sclk: peripherals.GPIO6.degrade(),
+                miso: peripherals.GPIO5.degrade(),
+                mosi: peripherals.GPIO7.degrade(),
+                cs: peripherals.GPIO10.degrade(),
+                dc: peripherals.GPIO4.degrade(),
+                rst: Some(peripherals.GPIO3.degrade())
+            },
+            button: Some(Button::active_low(Input::new(peripherals.GPIO9, Pull::None))),
+            encoder: None,
+            encoder_switch: None,
+            touch: None,
+            backlight: Some(peripherals.GPIO1.degrade()),
+            buzzer: None,
+            battery: None,
+            charger: None,
+            console: UsbSerialJtag::new(peripherals.USB_DEVICE).into_async(),
+            ledc: peripherals.LEDC,
+            lpwr: peripherals.LPWR,
+            timg0: peripherals.TIMG0
+        }
+    }
+}
+
+/*
+ * pocketdigi V1 Rev.0, ESP32-S3-WROOM-1U.
+ * The PEC11L encoder has external pull-ups and its switch is the only button.
+ * The panel's reset is tied to EN and the backlight isn't routed to the chip yet.
+ * The charger's LED outputs and BAT_OUT aren't connected either.
+ */
+#[cfg(feature = "board-pocketdigi-rev0")]
+mod pocketdigi_rev0 {
+    use super::*;
+
+    pub type BatteryChannel = NotFitted;
+
+    pub(super) fn take(peripherals: Peripherals) -> Board {
+        Board {
+            name: "pocketdigi rev0",
+            spi: peripherals.SPI2,
+            display: DisplayPins {
+                sclk: peripherals.GPIO12.degrade(),
+                miso: peripherals.GPIO13.degrade(),
+                mosi: peripherals.GPIO11.degrade(),
+                cs: peripherals.GPIO10.degrade(),
+                dc: peripherals.GPIO5.degrade(),
+                rst: None
+            },
+            button: None,
+            encoder: Some(Encoder::new(
+                Input::new(peripherals.GPIO4, Pull::None),
+                Input::new(peripherals.GPIO16, Pull::None))),
+            encoder_switch: Some(Button::active_low(Input::new(peripherals.GPIO15, Pull::None))),
+            touch: None,
+            backlight: None,
+            buzzer: None,
+            battery: None,
+            charger: None,
+            console: UsbSerialJtag::new(peripherals.USB_DEVICE).into_async(),
+            ledc: peripherals.LEDC,
+            lpwr: peripherals.LPWR,
+            timg0: peripherals.TIMG0
+        }
+    }
+}
+
+// pocketdigi V1 Rev.1, the same as Rev.0 with the panel's BL_3V3 backlight line on GPIO1
+#[cfg(feature = "board-pocketdigi-rev1")]
+mod pocketdigi_rev1 {
+    use super::*;
+
+    pub type BatteryChannel = NotFitted;
+
+    pub(super) fn take(peripherals: Peripherals) -> Board {
+        Board {
+            name: "pocketdigi rev1",
+            spi: peripherals.SPI2,
+            display: DisplayPins {
+                sclk: peripherals.GPIO12.degrade(),
+                miso: peripherals.GPIO13.degrade(),
+                mosi: peripherals.GPIO11.degrade(),
+                cs: peripherals.GPIO10.degrade(),
+                dc: peripherals.GPIO5.degrade(),
+                rst: None
+            },
+            button: None,
+            encoder: Some(Encoder::new(
+                Input::new(peripherals.GPIO4, Pull::None),
+                Input::new(peripherals.GPIO16, Pull::None))),
+            encoder_switch: Some(Button::active_low(Input::new(peripherals.GPIO15, Pull::None))),
+            touch: None,
+            backlight: Some(peripherals.GPIO1.degrade()),
+            buzzer: None,
+            battery: None,
+            charger: None,
+            console: UsbSerialJtag::new(peripherals.USB_DEVICE).into_async(),
+            ledc: peripherals.LEDC,
+            lpwr: peripherals.LPWR,
+            timg0: peripherals.TIMG0
+        }
+    }
+}
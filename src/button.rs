@@ -1,28 +1,62 @@
 use embassy_futures::select::{select, Either};
-use esp_hal::gpio::Input;
 use embassy_time::{Duration, Timer};
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
 
-pub struct Button<'a>(Input<'a>);
+/*
+ * Press gesture recognition on any async input pin.
+ * Pin errors are treated like the encoder's and panic, the chip's GPIO can't fail.
+ */
+pub struct Button<P> {
+    input: P,
+    // Pulled down and pressed to high, or pulled up and pressed to ground
+    active_high: bool
+}
+
+#[cfg(target_os = "none")]
+impl<P> Button<P> {
+    // For the board code that arms the pin as a wake source
+    pub(crate) fn pin(&mut self) -> &mut P {
+        &mut self.input
+    }
+
+    pub(crate) const fn is_active_high(&self) -> bool {
+        self.active_high
+    }
+}
 
 const DEBOUNCE_DELAY: Duration = Duration::from_millis(50);
 const LONG_PRESS: Duration = Duration::from_millis(1000);
 
 
-impl<'a> Button<'a> {
-    pub const fn new(button: Input<'a>) -> Self {
-        Self(button)
+impl<P: Wait + InputPin> Button<P> {
+    pub const fn new(button: P) -> Self {
+        Self { input: button, active_high: true }
+    }
+
+    // For switches wired to ground, like the encoder's push switch
+    pub const fn active_low(button: P) -> Self {
+        Self { input: button, active_high: false }
     }
 
     #[inline]
     async fn wait_for_button_up(&mut self) -> &mut Self {
-        self.0.wait_for_low().await;
-        esp_println::println!("waited for low");
+        if self.active_high {
+            self.input.wait_for_low().await.unwrap();
+        } else {
+            self.input.wait_for_high().await.unwrap();
+        }
+        log::debug!("waited for release");
         self
     }
 
     #[inline]
     async fn wait_for_button_down(&mut self) -> &mut Self {
-        self.0.wait_for_high().await;
+        if self.active_high {
+            self.input.wait_for_high().await.unwrap();
+        } else {
+            self.input.wait_for_low().await.unwrap();
+        }
         self
     }
 
@@ -34,11 +68,11 @@ impl<'a> Button<'a> {
         let press_duration = 
             match select(self.wait_for_button_up(), Timer::after(LONG_PRESS)).await {
                 Either::First(_) => { 
-                    esp_println::println!("Short Press!");
+                    log::debug!("Short Press!");
                     PressDuration::Short
                 },
                 Either::Second(()) => { 
-                    esp_println::println!("Long Press!");
+                    log::debug!("Long Press!");
                     PressDuration::Long
                 }
             };
@@ -47,13 +81,87 @@ impl<'a> Button<'a> {
 
     #[inline]
     pub async fn wait_for_press(&mut self) -> &mut Self {
-        self.0.wait_for_rising_edge().await;
+        if self.active_high {
+            self.input.wait_for_rising_edge().await.unwrap();
+        } else {
+            self.input.wait_for_falling_edge().await.unwrap();
+        }
         self
     }
 }
 
+// Which physical button a press came from
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ButtonId {
+    Main,
+    EncoderSwitch
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum PressDuration {
     Short,
     Long
 }
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::select::{select, Either};
+    use embassy_time::{Duration, Timer};
+
+    use super::{Button, PressDuration};
+    use crate::testing::{run_timed, ScriptedPin};
+
+    // Contacts chatter for a few milliseconds on both the press and the release
+    const SHORT_THEN_LONG: [(u64, bool); 12] = [
+        (100, true), (102, false), (104, true), (107, false), (109, true),
+        (400, false), (401, true), (403, false),
+        (1000, true), (1003, false), (1005, true),
+        (2500, false)
+    ];
+
+    #[test]
+    fn bouncy_press_is_one_short_press() {
+        let ((first, second), took) = run_timed(async {
+            let mut button = Button::new(ScriptedPin::new(&SHORT_THEN_LONG));
+            (button.press_duration().await, button.press_duration().await)
+        });
+        assert_eq!(first, PressDuration::Short);
+        // The chatter at 102-109 and 401-403 didn't end the first press early or start another
+        assert_eq!(second, PressDuration::Long);
+        // The long press is reported while still held, a debounce delay plus LONG_PRESS after going down
+        assert_eq!(took, Duration::from_millis(2050));
+    }
+
+    #[test]
+    fn short_press_ends_on_release() {
+        let (press, took) = run_timed(async {
+            Button::new(ScriptedPin::new(&SHORT_THEN_LONG)).press_duration().await
+        });
+        assert_eq!(press, PressDuration::Short);
+        assert_eq!(took, Duration::from_millis(400));
+    }
+
+    #[test]
+    fn release_chatter_is_not_a_press() {
+        const SCRIPT: [(u64, bool); 6] = [(100, true), (300, false), (302, true), (304, false), (305, true), (306, false)];
+        let (presses, _) = run_timed(async {
+            let mut button = Button::new(ScriptedPin::new(&SCRIPT));
+            let first = button.press_duration().await;
+            let second = select(button.press_duration(), Timer::after(Duration::from_secs(5))).await;
+            (first, second)
+        });
+        assert_eq!(presses.0, PressDuration::Short);
+        assert!(matches!(presses.1, Either::Second(())));
+    }
+
+    #[test]
+    fn active_low_switch() {
+        // Idles high through the pull-up and is pressed to ground
+        const SCRIPT: [(u64, bool); 5] = [(0, true), (100, false), (103, true), (105, false), (1500, true)];
+        let (press, took) = run_timed(async {
+            Button::active_low(ScriptedPin::new(&SCRIPT)).press_duration().await
+        });
+        assert_eq!(press, PressDuration::Long);
+        assert_eq!(took, Duration::from_millis(1150));
+    }
+}
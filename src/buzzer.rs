@@ -0,0 +1,12 @@
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+
+use crate::melody::Tune;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alert {
+    pub tune: Tune,
+    // Percent, 0 plays nothing
+    pub volume: u8
+}
+
+pub type BuzzerNotifier = Signal<CriticalSectionRawMutex, Alert>;
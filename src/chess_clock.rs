@@ -0,0 +1,369 @@
+use core::fmt::Write;
+use embassy_time::{Duration, Instant};
+use heapless::String;
+
+use crate::{button::PressDuration, clock_util::SessionState, draw_panels::{Panel, PanelPosition, Payload}, time_util::{format_duration, rounded_up, DisplayPrecision}};
+
+const ZERO: Duration = Duration::from_ticks(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChessSide {
+    Top,
+    Bottom
+}
+
+impl ChessSide {
+    pub const fn other(self) -> Self {
+        match self {
+            Self::Top => Self::Bottom,
+            Self::Bottom => Self::Top
+        }
+    }
+
+    pub const fn position(self) -> PanelPosition {
+        match self {
+            Self::Top => PanelPosition::Top,
+            Self::Bottom => PanelPosition::Bottom
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    // No time is ever given back
+    SuddenDeath,
+    // Fixed increment added after every completed move
+    Fischer(Duration),
+    // Time used on the move is given back, up to the delay
+    Bronstein(Duration),
+    // The clock only starts counting down once the delay has passed
+    SimpleDelay(Duration)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChessConfig {
+    pub top_budget: Duration,
+    pub bottom_budget: Duration,
+    pub control: TimeControl
+}
+
+impl Default for ChessConfig {
+    // 5 minutes per side with a 3 second increment ("5|3 blitz")
+    fn default() -> Self {
+        Self {
+            top_budget: Duration::from_secs(5 * 60),
+            bottom_budget: Duration::from_secs(5 * 60),
+            control: TimeControl::Fischer(Duration::from_secs(3))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SideClock {
+    remaining: Duration,
+    moves: u16
+}
+
+/*
+ * Two countdown clocks where only the side to move is running.
+ * All methods take the current Instant so the clock can be driven without hardware.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct ChessClock {
+    control: TimeControl,
+    top: SideClock,
+    bottom: SideClock,
+    active: ChessSide,
+    // Time spent on the current move, excluding pauses
+    move_elapsed: Duration,
+    last_update: Instant,
+    running: bool,
+    flagged: Option<ChessSide>
+}
+
+impl ChessClock {
+    pub fn new(config: ChessConfig, now: Instant) -> Self {
+        Self {
+            control: config.control,
+            top: SideClock { remaining: config.top_budget, moves: 0 },
+            bottom: SideClock { remaining: config.bottom_budget, moves: 0 },
+            active: ChessSide::Top,
+            move_elapsed: ZERO,
+            last_update: now,
+            running: false,
+            flagged: None
+        }
+    }
+
+    fn side(&self, side: ChessSide) -> &SideClock {
+        match side {
+            ChessSide::Top => &self.top,
+            ChessSide::Bottom => &self.bottom
+        }
+    }
+
+    fn side_mut(&mut self, side: ChessSide) -> &mut SideClock {
+        match side {
+            ChessSide::Top => &mut self.top,
+            ChessSide::Bottom => &mut self.bottom
+        }
+    }
+
+    // Portion of the current move that is taken off the active clock
+    fn charged(&self) -> Duration {
+        match self.control {
+            TimeControl::SimpleDelay(delay) => self.move_elapsed.checked_sub(delay).unwrap_or(ZERO),
+            _ => self.move_elapsed
+        }
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        if self.running && self.flagged.is_none() {
+            self.move_elapsed += now.saturating_duration_since(self.last_update);
+            if self.charged() >= self.side(self.active).remaining {
+                self.flagged = Some(self.active);
+                self.running = false;
+            }
+        }
+        self.last_update = now;
+    }
+
+    pub fn remaining(&self, side: ChessSide) -> Duration {
+        let remaining = self.side(side).remaining;
+        if side == self.active {
+            remaining.checked_sub(self.charged()).unwrap_or(ZERO)
+        } else {
+            remaining
+        }
+    }
+
+    pub const fn moves(&self, side: ChessSide) -> u16 {
+        match side {
+            ChessSide::Top => self.top.moves,
+            ChessSide::Bottom => self.bottom.moves
+        }
+    }
+
+    pub const fn active(&self) -> ChessSide {
+        self.active
+    }
+
+    pub const fn flagged(&self) -> Option<ChessSide> {
+        self.flagged
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Ends the active side's move and starts the opponent's clock
+    pub fn hand_over(&mut self, now: Instant) {
+        self.update(now);
+        if self.flagged.is_some() {
+            return
+        }
+
+        let mover = self.active;
+        let refund = match self.control {
+            TimeControl::SuddenDeath | TimeControl::SimpleDelay(_) => ZERO,
+            TimeControl::Fischer(increment) => increment,
+            TimeControl::Bronstein(delay) => self.move_elapsed.min(delay)
+        };
+        let remaining = self.remaining(mover) + refund;
+
+        let clock = self.side_mut(mover);
+        clock.remaining = remaining;
+        clock.moves = clock.moves.saturating_add(1);
+
+        self.active = mover.other();
+        self.move_elapsed = ZERO;
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        self.update(now);
+        self.running = false;
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        self.update(now);
+        self.running = self.flagged.is_none();
+    }
+
+    // Short press ends the move or resumes, long press pauses or resumes
+    pub fn press(&mut self, press: PressDuration, now: Instant) {
+        match (press, self.running) {
+            (PressDuration::Short, true) => self.hand_over(now),
+            (PressDuration::Long, true) => self.pause(now),
+            (_, false) => self.resume(now)
+        }
+    }
+
+    pub fn render(&mut self, now: Instant) -> (Panel, Duration) {
+        self.update(now);
+        let frame = ChessFrame {
+            top: format_duration(rounded_up(self.remaining(ChessSide::Top), DisplayPrecision::Seconds)),
+            bottom: format_duration(rounded_up(self.remaining(ChessSide::Bottom), DisplayPrecision::Seconds)),
+            top_moves: self.top.moves,
+            bottom_moves: self.bottom.moves,
+            active: self.active,
+            running: self.running,
+            flagged: self.flagged
+        };
+
+        // Wake up exactly when the displayed second of the running clock changes
+        let sleep_dur = if self.running {
+            let tick = Duration::from_secs(1).as_ticks();
+            let until_change = self.remaining(self.active).as_ticks() % tick;
+            Duration::from_ticks(if until_change == 0 { tick } else { until_change })
+        } else {
+            Duration::from_secs(1)
+        };
+
+        (Panel(PanelPosition::FullScreen, Payload::Chess(frame)), sleep_dur)
+    }
+}
+
+// Everything the display needs to draw both clocks in a single payload
+#[derive(Debug, Clone, Copy)]
+pub struct ChessFrame {
+    pub top: [u8; 20],
+    pub bottom: [u8; 20],
+    pub top_moves: u16,
+    pub bottom_moves: u16,
+    pub active: ChessSide,
+    pub running: bool,
+    pub flagged: Option<ChessSide>
+}
+
+impl ChessFrame {
+    pub fn label(&self) -> String<16> {
+        let mut label = String::new();
+        if self.flagged.is_some() {
+            let _ = label.push_str("flag!");
+        } else {
+            let _ = write!(label, "{} - {}", self.top_moves, self.bottom_moves);
+        }
+        label
+    }
+
+    pub const fn divider_state(&self) -> SessionState {
+        match (self.running, self.active) {
+            (false, _) if self.flagged.is_none() => SessionState::Paused,
+            (_, ChessSide::Top) => SessionState::Working,
+            (_, ChessSide::Bottom) => SessionState::Break
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn started(control: TimeControl, budget: Duration) -> ChessClock {
+        let mut clock = ChessClock::new(ChessConfig { top_budget: budget, bottom_budget: budget, control }, Instant::from_secs(0));
+        clock.resume(Instant::from_secs(0));
+        clock
+    }
+
+    // Each side thinks for the given seconds in turn, top first
+    fn play(clock: &mut ChessClock, moves: &[u64]) -> Instant {
+        let mut now = Instant::from_secs(0);
+        for seconds in moves {
+            now += Duration::from_secs(*seconds);
+            clock.hand_over(now);
+        }
+        now
+    }
+
+    #[test]
+    fn fischer_adds_the_increment_after_every_move() {
+        let mut clock = started(TimeControl::Fischer(Duration::from_secs(2)), MINUTE);
+        play(&mut clock, &[5, 3, 10, 1]);
+        assert_eq!(clock.remaining(ChessSide::Top), Duration::from_secs(60 - 5 + 2 - 10 + 2));
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 3 + 2 - 1 + 2));
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_time_used_up_to_the_delay() {
+        let mut clock = started(TimeControl::Bronstein(Duration::from_secs(3)), MINUTE);
+        play(&mut clock, &[2, 5, 3, 1]);
+        // Quick moves cost nothing, slower ones only what's past the delay
+        assert_eq!(clock.remaining(ChessSide::Top), MINUTE);
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 5 + 3));
+    }
+
+    #[test]
+    fn simple_delay_only_counts_down_after_the_delay() {
+        let mut clock = started(TimeControl::SimpleDelay(Duration::from_secs(3)), MINUTE);
+        let now = play(&mut clock, &[2, 5, 4]);
+        assert_eq!(clock.remaining(ChessSide::Top), Duration::from_secs(60 - 1));
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 2));
+
+        // Bottom is inside the delay, so its clock shows the full time
+        clock.update(now + Duration::from_secs(3));
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 2));
+        clock.update(now + Duration::from_secs(4));
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 3));
+    }
+
+    #[test]
+    fn the_flag_falls_exactly_at_zero() {
+        let mut clock = started(TimeControl::SuddenDeath, Duration::from_secs(10));
+        clock.update(Instant::from_millis(9_999));
+        assert_eq!(clock.flagged(), None);
+        assert_eq!(clock.remaining(ChessSide::Top), Duration::from_millis(1));
+
+        clock.update(Instant::from_secs(10));
+        assert_eq!(clock.flagged(), Some(ChessSide::Top));
+        assert_eq!(clock.remaining(ChessSide::Top), Duration::from_ticks(0));
+        assert!(!clock.is_running());
+    }
+
+    #[test]
+    fn the_delay_holds_the_flag_off() {
+        let mut clock = started(TimeControl::SimpleDelay(Duration::from_secs(5)), Duration::from_secs(10));
+        clock.update(Instant::from_millis(14_999));
+        assert_eq!(clock.flagged(), None);
+        clock.update(Instant::from_secs(15));
+        assert_eq!(clock.flagged(), Some(ChessSide::Top));
+    }
+
+    #[test]
+    fn no_increment_after_the_flag() {
+        let mut clock = started(TimeControl::Fischer(Duration::from_secs(5)), Duration::from_secs(10));
+        clock.hand_over(Instant::from_secs(12));
+        assert_eq!(clock.flagged(), Some(ChessSide::Top));
+        assert_eq!(clock.remaining(ChessSide::Top), Duration::from_ticks(0));
+        assert_eq!(clock.moves(ChessSide::Top), 0);
+        assert_eq!(clock.active(), ChessSide::Top);
+
+        // A flagged game stays stopped
+        clock.press(PressDuration::Short, Instant::from_secs(13));
+        assert!(!clock.is_running());
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn each_side_counts_its_own_moves() {
+        let mut clock = started(TimeControl::SuddenDeath, MINUTE);
+        play(&mut clock, &[1, 1, 1]);
+        assert_eq!((clock.moves(ChessSide::Top), clock.moves(ChessSide::Bottom)), (2, 1));
+        assert_eq!(clock.active(), ChessSide::Bottom);
+
+        // Pausing and resuming isn't a move, and the pause isn't charged
+        clock.press(PressDuration::Long, Instant::from_secs(4));
+        clock.press(PressDuration::Short, Instant::from_secs(30));
+        assert_eq!((clock.moves(ChessSide::Top), clock.moves(ChessSide::Bottom)), (2, 1));
+        clock.press(PressDuration::Short, Instant::from_secs(31));
+        assert_eq!((clock.moves(ChessSide::Top), clock.moves(ChessSide::Bottom)), (2, 2));
+        assert_eq!(clock.remaining(ChessSide::Bottom), Duration::from_secs(60 - 1 - 2));
+        let Panel(_, Payload::Chess(frame)) = clock.render(Instant::from_secs(31)).0 else {
+            panic!("not the chess clock")
+        };
+        assert_eq!(frame.label().as_str(), "2 - 2");
+    }
+}
@@ -9,6 +9,12 @@ pub struct ClickableElement {
     prev_element: u8
 }
 
+impl ClickableElement {
+    pub const fn new(position: Rectangle, prev_element: u8, next_element: u8) -> Self {
+        Self { position, value: 0, next_element, prev_element }
+    }
+}
+
 impl UINode for ClickableElement {
     fn get_position(&self) -> &Rectangle {
         &self.position
@@ -0,0 +1,311 @@
+use core::fmt::Write;
+use heapless::String;
+
+use embassy_time::Duration;
+use embedded_graphics::{prelude::{Point, Size}, primitives::Rectangle};
+
+use crate::{clickable::ClickableElement, constants::{BRIGHTNESS_STEP, MIN_BRIGHTNESS, VOLUME_STEP}, draw_panels::PanelPosition, scenes::{self, UIType}, settings::Settings, time_util::{format_duration_with, DisplayPrecision, SECONDS_PER_DAY}};
+
+// Where the colon of "HH:MM" starts on the upper panel, taps left of it set the hours
+const COLON_X: i32 = 110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockField {
+    Hours,
+    Minutes,
+    // Backlight level, shown live while it's being picked
+    Brightness,
+    // Buzzer loudness, kept while muted
+    Volume,
+    Mute,
+    // Fractional digits of the timers
+    Precision
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetterResult {
+    Editing,
+    Done,
+    Cancelled
+}
+
+/*
+ * Settings scene for the time of day, the backlight and the buzzer.
+ * The encoder changes the selected field, a short press moves on to minutes, then brightness, volume, mute,
+ * the timer precision and then saves, a long press leaves without changing anything.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSetter {
+    hours: u8,
+    minutes: u8,
+    brightness: u8,
+    volume: u8,
+    muted: bool,
+    precision: DisplayPrecision,
+    field: ClockField
+}
+
+impl ClockSetter {
+    pub const fn new(local_seconds: u64, settings: &Settings, precision: DisplayPrecision) -> Self {
+        let of_day = local_seconds % SECONDS_PER_DAY;
+        Self {
+            hours: (of_day / 3600) as u8,
+            minutes: (of_day % 3600 / 60) as u8,
+            brightness: settings.brightness,
+            volume: settings.volume,
+            muted: settings.muted,
+            precision,
+            field: ClockField::Hours
+        }
+    }
+
+    pub const fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub const fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub const fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub const fn precision(&self) -> DisplayPrecision {
+        self.precision
+    }
+
+    pub const fn field(&self) -> ClockField {
+        self.field
+    }
+
+    // Moves the cursor straight to a field, like a tap on it
+    pub fn focus(&mut self, field: ClockField) {
+        self.field = field;
+    }
+
+    // Fields wrap around instead of stopping at their limits
+    pub fn scroll_by(&mut self, steps: i32) {
+        match self.field {
+            ClockField::Hours => self.hours = (self.hours as i32 + steps).rem_euclid(24) as u8,
+            ClockField::Minutes => self.minutes = (self.minutes as i32 + steps).rem_euclid(60) as u8,
+            // Brightness stops at its ends, wrapping from full to dark would be a surprise
+            ClockField::Brightness => {
+                let brightness = self.brightness as i32 + steps * BRIGHTNESS_STEP as i32;
+                self.brightness = brightness.clamp(MIN_BRIGHTNESS as i32, 100) as u8;
+            }
+            // Silence is the mute's job, so the volume stops at one step
+            ClockField::Volume => {
+                let volume = self.volume as i32 + steps * VOLUME_STEP as i32;
+                self.volume = volume.clamp(VOLUME_STEP as i32, 100) as u8;
+            }
+            // Every detent flips it
+            ClockField::Mute => self.muted ^= steps % 2 != 0,
+            ClockField::Precision => {
+                let all = DisplayPrecision::ALL;
+                let index = all.iter().position(|precision| *precision == self.precision).unwrap_or(0);
+                self.precision = all[(index as i32 + steps).rem_euclid(all.len() as i32) as usize];
+            }
+        }
+    }
+
+    pub fn short_press(&mut self) -> SetterResult {
+        match self.field {
+            ClockField::Hours => {
+                self.field = ClockField::Minutes;
+                SetterResult::Editing
+            }
+            ClockField::Minutes => {
+                self.field = ClockField::Brightness;
+                SetterResult::Editing
+            }
+            ClockField::Brightness => {
+                self.field = ClockField::Volume;
+                SetterResult::Editing
+            }
+            ClockField::Volume => {
+                self.field = ClockField::Mute;
+                SetterResult::Editing
+            }
+            ClockField::Mute => {
+                self.field = ClockField::Precision;
+                SetterResult::Editing
+            }
+            ClockField::Precision => SetterResult::Done
+        }
+    }
+
+    pub const fn long_press(&self) -> SetterResult {
+        SetterResult::Cancelled
+    }
+
+    // Same day as `local_seconds`, at the chosen time with the seconds zeroed
+    pub const fn apply_to(&self, local_seconds: u64) -> u64 {
+        let midnight = local_seconds - local_seconds % SECONDS_PER_DAY;
+        midnight + self.hours as u64 * 3600 + self.minutes as u64 * 60
+    }
+
+    pub fn frame(&self) -> ClockFrame {
+        let mut time = [b' '; 20];
+        let mut text: String<20> = String::new();
+        let _ = write!(text, "{:02}:{:02}", self.hours, self.minutes);
+        time[..text.len()].copy_from_slice(text.as_bytes());
+
+        let number = match self.field {
+            // A zero timer in the picked precision shows what the digits will look like
+            ClockField::Precision => return ClockFrame {
+                time,
+                value: format_duration_with(Duration::from_ticks(0), self.precision),
+                field: self.field,
+                muted: self.muted
+            },
+            ClockField::Volume => self.volume,
+            // What the buzzer will actually play at
+            ClockField::Mute if self.muted => 0,
+            ClockField::Mute => self.volume,
+            ClockField::Hours | ClockField::Minutes | ClockField::Brightness => self.brightness
+        };
+        let mut value = [b' '; 20];
+        text.clear();
+        let _ = write!(text, "{}", number);
+        value[..text.len()].copy_from_slice(text.as_bytes());
+        ClockFrame { time, value, field: self.field, muted: self.muted }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClockFrame {
+    pub time: [u8; 20],
+    // Brightness or volume in percent or a sample of the precision, drawn on the lower panel
+    pub value: [u8; 20],
+    pub field: ClockField,
+    pub muted: bool
+}
+
+impl ClockFrame {
+    pub const fn label(&self) -> &'static str {
+        match self.field {
+            ClockField::Hours => "set hours",
+            ClockField::Minutes => "set minutes",
+            ClockField::Brightness => "brightness",
+            ClockField::Volume => "volume",
+            ClockField::Mute if self.muted => "muted",
+            ClockField::Mute => "sound on",
+            ClockField::Precision => "precision"
+        }
+    }
+
+    // Hours left of the colon and minutes right of it on the upper panel, then the lower panel
+    pub fn elements(&self) -> [UIType; 3] {
+        let top = PanelPosition::Top.touch_area();
+        let hours = Rectangle::new(top.top_left, Size::new((COLON_X - top.top_left.x) as u32, top.size.height));
+        let minutes = Rectangle::new(Point::new(COLON_X, top.top_left.y), Size::new(top.size.width - hours.size.width, top.size.height));
+        [
+            UIType::Clickable(ClickableElement::new(hours, 2, 1)),
+            UIType::Clickable(ClickableElement::new(minutes, 0, 2)),
+            UIType::Clickable(ClickableElement::new(PanelPosition::Bottom.touch_area(), 1, 0))
+        ]
+    }
+
+    // The lower panel shows the brightness while the time is being set, then whichever field has the cursor
+    pub fn field_at(&self, point: Point) -> Option<ClockField> {
+        scenes::element_at(&self.elements(), point).map(|index| match (index, self.field) {
+            (0, _) => ClockField::Hours,
+            (1, _) => ClockField::Minutes,
+            (_, ClockField::Hours | ClockField::Minutes) => ClockField::Brightness,
+            (_, field) => field
+        })
+    }
+}
+
+// Hours and minutes shown in the divider
+pub const fn time_of_day(local_seconds: u64) -> (u8, u8) {
+    let of_day = local_seconds % SECONDS_PER_DAY;
+    ((of_day / 3600) as u8, (of_day % 3600 / 60) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{prelude::Point, primitives::Rectangle};
+
+    use super::{ClockField, ClockFrame, ClockSetter, SetterResult};
+    use crate::{settings::Settings, time_util::DisplayPrecision};
+
+    fn settings() -> Settings {
+        Settings { brightness: 80, volume: 60, ..Settings::default() }
+    }
+
+    #[test]
+    fn precision_is_the_last_field() {
+        let mut setter = ClockSetter::new(0, &settings(), DisplayPrecision::Seconds);
+        for field in [ClockField::Minutes, ClockField::Brightness, ClockField::Volume, ClockField::Mute, ClockField::Precision] {
+            assert_eq!(setter.short_press(), SetterResult::Editing);
+            assert_eq!(setter.field(), field);
+        }
+
+        setter.scroll_by(1);
+        assert_eq!(setter.precision(), DisplayPrecision::Tenths);
+        assert_eq!(&setter.frame().value[..10], b"00:00:00.0");
+        setter.scroll_by(2);
+        assert_eq!(setter.precision(), DisplayPrecision::Seconds);
+        setter.scroll_by(-1);
+        assert_eq!(setter.precision(), DisplayPrecision::Hundredths);
+        assert_eq!(setter.brightness(), 80);
+        assert_eq!(setter.short_press(), SetterResult::Done);
+    }
+
+    #[test]
+    fn volume_and_mute_are_set_separately() {
+        let mut setter = ClockSetter::new(0, &settings(), DisplayPrecision::Seconds);
+        setter.focus(ClockField::Volume);
+        setter.scroll_by(3);
+        assert_eq!(setter.volume(), 90);
+        assert_eq!(&setter.frame().value[..2], b"90");
+        assert_eq!(setter.frame().label(), "volume");
+        setter.scroll_by(5);
+        assert_eq!(setter.volume(), 100);
+        // Turning it down stops short of silent, muting is what silences it
+        setter.scroll_by(-20);
+        assert_eq!(setter.volume(), 10);
+
+        setter.focus(ClockField::Mute);
+        assert_eq!(setter.frame().label(), "sound on");
+        setter.scroll_by(1);
+        assert!(setter.muted());
+        assert_eq!(setter.frame().label(), "muted");
+        assert_eq!(&setter.frame().value[..2], b"0 ");
+        setter.scroll_by(2);
+        assert!(setter.muted());
+        setter.scroll_by(-1);
+        assert!(!setter.muted());
+        // The volume is kept through muting
+        assert_eq!(setter.volume(), 10);
+    }
+
+    fn positions(frame: &ClockFrame) -> [Rectangle; 3] {
+        frame.elements().map(|element| *element.node().unwrap().get_position())
+    }
+
+    #[test]
+    fn taps_find_the_field_under_them() {
+        let mut setter = ClockSetter::new(0, &settings(), DisplayPrecision::Seconds);
+        let frame = setter.frame();
+        let [hours, minutes, lower] = positions(&frame);
+        // The colon splits the upper panel, right up to the last pixel either side
+        assert_eq!(frame.field_at(hours.center()), Some(ClockField::Hours));
+        assert_eq!(frame.field_at(hours.bottom_right().unwrap()), Some(ClockField::Hours));
+        assert_eq!(frame.field_at(minutes.top_left), Some(ClockField::Minutes));
+        assert_eq!(frame.field_at(minutes.bottom_right().unwrap()), Some(ClockField::Minutes));
+        assert_eq!(frame.field_at(lower.center()), Some(ClockField::Brightness));
+        // The divider between the panels isn't a field
+        let divider = Point::new(160, 120);
+        assert!(positions(&frame).iter().all(|position| !position.contains(divider)));
+        assert_eq!(frame.field_at(divider), None);
+
+        setter.focus(ClockField::Mute);
+        assert_eq!(setter.frame().field_at(lower.center()), Some(ClockField::Mute));
+        setter.focus(ClockField::Precision);
+        assert_eq!(setter.frame().field_at(lower.center()), Some(ClockField::Precision));
+        assert_eq!(setter.frame().label(), "precision");
+    }
+}
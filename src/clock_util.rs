@@ -1,8 +1,8 @@
-use embassy_executor::{SpawnError, Spawner};
-use embassy_futures::select::{select, Either};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
-use embassy_time::{ Duration, Ticker, Timer };
-use crate::{button::{self, Button, PressDuration}, draw_panels::{Panel, PanelPosition}, render_display::{TFTNotifier, TFTRender}, tft::TFT, time_util::Time};
+use embassy_time::{ Duration, Instant, Ticker };
+use embedded_graphics::prelude::Point;
+use heapless::Vec;
+use crate::{backlight::{BacklightLevel, Dimmer}, buzzer::Alert, battery::BatteryStatus, button::{ButtonId, PressDuration}, chess_clock::{ChessClock, ChessConfig}, clock_setter::{self, ClockField, ClockSetter, SetterResult}, draw_panels::{Panel, PanelPosition, Payload, PresetsFrame, TimersFrame}, flowtime::{BreakCredit, FlowRatio}, goal::GoalProgress, interval::{IntervalProgram, IntervalTimer, SegmentKind}, melody::Tune, power::{PowerControl, PowerManager}, constants::{DOUBLE_PRESS_WINDOW, MAX_PRESETS, OVERTIME_REMINDER, TIME_SAVE_INTERVAL}, history::{DayTotals, History, HistoryEntry, HistoryKind}, idle::{IdleStatus, IdleWatch}, input_log::{InputLog, InputRecord, RecordedInput}, presets::{PickerResult, Preset, PresetPicker, PresetStorage, PresetStore, PRESET_NAME_LEN}, scenes::Scene, settings::Settings, state_machine::{ButtonMap, SessionAction, SessionEvent, StateMachine, Step}, stopwatch::Stopwatch, time_util::{DisplayPrecision, Time}, timer_edit::{TimerEdit, EDIT_TIMEOUT}, touch::{SwipeDirection, TouchGesture}, wall_clock::{self, WallClock}};
 
 /*
  * Represents a single Ticker that increments 'run_duration' every tenth of a second
@@ -27,7 +27,7 @@ impl SingleClock {
     }
 }
 
-#[derive(Debug, PartialEq, Default, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
 pub enum SessionState {
     #[default]
     Working,
@@ -36,152 +36,1224 @@ pub enum SessionState {
 }
 
 impl SessionState {
-    pub async fn execute(
-        self, 
-        session: &mut DoubleTimerSession<'_>, 
-        button: &mut Button<'_>) -> Self 
-    {
+    pub(crate) fn render(self, time: &mut Time, now: Instant) -> (Panel, Duration) {
         match self {
-            SessionState::Working => self.execute_working(session, button).await,
-            SessionState::Break => self.execute_break(session, button).await,
-            SessionState::Paused => self.execute_paused(session, button).await,
+            Self::Working => Self::render_working(time, now),
+            Self::Break => Self::render_break(time, now),
+            Self::Paused => Self::render_paused(time, now)
         }
     }
 
-    async fn execute_working(self, session: &mut DoubleTimerSession<'_>, button: &mut Button<'_>) -> Self {
-        session.set_state(self).await;
-        match button.press_duration().await {
-            PressDuration::Short => {
-                esp_println::println!("working -> break (short)");
-                Self::Break
-            }
-            PressDuration::Long => {
-                esp_println::println!("working -> paused (long)");
-                Self::Paused
+    fn render_working(time: &mut Time, now: Instant) -> (Panel, Duration) {
+        let (frame, sleep_dur) = time.sleep_for_work(now);
+        (Panel::from_timers(frame), sleep_dur)
+    }
+
+    fn render_break(time: &mut Time, now: Instant) -> (Panel, Duration) {
+        let (frame, sleep_dur) = time.sleep_for_break(now);
+        (Panel::from_timers(frame), sleep_dur)
+    }
+
+    fn render_paused(time: &mut Time, now: Instant) -> (Panel, Duration) {
+        let (frame, sleep_dur) = time.sleep_for_pause(now);
+        (Panel::from_timers(frame), sleep_dur)
+    }
+
+}
+
+pub type SessionOuterNotifier = Channel<CriticalSectionRawMutex, SessionNotice, 4>;
+
+// What the two timer panels are used for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionMode {
+    #[default]
+    DoubleTimer,
+    ChessClock(ChessConfig),
+    Stopwatch,
+    // Work earns break credit that the lower panel counts down
+    Flowtime(FlowRatio),
+    Interval(IntervalProgram)
+}
+
+impl From<Scene> for SessionMode {
+    fn from(scene: Scene) -> Self {
+        match scene {
+            Scene::ConfigTaro | Scene::ConfigTaroPlus => Self::DoubleTimer,
+            Scene::ConfigCountingUp => Self::Stopwatch,
+            Scene::ConfigInterval => Self::Interval(IntervalProgram::default())
+        }
+    }
+}
+
+// Runtime state of the selected SessionMode.
+// Only a single instance lives in device_loop, so the lap buffer stays inline.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum ActiveMode {
+    DoubleTimer,
+    ChessClock(ChessClock),
+    Stopwatch(Stopwatch),
+    Flowtime(FlowRatio),
+    Interval(IntervalTimer)
+}
+
+impl ActiveMode {
+    fn new(mode: SessionMode, now: Instant) -> Self {
+        match mode {
+            SessionMode::DoubleTimer => Self::DoubleTimer,
+            SessionMode::ChessClock(config) => Self::ChessClock(ChessClock::new(config, now)),
+            SessionMode::Stopwatch => Self::Stopwatch(Stopwatch::new(now)),
+            SessionMode::Flowtime(ratio) => Self::Flowtime(ratio),
+            SessionMode::Interval(program) => Self::Interval(IntervalTimer::new(program, now))
+        }
+    }
+
+    // Modes that run on the work and break totals and the session state machine
+    const fn uses_timers(&self) -> bool {
+        matches!(self, Self::DoubleTimer | Self::Flowtime(_))
+    }
+
+    fn render(&mut self, state: SessionState, time: &mut Time, now: Instant) -> (Panel, Duration) {
+        match self {
+            Self::DoubleTimer => state.render(time, now),
+            Self::ChessClock(clock) => clock.render(now),
+            Self::Stopwatch(stopwatch) => stopwatch.render(now),
+            Self::Interval(interval) => interval.render(now),
+            Self::Flowtime(ratio) => {
+                let (mut panel, sleep_dur) = state.render(time, now);
+                if let Payload::Timers(frame) = &mut panel.1 {
+                    let credit = ratio.credit(time.work_elapsed(), time.break_elapsed());
+                    frame.break_time = credit.format(time.precision());
+                }
+                (panel, sleep_dur)
             }
         }
     }
 
-    async fn execute_break(self, session: &mut DoubleTimerSession<'_>, button: &mut Button<'_>) -> Self {
-        session.set_state(self).await;
-        match button.press_duration().await {
-            PressDuration::Short => {
-                esp_println::println!("break -> working (short)");
-                Self::Working
+    // Returns true when the mode consumed the event itself
+    fn handle_event(&mut self, event: SessionEvent, now: Instant) -> bool {
+        let press = match event {
+            SessionEvent::ShortPress => PressDuration::Short,
+            SessionEvent::LongPress => PressDuration::Long,
+            _ => return false
+        };
+
+        match self {
+            Self::DoubleTimer | Self::Flowtime(_) => return false,
+            Self::ChessClock(clock) => clock.press(press, now),
+            Self::Stopwatch(stopwatch) => stopwatch.press(press, now),
+            Self::Interval(interval) => interval.press(press, now)
+        }
+        true
+    }
+
+    fn scroll(&mut self, steps: i32) {
+        match self {
+            Self::Stopwatch(stopwatch) => stopwatch.scroll_by(steps),
+            Self::Interval(interval) => interval.scroll_by(steps),
+            _ => ()
+        }
+    }
+
+    // Segment changes in interval training play the tune for the kind of segment that ended
+    fn take_alert(&mut self) -> Option<Tune> {
+        let Self::Interval(interval) = self else {
+            return None
+        };
+        Some(match interval.take_alert()? {
+            SegmentKind::Work => Tune::WorkDone,
+            SegmentKind::Rest => Tune::BreakDone
+        })
+    }
+}
+
+pub enum SessionNotice {
+    Event(SessionEvent),
+    Press(ButtonId, PressDuration),
+    SetMode(SessionMode),
+    AdjustTimer(SessionState, i32),
+    SetPrecision(DisplayPrecision),
+    SetSettings(Settings),
+    SetWallClock(u64),
+    OpenClockSettings,
+    OpenPresetPicker,
+    DumpInputs,
+    ExportHistory,
+    Scroll(i32),
+    Touch(TouchGesture),
+    Battery(BatteryStatus),
+    Woke(Duration)
+}
+
+impl SessionNotice {
+    // Everything but the two dumps goes into the input log before it's applied
+    #[cfg(target_os = "none")]
+    pub(crate) fn apply(self, device: &mut DeviceState, now: Instant) -> Outcome {
+        let input = match self {
+            Self::DumpInputs => {
+                device.inputs.dump();
+                return Outcome::Handled
             }
-            PressDuration::Long => {
-                esp_println::println!("working -> paused (long)");
-                Self::Paused
+            Self::ExportHistory => {
+                device.history.export(device.today());
+                return Outcome::Handled
             }
+            Self::Event(event) => RecordedInput::Event(event),
+            Self::Press(button, press) => RecordedInput::Press(button, press),
+            Self::SetMode(mode) => RecordedInput::SetMode(mode),
+            Self::AdjustTimer(timer, minutes) => RecordedInput::AdjustTimer(timer, minutes),
+            Self::SetPrecision(precision) => RecordedInput::SetPrecision(precision),
+            Self::SetSettings(settings) => RecordedInput::SetSettings(settings),
+            Self::SetWallClock(utc_seconds) => RecordedInput::SetWallClock(utc_seconds),
+            Self::OpenClockSettings => RecordedInput::OpenClockSettings,
+            Self::OpenPresetPicker => RecordedInput::OpenPresetPicker,
+            Self::Scroll(steps) => RecordedInput::Scroll(steps),
+            Self::Touch(gesture) => RecordedInput::Touch(gesture),
+            Self::Battery(status) => RecordedInput::Battery(status),
+            Self::Woke(missed) => RecordedInput::Woke(missed)
+        };
+        device.input(input, now)
+    }
+}
+
+// What an input did to the session, returned so device_loop can log it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    // Nothing for the state machine, or a menu, editor or mode took the input
+    Handled,
+    Stepped(Step),
+    // The event has no transition from the current state
+    Rejected(SessionEvent, SessionState),
+    Undone { from: SessionState, to: SessionState },
+    NothingToUndo
+}
+
+// What swiping switches between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    Timers,
+    Stats
+}
+
+// Where device_loop's updates go: the board's display, backlight and buzzer, or a recording in a replay
+pub(crate) trait DeviceOutputs: PowerControl {
+    fn render(&mut self, panel: Panel);
+    fn backlight(&mut self, level: BacklightLevel);
+    fn alert(&mut self, alert: Alert);
+}
+
+// Everything device_loop owns
+pub(crate) struct DeviceState {
+    time: Time,
+    machine: StateMachine,
+    mode: ActiveMode,
+    history: History,
+    settings: Settings,
+    // When the last short press changed state, for double press detection
+    last_short_press: Option<Instant>,
+    buttons: ButtonMap,
+    inputs: InputLog,
+    editing: Option<TimerEdit>,
+    idle: IdleWatch,
+    wall_clock: WallClock,
+    // Day the current totals belong to
+    day: u64,
+    setting_clock: Option<ClockSetter>,
+    screen: Screen,
+    presets: Vec<Preset, MAX_PRESETS>,
+    preset_store: PresetStore<PresetStorage>,
+    current_preset: usize,
+    picker: Option<PresetPicker>,
+    // Latest reading from battery_loop, None on boards without a cell
+    battery: Option<BatteryStatus>,
+    power: PowerManager,
+    dimmer: Dimmer,
+    // Tune waiting for device_loop to hand it to the buzzer
+    alert: Option<Tune>,
+    // Whether the daily goal was reached at the last render
+    goal_reached: bool,
+    // Overtime reminders already played for the current Flowtime break
+    overtime_reminders: u64,
+    // When the wall clock was last written to the preset store
+    time_saved: Instant
+}
+
+impl DeviceState {
+    pub(crate) fn new(mut preset_store: PresetStore<PresetStorage>, now: Instant) -> Self {
+        let (presets, current_preset) = preset_store.load();
+        let settings = Settings::default();
+        let wall_clock = WallClock::from_saved(preset_store.saved_time(), now);
+        let day = wall_clock::day_number(
+            wall_clock.local_seconds(now, settings.utc_offset_minutes),
+            settings.rollover_hour);
+
+        Self {
+            time: Time::new(now),
+            machine: StateMachine::default(),
+            mode: ActiveMode::DoubleTimer,
+            history: History::new(),
+            settings,
+            last_short_press: None,
+            buttons: ButtonMap::default(),
+            inputs: InputLog::new(),
+            editing: None,
+            idle: IdleWatch::new(now),
+            wall_clock,
+            day,
+            setting_clock: None,
+            screen: Screen::Timers,
+            presets,
+            preset_store,
+            current_preset,
+            picker: None,
+            battery: None,
+            power: PowerManager::new(now),
+            dimmer: Dimmer::new(now),
+            alert: None,
+            goal_reached: false,
+            overtime_reminders: 0,
+            time_saved: now
         }
     }
 
-    async fn execute_paused(self, session: &mut DoubleTimerSession<'_>, button: &mut Button<'_>) -> Self {
-        session.set_state(self).await;
-        match button.press_duration().await {
-            PressDuration::Short => {
-                esp_println::println!("pause -> working (short)");
-                Self::Working
+    // Starts with the last used preset while the picker offers the others
+    pub(crate) fn boot(&mut self, now: Instant) {
+        self.apply_preset(self.current_preset, now);
+        self.open_preset_picker(now);
+    }
+
+    fn open_clock_settings(&mut self, now: Instant) {
+        self.setting_clock = Some(ClockSetter::new(self.local_seconds(now), &self.settings, self.time.precision()));
+    }
+
+    fn open_preset_picker(&mut self, now: Instant) {
+        self.picker = Some(PresetPicker::new(self.current_preset, self.presets.len(), now));
+    }
+
+    fn apply_preset(&mut self, index: usize, now: Instant) {
+        let Some(preset) = self.presets.get(index).cloned() else {
+            return
+        };
+        log::info!("preset {}", preset.name.as_str());
+        self.mode = ActiveMode::new(preset.mode, now);
+        self.time.set_precision(preset.precision);
+        self.set_settings(preset.settings, now);
+
+        if index != self.current_preset {
+            self.current_preset = index;
+            if self.preset_store.set_last_used(index).is_err() {
+                log::warn!("couldn't save the last used preset");
             }
-            PressDuration::Long => {
-                esp_println::println!("pause -> break (long)");
-                Self::Break
+        }
+    }
+
+    fn finish_picking(&mut self, result: PickerResult, now: Instant) {
+        if let PickerResult::Picked(index) = result {
+            self.picker = None;
+            self.apply_preset(index, now);
+        }
+    }
+
+    fn presets_frame(&self, selected: usize) -> PresetsFrame {
+        let mut names = [[0; PRESET_NAME_LEN]; MAX_PRESETS];
+        for (name, preset) in names.iter_mut().zip(&self.presets) {
+            name[..preset.name.len()].copy_from_slice(preset.name.as_bytes());
+        }
+        PresetsFrame { names, count: self.presets.len(), selected }
+    }
+
+    // A new rollover hour or UTC offset moves the day boundary, it doesn't end the day
+    fn set_settings(&mut self, settings: Settings, now: Instant) {
+        self.settings = settings;
+        self.day = wall_clock::day_number(self.local_seconds(now), settings.rollover_hour);
+    }
+
+    // Setting the clock doesn't end the day either, even when it crosses the rollover hour
+    fn set_wall_clock(&mut self, utc_seconds: u64, now: Instant) {
+        self.wall_clock.set(utc_seconds, now);
+        self.day = wall_clock::day_number(self.local_seconds(now), self.settings.rollover_hour);
+        self.save_time(now);
+    }
+
+    // Keeps a recent time for the next boot to start from
+    fn save_time(&mut self, now: Instant) {
+        self.time_saved = now;
+        if self.preset_store.save_time(self.wall_clock.utc_seconds(now)).is_err() {
+            log::warn!("couldn't save the time");
+        }
+    }
+
+    fn finish_clock_setting(&mut self, result: SetterResult, now: Instant) {
+        let Some(setter) = self.setting_clock else {
+            return
+        };
+        match result {
+            SetterResult::Editing => return,
+            SetterResult::Done => {
+                let offset = i64::from(self.settings.utc_offset_minutes) * 60;
+                let local = setter.apply_to(self.local_seconds(now));
+                self.set_wall_clock(local.saturating_add_signed(-offset), now);
+                if setter.brightness() != self.settings.brightness {
+                    self.set_brightness(setter.brightness());
+                }
+                if setter.volume() != self.settings.volume || setter.muted() != self.settings.muted {
+                    self.set_sound(setter.volume(), setter.muted());
+                }
+                if setter.precision() != self.time.precision() {
+                    self.set_precision(setter.precision());
+                }
             }
+            SetterResult::Cancelled => ()
         }
+        self.setting_clock = None;
     }
 
-    pub(crate) fn render(self, time: &mut Time) -> (Panel, Duration) {
-        match self {
-            Self::Working => Self::render_working(time),
-            Self::Break => Self::render_break(time),
-            Self::Paused => Self::render_paused(time)
+    fn local_seconds(&self, now: Instant) -> u64 {
+        self.wall_clock.local_seconds(now, self.settings.utc_offset_minutes)
+    }
+
+    // Today's totals so far, in whole seconds like the archived days
+    fn today(&self) -> DayTotals {
+        DayTotals {
+            day: self.day,
+            work: Duration::from_secs(self.time.work_elapsed().as_secs()),
+            break_time: Duration::from_secs(self.time.break_elapsed().as_secs()),
+            goal: self.settings.daily_goal
         }
     }
 
-    fn render_working(time: &mut Time) -> (Panel, Duration) {
-        let (display_time, sleep_dur) = time.sleep_for_work();
-        let panel = Panel::from_time(display_time, PanelPosition::Top);
-        (panel, sleep_dur)
+    // Archives the totals once the day is over and returns the time left until the next rollover
+    fn check_rollover(&mut self, now: Instant) -> Duration {
+        let local = self.local_seconds(now);
+        let hour = self.settings.rollover_hour;
+        let day = wall_clock::day_number(local, hour);
+        if day != self.day {
+            let (work, break_time) = self.time.rollover(wall_clock::since_rollover(local, hour), now);
+            log::info!("day {} done: {:?} work, {:?} break", self.day, work, break_time);
+            let kind = HistoryKind::DailyTotals { day: self.day, work, break_time, goal: self.settings.daily_goal };
+            self.history.push(HistoryEntry { at: now, kind });
+            self.day = day;
+        }
+        wall_clock::until_rollover(local, hour)
     }
 
-    fn render_break(time: &mut Time) -> (Panel, Duration) {
-        let (display_time, sleep_dur) = time.sleep_for_break();
-        let panel = Panel::from_time(display_time, PanelPosition::Bottom);
-        (panel, sleep_dur)
+    /*
+     * One pass of device_loop: draws, drives the backlight and the buzzer, then lets the power policy decide.
+     * Returns how long device_loop may wait for a notice before calling it again, None while asleep.
+     */
+    pub(crate) fn update<O: DeviceOutputs>(&mut self, outputs: &mut O, now: Instant) -> Option<Duration> {
+        // Nothing is drawn while the panel is off, the wake notice starts rendering again
+        if self.power.is_asleep() {
+            return None
+        }
+
+        let (panel, sleep_dur) = self.render(now);
+        outputs.render(panel);
+        if let Some(level) = self.backlight(now) {
+            outputs.backlight(level);
+        }
+        if let Some(alert) = self.take_alert() {
+            outputs.alert(alert);
+        }
+        Some(self.manage_power(outputs, sleep_dur, now))
     }
 
-    fn render_paused(time: &mut Time) -> (Panel, Duration) {
-        let (display_time, sleep_dur) = time.sleep_for_pause();
-        let panel = Panel::from_time(display_time, PanelPosition::Middle);
+    fn render(&mut self, now: Instant) -> (Panel, Duration) {
+        if self.editing.is_some_and(|edit| edit.expired(now)) {
+            self.editing = None;
+        }
+
+        if let Some(picker) = self.picker {
+            if picker.expired(now) {
+                self.finish_picking(picker.long_press(), now);
+            }
+        }
+
+        let idle_status = self.check_idle(now);
+        let until_rollover = self.check_rollover(now);
+        if now.saturating_duration_since(self.time_saved) >= TIME_SAVE_INTERVAL {
+            self.save_time(now);
+        }
+
+        let (mut panel, sleep_dur) = self.mode.render(self.machine.state(), &mut self.time, now);
+        if let Some(tune) = self.mode.take_alert() {
+            self.sound_alert(tune);
+        }
+        self.check_goal();
+        self.check_overtime();
+        // Wake up right at the boundary so the running segment is split there
+        let sleep_dur = sleep_dur.min(until_rollover);
+        let sleep_dur = self.dimmer.until_dim(&self.settings, now).map_or(sleep_dur, |left| sleep_dur.min(left));
+        if self.screen == Screen::Stats {
+            panel = Panel(PanelPosition::FullScreen, Payload::Stats(self.history.stats(self.today())));
+        }
+        if let Some(setter) = &self.setting_clock {
+            panel = Panel(PanelPosition::FullScreen, Payload::SetClock(setter.frame()));
+        }
+        if let Some(picker) = &self.picker {
+            panel = Panel(PanelPosition::FullScreen, Payload::Presets(self.presets_frame(picker.selected())));
+        }
+        if let Payload::Timers(frame) = &mut panel.1 {
+            frame.time_of_day = Some(clock_setter::time_of_day(self.local_seconds(now)));
+            frame.editing = self.editing.map(|edit| edit.timer());
+            frame.goal = self.settings.daily_goal.map(|goal| GoalProgress::new(self.time.work_elapsed(), goal));
+            frame.battery = self.battery;
+            frame.palette = self.presets.get(self.current_preset).map(|preset| preset.palette).unwrap_or_default();
+            if let IdleStatus::Prompting(left) = idle_status {
+                frame.idle_countdown = Some(left.as_secs() + 1);
+            }
+        }
         (panel, sleep_dur)
     }
 
+    // Only a plain pause sleeps; other modes keep their own clocks and menus are waiting on an answer
+    fn can_sleep(&self) -> bool {
+        self.mode.uses_timers()
+            && self.machine.state() == SessionState::Paused
+            && self.editing.is_none()
+            && self.setting_clock.is_none()
+            && self.picker.is_none()
+    }
+
+    // Returns how long device_loop may wait before it has to check again
+    fn manage_power<C: PowerControl>(&mut self, control: &mut C, sleep_dur: Duration, now: Instant) -> Duration {
+        match self.power.update(control, self.can_sleep(), now) {
+            Some(until_sleep) => sleep_dur.min(until_sleep),
+            None => sleep_dur
+        }
+    }
+
+    /*
+     * Paused totals don't move, so only the wall clock has to be carried over the time Instant missed.
+     * The day rollover then catches up on the next render.
+     */
+    fn wake(&mut self, missed: Duration, now: Instant) {
+        self.wall_clock.skip(missed);
+        self.idle.input(now);
+        self.power.woke(now);
+        // The backlight was switched off for the sleep
+        self.dimmer.input(now);
+        self.dimmer.reset();
+    }
+
+    // The level being picked in the settings is shown as it is, without the night cap
+    fn backlight(&mut self, now: Instant) -> Option<BacklightLevel> {
+        let (hour, _) = clock_setter::time_of_day(self.local_seconds(now));
+        let settings = match self.setting_clock {
+            Some(setter) if setter.field() == ClockField::Brightness => Settings {
+                brightness: setter.brightness(),
+                night: None,
+                ..self.settings
+            },
+            _ => self.settings
+        };
+        self.dimmer.update(&settings, hour, now)
+    }
+
+    // Kept with the current preset so switching presets doesn't undo it
+    fn set_brightness(&mut self, brightness: u8) {
+        self.settings.brightness = brightness;
+        self.update_preset(|preset| preset.settings.brightness = brightness);
+    }
+
+    fn set_sound(&mut self, volume: u8, muted: bool) {
+        self.settings.volume = volume;
+        self.settings.muted = muted;
+        self.update_preset(|preset| {
+            preset.settings.volume = volume;
+            preset.settings.muted = muted;
+        });
+    }
+
+    fn set_precision(&mut self, precision: DisplayPrecision) {
+        self.time.set_precision(precision);
+        self.update_preset(|preset| preset.precision = precision);
+    }
+
+    fn update_preset(&mut self, update: impl FnOnce(&mut Preset)) {
+        let Some(preset) = self.presets.get_mut(self.current_preset) else {
+            return
+        };
+        update(preset);
+        if self.preset_store.save(&self.presets, self.current_preset).is_err() {
+            log::warn!("couldn't save the preset");
+        }
+    }
+
+    // Only an unattended work timer is a problem, breaks and other modes can run on
+    fn check_idle(&mut self, now: Instant) -> IdleStatus {
+        let watching = self.mode.uses_timers()
+            && self.machine.state() == SessionState::Working;
+        if !watching {
+            self.idle.input(now);
+            return IdleStatus::Active
+        }
+
+        let status = self.idle.status(now, &self.settings);
+        if status == IdleStatus::TimedOut {
+            self.idle_timeout(now);
+        }
+        status
+    }
+
+    /*
+     * Nobody answered the prompt, so pause as of the last input.
+     * The transition is logged at that moment too, which lets undo give the idle time back.
+     */
+    fn idle_timeout(&mut self, now: Instant) {
+        let last_input = self.idle.last_input();
+        let paused_from = self.machine.paused_from();
+        if let Some(step) = self.machine.handle(SessionEvent::IdleTimeout) {
+            let idle = now.saturating_duration_since(last_input);
+            self.time.reassign(step.from, step.to, idle, now);
+            self.perform(&step, paused_from, last_input);
+        }
+        self.idle.input(now);
+    }
+
+    // Returns false when the input only dismissed the idle prompt
+    fn register_input(&mut self, now: Instant) -> bool {
+        let prompting = matches!(self.idle.status(now, &self.settings), IdleStatus::Prompting(_))
+            && self.machine.state() == SessionState::Working;
+        self.idle.input(now);
+        !prompting
+    }
+
+    // Every notice that changes the session goes through here, so the log holds enough to replay it
+    fn input(&mut self, input: RecordedInput, now: Instant) -> Outcome {
+        self.inputs.push(InputRecord { at: now, input });
+        match input {
+            RecordedInput::Press(..) | RecordedInput::Event(_) | RecordedInput::Scroll(_) | RecordedInput::Touch(_) => {
+                self.dimmer.input(now);
+                // Came in after the panel was blanked, the CPU stays up and the panel comes back on instead
+                if self.power.input(now) {
+                    self.dimmer.reset();
+                    return Outcome::Handled
+                }
+            }
+            _ => ()
+        }
+
+        match input {
+            RecordedInput::Press(button, press) => return self.handle_input(self.buttons.event(button, press), now),
+            RecordedInput::Event(event) => return self.handle_input(event, now),
+            RecordedInput::Scroll(steps) => {
+                if self.register_input(now) {
+                    self.scroll(steps, now)
+                }
+            }
+            RecordedInput::Touch(gesture) if self.register_input(now) => return self.touch(gesture, now),
+            RecordedInput::Touch(_) => (),
+            RecordedInput::SetMode(mode) => self.mode = ActiveMode::new(mode, now),
+            RecordedInput::SetSettings(settings) => self.set_settings(settings, now),
+            RecordedInput::SetPrecision(precision) => self.time.set_precision(precision),
+            RecordedInput::AdjustTimer(timer, minutes) => self.adjust(timer, minutes, now),
+            RecordedInput::SetWallClock(utc_seconds) => self.set_wall_clock(utc_seconds, now),
+            RecordedInput::OpenClockSettings => self.open_clock_settings(now),
+            RecordedInput::OpenPresetPicker => self.open_preset_picker(now),
+            RecordedInput::Battery(status) => self.battery = Some(status),
+            RecordedInput::Woke(missed) => self.wake(missed, now)
+        }
+        Outcome::Handled
+    }
+
+    // Long presses act like the main button, horizontal swipes flip between the timers and the stats unless a menu is open
+    fn touch(&mut self, gesture: TouchGesture, now: Instant) -> Outcome {
+        let in_menu = self.picker.is_some() || self.setting_clock.is_some();
+        match gesture {
+            TouchGesture::Tap(point) => self.tap(point, now),
+            TouchGesture::LongPress(_) => return self.handle_event(SessionEvent::LongPress, now),
+            TouchGesture::Swipe(SwipeDirection::Left | SwipeDirection::Right) if !in_menu => {
+                self.screen = match self.screen {
+                    Screen::Timers => Screen::Stats,
+                    Screen::Stats => Screen::Timers
+                };
+            }
+            TouchGesture::Swipe(_) => ()
+        }
+        Outcome::Handled
+    }
+
+    /*
+     * Taps are hit-tested against whatever render() put on screen.
+     * A preset row is picked, a clock setting field or a timer total gets the cursor,
+     * and tapping the one that already has it selects it like a press would.
+     */
+    fn tap(&mut self, point: Point, now: Instant) {
+        if let Some(picker) = self.picker {
+            if let Some(index) = self.presets_frame(picker.selected()).row_at(point) {
+                self.finish_picking(PickerResult::Picked(index), now);
+            }
+            return
+        }
+        if let Some(setter) = &mut self.setting_clock {
+            match setter.frame().field_at(point) {
+                Some(field) if field == setter.field() => {
+                    let result = setter.short_press();
+                    self.finish_clock_setting(result, now);
+                }
+                Some(field) => setter.focus(field),
+                None => ()
+            }
+            return
+        }
+        if self.screen != Screen::Timers || !self.mode.uses_timers() {
+            return
+        }
+        match (TimersFrame::timer_at(point), self.editing) {
+            (Some(timer), Some(edit)) if edit.timer() == timer => self.editing = None,
+            (Some(timer), _) => self.editing = Some(TimerEdit::new(timer, now)),
+            (None, _) => ()
+        }
+    }
+
+    fn handle_input(&mut self, event: SessionEvent, now: Instant) -> Outcome {
+        if self.register_input(now) {
+            self.handle_event(event, now)
+        } else {
+            Outcome::Handled
+        }
+    }
+
+    fn handle_event(&mut self, event: SessionEvent, now: Instant) -> Outcome {
+        if let Some(picker) = &mut self.picker {
+            picker.touch(now);
+            let result = match event {
+                SessionEvent::ShortPress => picker.short_press(),
+                SessionEvent::LongPress | SessionEvent::Select => picker.long_press(),
+                // Leaves the current preset as it is and moves on to the clock
+                SessionEvent::Menu => {
+                    self.picker = None;
+                    self.open_clock_settings(now);
+                    return Outcome::Handled
+                }
+                _ => PickerResult::Browsing
+            };
+            self.finish_picking(result, now);
+            return Outcome::Handled
+        }
+        if let Some(setter) = &mut self.setting_clock {
+            let result = match event {
+                SessionEvent::ShortPress | SessionEvent::Select => setter.short_press(),
+                SessionEvent::LongPress | SessionEvent::Menu => setter.long_press(),
+                _ => SetterResult::Editing
+            };
+            self.finish_clock_setting(result, now);
+            return Outcome::Handled
+        }
+        if let Some(edit) = &mut self.editing {
+            if event == SessionEvent::Select {
+                self.editing = None;
+                return Outcome::Handled
+            }
+            let press = match event {
+                SessionEvent::ShortPress => Some(PressDuration::Short),
+                SessionEvent::LongPress => Some(PressDuration::Long),
+                _ => None
+            };
+            if let Some(press) = press {
+                if !edit.press(press, now) {
+                    self.editing = None;
+                }
+                return Outcome::Handled
+            }
+        }
+        if self.mode.handle_event(event, now) {
+            return Outcome::Handled
+        }
+
+        match event {
+            SessionEvent::Undo => return self.undo(now),
+            SessionEvent::Menu => {
+                self.editing = None;
+                self.open_preset_picker(now);
+                return Outcome::Handled
+            }
+            // The first press of a double press already changed state, so take that back as well
+            SessionEvent::ShortPress if self.is_double_press(now) => {
+                self.last_short_press = None;
+                self.undo(now);
+                return self.undo(now)
+            }
+            _ => ()
+        }
+
+        let outcome = self.run_machine(event, now);
+        if event == SessionEvent::ShortPress && matches!(outcome, Outcome::Stepped(_)) {
+            self.last_short_press = Some(now);
+        }
+        outcome
+    }
+
+    fn run_machine(&mut self, event: SessionEvent, now: Instant) -> Outcome {
+        let paused_from = self.machine.paused_from();
+        match self.machine.handle(event) {
+            Some(step) => {
+                self.perform(&step, paused_from, now);
+                Outcome::Stepped(step)
+            }
+            None => Outcome::Rejected(event, self.machine.state())
+        }
+    }
+
+    fn is_double_press(&self, now: Instant) -> bool {
+        self.last_short_press
+            .is_some_and(|pressed| now.saturating_duration_since(pressed) <= DOUBLE_PRESS_WINDOW)
+    }
+
+    /*
+     * Steps back over the newest logged transition if it happened within the undo window.
+     * The time counted since then goes to the timer that was running before it,
+     * so undoing several quick toggles one after another keeps the totals consistent.
+     */
+    fn undo(&mut self, now: Instant) -> Outcome {
+        if !self.mode.uses_timers() {
+            return Outcome::NothingToUndo
+        }
+        // A manual correction after the transition blocks undoing past it
+        let Some(HistoryEntry { at, kind: HistoryKind::Transition { from, to, paused_from } }) =
+            self.history.last().copied() else {
+            return Outcome::NothingToUndo
+        };
+        let since = now.saturating_duration_since(at);
+        if since > self.settings.undo_window || to != self.machine.state() {
+            return Outcome::NothingToUndo
+        }
+
+        self.history.pop_last();
+        self.time.reassign(to, from, since, now);
+        self.machine.restore(from, paused_from);
+        Outcome::Undone { from, to }
+    }
+
+    // Encoder turns edit the timers on the double timer screen and scroll everywhere else
+    fn scroll(&mut self, steps: i32, now: Instant) {
+        if let Some(picker) = &mut self.picker {
+            picker.touch(now);
+            // The list is short enough that acceleration would only skip entries
+            picker.scroll_by(steps.signum());
+            return
+        }
+        if let Some(setter) = &mut self.setting_clock {
+            setter.scroll_by(steps);
+            return
+        }
+        if !self.mode.uses_timers() {
+            self.mode.scroll(steps);
+            return
+        }
+
+        let edit = self.editing.get_or_insert(TimerEdit::new(self.machine.state(), now));
+        edit.touch(now);
+        let timer = edit.timer();
+        self.adjust(timer, steps, now);
+    }
+
+    fn adjust(&mut self, timer: SessionState, minutes: i32, now: Instant) {
+        let seconds = self.time.adjust(timer, minutes, now);
+        if seconds == 0 {
+            return
+        }
+
+        // Consecutive steps on the same total end up as a single correction
+        if let Some(entry) = self.history.last_mut() {
+            if let HistoryKind::Correction { timer: last_timer, seconds: total } = &mut entry.kind {
+                if *last_timer == timer && now.saturating_duration_since(entry.at) <= EDIT_TIMEOUT {
+                    *total += seconds;
+                    entry.at = now;
+                    return
+                }
+            }
+        }
+        self.history.push(HistoryEntry { at: now, kind: HistoryKind::Correction { timer, seconds } });
+    }
+
+    fn perform(&mut self, step: &Step, paused_from: SessionState, now: Instant) {
+        for action in &step.actions {
+            match action {
+                SessionAction::LogHistory => {
+                    let kind = HistoryKind::Transition { from: step.from, to: step.to, paused_from };
+                    self.history.push(HistoryEntry { at: now, kind })
+                }
+                SessionAction::SoundAlert => {
+                    let tune = if step.from == SessionState::Break { Tune::BreakDone } else { Tune::WorkDone };
+                    self.sound_alert(tune)
+                }
+                SessionAction::CloseEdit => self.editing = None
+            }
+        }
+    }
+
+    // A later tune in the same step replaces an earlier one, the buzzer only plays the newest anyway
+    fn sound_alert(&mut self, tune: Tune) {
+        if self.settings.volume > 0 && !self.settings.muted {
+            self.alert = Some(tune);
+        }
+    }
+
+    fn take_alert(&mut self) -> Option<Alert> {
+        let tune = self.alert.take()?;
+        Some(Alert { tune, volume: self.settings.volume })
+    }
+
+    // Only the moment of reaching it plays, a goal that was already reached at boot or after undo stays quiet
+    fn check_goal(&mut self) {
+        let reached = self.settings.daily_goal
+            .is_some_and(|goal| GoalProgress::new(self.time.work_elapsed(), goal).reached());
+        if reached && !self.goal_reached {
+            self.sound_alert(Tune::GoalReached);
+        }
+        self.goal_reached = reached;
+    }
+
+    // A Flowtime break past its credit is reminded of right away and then every OVERTIME_REMINDER
+    fn check_overtime(&mut self) {
+        let overdrawn = match self.mode {
+            ActiveMode::Flowtime(ratio) if self.machine.state() == SessionState::Break => {
+                match ratio.credit(self.time.work_elapsed(), self.time.break_elapsed()) {
+                    BreakCredit::Overdrawn(overdrawn) => Some(overdrawn),
+                    BreakCredit::Remaining(_) => None
+                }
+            }
+            _ => None
+        };
+        let Some(overdrawn) = overdrawn else {
+            self.overtime_reminders = 0;
+            return
+        };
+        let due = overdrawn.as_ticks() / OVERTIME_REMINDER.as_ticks() + 1;
+        if due > self.overtime_reminders {
+            self.overtime_reminders = due;
+            self.sound_alert(Tune::Overtime);
+        }
+    }
 }
 
-pub type SessionNotifier = (SessionOuterNotifier, TFTNotifier);
-pub type SessionOuterNotifier = Channel<CriticalSectionRawMutex, SessionNotice, 4>;
+// What a replayed session sent to the hardware
+#[derive(Default)]
+pub struct ReplayOutputs {
+    pub panel: Option<Panel>,
+    pub backlight: Option<BacklightLevel>,
+    pub alert: Option<Alert>,
+    // Tunes played so far, `alert` is the newest
+    pub alerts: usize,
+    pub sleeps: usize,
+    // Sleeps an input called off before the CPU went down
+    pub cancelled: usize
+}
 
-pub struct DoubleTimerSession<'spi>(&'spi SessionOuterNotifier);
-//{
-//    tft: TFT<'spi>,
-//    work_clock: Duration,
-//    break_clock: Duration,
-//    session_state: SessionState,
-//    spawner: Spawner
-//}
+impl PowerControl for ReplayOutputs {
+    fn blank_display(&mut self) {
+        self.panel = Some(Panel(PanelPosition::FullScreen, Payload::Sleep));
+        self.backlight = Some(BacklightLevel::OFF);
+    }
 
-impl<'spi> DoubleTimerSession<'spi> {
-    pub fn new(
-        tft: TFT<'static>,
-        spawner: Spawner,
-        notifier: &'static SessionNotifier,
-    ) -> Result<Self, SpawnError> {
-        let (outer_notifier, tft_notifier) = notifier;
-        let tft = TFTRender::new(tft, tft_notifier, spawner)?;
-        spawner.spawn(device_loop(outer_notifier, tft))?;
-        Ok(Self(outer_notifier))
+    // The log holds the wake notice, so there's nothing to wait for
+    fn light_sleep(&mut self) {
+        self.sleeps += 1;
     }
 
-    pub(crate) async fn set_state(&self, new_state: SessionState) {
-        self.0.send(SessionNotice::SetState(new_state)).await;
+    fn cancel_sleep(&mut self) {
+        self.cancelled += 1;
     }
+}
 
-    #[must_use]
-    pub const fn notifier() -> SessionNotifier {
-        (Channel::new(), TFTRender::notifier())
+impl DeviceOutputs for ReplayOutputs {
+    fn render(&mut self, panel: Panel) {
+        self.panel = Some(panel);
     }
 
+    fn backlight(&mut self, level: BacklightLevel) {
+        self.backlight = Some(level);
+    }
+
+    fn alert(&mut self, alert: Alert) {
+        self.alert = Some(alert);
+        self.alerts += 1;
+    }
 }
 
-pub enum SessionNotice {
-    SetState(SessionState),
-    AdjustTimer(Duration)
+/*
+ * Runs a dump of the input log through a fresh session without any hardware.
+ * Between records it wakes up whenever device_loop's timer would have, so timeouts, segment changes
+ * and the day rollover land where they did on the device.
+ * The session only comes out the same when it boots with the same presets and nothing was dropped from the log.
+ */
+pub struct SessionReplay {
+    device: DeviceState,
+    outputs: ReplayOutputs,
+    // When device_loop would next wake up on its own, None while asleep
+    wake_at: Option<Instant>
 }
 
-impl SessionNotice {
-    pub(crate) fn apply(self, time: &mut Time, state: &mut SessionState) {
-        match self {
-            Self::AdjustTimer(delta) => {
-                *time += delta
-            }
-            Self::SetState(new_state) => {
-                *state = new_state
-            }
+impl SessionReplay {
+    pub fn new(presets: PresetStore<PresetStorage>, boot: Instant) -> Self {
+        let mut device = DeviceState::new(presets, boot);
+        device.boot(boot);
+        let mut replay = Self { device, outputs: ReplayOutputs::default(), wake_at: None };
+        replay.update(boot);
+        replay
+    }
+
+    fn update(&mut self, now: Instant) {
+        self.wake_at = self.device.update(&mut self.outputs, now).map(|sleep_dur| now + sleep_dur);
+    }
+
+    pub fn advance_to(&mut self, now: Instant) {
+        while let Some(at) = self.wake_at.filter(|at| *at <= now) {
+            self.update(at);
+        }
+    }
+
+    // Applies the record at its own time, then redraws like device_loop does after every notice
+    pub fn feed(&mut self, record: InputRecord) -> &ReplayOutputs {
+        self.advance_to(record.at);
+        self.device.input(record.input, record.at);
+        self.update(record.at);
+        &self.outputs
+    }
+
+    // Feeds every record in a serial log, anything before "input" on a line and any other line is skipped
+    pub fn run(&mut self, log: &str) -> &ReplayOutputs {
+        let records = log
+            .lines()
+            .filter_map(|line| line.find("input ").map(|start| &line[start..]))
+            .filter_map(InputRecord::parse);
+        for record in records {
+            self.feed(record);
         }
+        &self.outputs
+    }
+
+    pub const fn outputs(&self) -> &ReplayOutputs {
+        &self.outputs
     }
 }
 
-#[embassy_executor::task]
-async fn device_loop(session_notifier: &'static SessionOuterNotifier, tft_renderer: TFTRender<'static>) -> ! {
-    let mut time = Time::default();
-    let mut session_state = SessionState::default();
+#[cfg(test)]
+mod tests {
+    use std::{format, string::String as StdString};
+
+    use super::*;
+    use crate::{battery::ChargeState, constants::SLEEP_AFTER_PAUSE, scenes::UIType, state_machine::SerialCommand};
+
+    fn at(seconds: u64, input: RecordedInput) -> InputRecord {
+        InputRecord { at: Instant::from_secs(seconds), input }
+    }
+
+    fn replay() -> SessionReplay {
+        SessionReplay::new(PresetStore::new(PresetStorage::default()), Instant::from_secs(0))
+    }
+
+    fn timers(outputs: &ReplayOutputs) -> TimersFrame {
+        match outputs.panel.as_ref().map(|panel| panel.1) {
+            Some(Payload::Timers(frame)) => frame,
+            other => panic!("not the timers: {:?}", other)
+        }
+    }
+
+    fn text(time: [u8; 20]) -> StdString {
+        StdString::from_utf8(time.to_vec()).unwrap().trim_end().into()
+    }
+
+    // Picks the count up preset the picker opens on, works a minute, then takes a break with a correction
+    fn double_timer_session() -> [InputRecord; 6] {
+        let quiet = Settings { volume: 0, ..Settings::default() };
+        [
+            at(2, RecordedInput::Press(ButtonId::Main, PressDuration::Long)),
+            at(62, RecordedInput::Press(ButtonId::Main, PressDuration::Short)),
+            at(92, RecordedInput::AdjustTimer(SessionState::Working, 5)),
+            at(100, RecordedInput::Battery(BatteryStatus { percent: 80, charge: ChargeState::Discharging })),
+            at(122, RecordedInput::Press(ButtonId::Main, PressDuration::Long)),
+            at(130, RecordedInput::SetSettings(quiet))
+        ]
+    }
+
+    #[test]
+    fn replay_runs_on_the_recorded_timestamps() {
+        let mut replay = replay();
+        for record in double_timer_session() {
+            replay.feed(record);
+        }
+        let frame = timers(replay.outputs());
+        assert_eq!(frame.state, SessionState::Paused);
+        assert_eq!(text(frame.work_time), "00:06:02");
+        assert_eq!(text(frame.break_time), "00:01:00");
+        assert_eq!(frame.battery.map(|status| status.percent), Some(80));
+        assert_eq!(replay.outputs().sleeps, 0);
+    }
+
+    #[test]
+    fn replay_sleeps_and_wakes_like_the_device() {
+        let mut replay = replay();
+        for record in double_timer_session() {
+            replay.feed(record);
+        }
+        // Nothing happens between records, but the timer still puts the paused session to sleep
+        replay.advance_to(Instant::from_secs(130) + SLEEP_AFTER_PAUSE);
+        assert_eq!(replay.outputs().sleeps, 1);
+        assert!(matches!(replay.outputs().panel, Some(Panel(_, Payload::Sleep))));
+
+        replay.feed(at(4000, RecordedInput::Woke(Duration::from_secs(3600))));
+        // The pause interrupted the break, so that's what carries on
+        replay.feed(at(4001, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        replay.advance_to(Instant::from_secs(4011));
+        let frame = timers(replay.outputs());
+        assert_eq!(frame.state, SessionState::Break);
+        assert_eq!(text(frame.work_time), "00:06:02");
+        assert_eq!(text(frame.break_time), "00:01:10");
+    }
+
+    #[test]
+    fn input_right_after_the_blank_calls_the_sleep_off() {
+        let mut replay = replay();
+        for record in double_timer_session() {
+            replay.feed(record);
+        }
+        replay.advance_to(Instant::from_secs(130) + SLEEP_AFTER_PAUSE);
+        assert_eq!(replay.outputs().sleeps, 1);
+
+        // Only wakes the panel, the session stays paused
+        let wake_at = 131 + SLEEP_AFTER_PAUSE.as_secs();
+        replay.feed(at(wake_at, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        assert_eq!(replay.outputs().cancelled, 1);
+        let frame = timers(replay.outputs());
+        assert_eq!(frame.state, SessionState::Paused);
+        assert_eq!(text(frame.work_time), "00:06:02");
+        assert_ne!(replay.outputs().backlight, Some(BacklightLevel::OFF));
+
+        // Awake again, so the next press counts and the timeout starts over
+        replay.feed(at(wake_at + 1, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        assert_eq!(timers(replay.outputs()).state, SessionState::Break);
+        assert_eq!(replay.outputs().sleeps, 1);
+    }
+
+    #[test]
+    fn a_stopped_timer_ends_its_edit() {
+        let mut replay = replay();
+        for record in &double_timer_session()[..2] {
+            replay.feed(*record);
+        }
+        assert_eq!(timers(replay.outputs()).state, SessionState::Break);
+        assert_eq!(timers(replay.feed(at(63, RecordedInput::Scroll(1)))).editing, Some(SessionState::Break));
+        // Presses go to the edit, but a serial command still switches the timers
+        let outputs = replay.feed(at(64, RecordedInput::Event(SessionEvent::SerialCommand(SerialCommand::Work))));
+        assert_eq!(timers(outputs).state, SessionState::Working);
+        assert_eq!(timers(outputs).editing, None);
+    }
+
+    #[test]
+    fn segment_alerts_play_between_records() {
+        let mut replay = replay();
+        replay.feed(at(1, RecordedInput::Press(ButtonId::Main, PressDuration::Long)));
+        replay.feed(at(2, RecordedInput::SetMode(SessionMode::Interval(IntervalProgram::default()))));
+        replay.feed(at(3, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        assert_eq!(replay.outputs().alerts, 0);
+
+        // Five minutes of warm-up, then 40s of work
+        replay.feed(at(3 + 300 + 41, RecordedInput::Battery(BatteryStatus { percent: 50, charge: ChargeState::Charging })));
+        assert_eq!(replay.outputs().alerts, 2);
+        assert_eq!(replay.outputs().alert.map(|alert| alert.tune), Some(Tune::WorkDone));
+    }
 
-    loop {
-        let (panel, sleep_dur) = session_state.render(&mut time);
-        tft_renderer.render(panel);
-        if let Either::First(notification) = select(session_notifier.receive(), Timer::after(sleep_dur)).await
-        {
-            notification.apply(&mut time, &mut session_state);
+    #[test]
+    fn muting_keeps_the_segment_alerts_quiet() {
+        let mut replay = replay();
+        replay.feed(at(1, RecordedInput::Press(ButtonId::Main, PressDuration::Long)));
+        replay.feed(at(2, RecordedInput::SetMode(SessionMode::Interval(IntervalProgram::default()))));
+        replay.feed(at(2, RecordedInput::SetSettings(Settings { muted: true, ..Settings::default() })));
+        replay.feed(at(3, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        replay.feed(at(3 + 300 + 41, RecordedInput::Battery(BatteryStatus { percent: 50, charge: ChargeState::Charging })));
+        assert_eq!(replay.outputs().alerts, 0);
+    }
+
+    fn tap(seconds: u64, x: i32, y: i32) -> InputRecord {
+        at(seconds, RecordedInput::Touch(TouchGesture::Tap(Point::new(x, y))))
+    }
+
+    // A tap in the middle of an element the screen was drawn with
+    fn tap_on(seconds: u64, element: UIType) -> InputRecord {
+        let center = element.node().unwrap().get_position().center();
+        tap(seconds, center.x, center.y)
+    }
+
+    fn picker(outputs: &ReplayOutputs) -> PresetsFrame {
+        match outputs.panel.as_ref().map(|panel| panel.1) {
+            Some(Payload::Presets(frame)) => frame,
+            other => panic!("not the picker: {:?}", other)
         }
     }
+
+    #[test]
+    fn taps_hit_what_is_on_screen() {
+        // The picker is up after boot, the second row is the stopwatch
+        let mut stopwatch = replay();
+        let rows = picker(stopwatch.outputs()).elements();
+        let outputs = stopwatch.feed(tap_on(1, rows[1]));
+        assert!(matches!(outputs.panel, Some(Panel(_, Payload::Stopwatch(_)))));
+
+        let mut replay = replay();
+        let frame = picker(replay.outputs());
+        // Slots past the last preset aren't drawn, so they can't be hit either
+        assert!(frame.elements()[frame.count..].iter().all(|row| row.node().is_none()));
+        replay.feed(tap_on(1, frame.elements()[0]));
+        // The break total takes the cursor and the encoder corrects it
+        let [work, rest] = TimersFrame::elements();
+        let frame = timers(replay.feed(tap_on(2, rest)));
+        assert_eq!(frame.editing, Some(SessionState::Break));
+        let frame = timers(replay.feed(at(3, RecordedInput::Scroll(2))));
+        assert_eq!(text(frame.break_time), "00:02:00");
+        assert_eq!(timers(replay.feed(tap_on(4, work))).editing, Some(SessionState::Working));
+        assert_eq!(timers(replay.feed(tap_on(5, work))).editing, None);
+        // The divider isn't a target
+        let divider = Point::new(160, 120);
+        assert!(TimersFrame::elements().iter().all(|element| !element.node().unwrap().get_position().contains(divider)));
+        assert_eq!(timers(replay.feed(tap(6, divider.x, divider.y))).editing, None);
+    }
+
+    #[test]
+    fn taps_move_the_clock_settings_cursor() {
+        let mut replay = replay();
+        replay.feed(at(1, RecordedInput::Press(ButtonId::Main, PressDuration::Long)));
+        replay.feed(at(2, RecordedInput::OpenClockSettings));
+        let frame = |outputs: &ReplayOutputs| match outputs.panel.as_ref().map(|panel| panel.1) {
+            Some(Payload::SetClock(frame)) => Some(frame),
+            _ => None
+        };
+        let [_, minutes, lower] = frame(replay.outputs()).unwrap().elements();
+        assert_eq!(frame(replay.feed(tap_on(3, minutes))).map(|frame| frame.field), Some(ClockField::Minutes));
+        assert_eq!(frame(replay.feed(tap_on(4, lower))).map(|frame| frame.field), Some(ClockField::Brightness));
+        // Tapping the field that has the cursor moves on, like a short press
+        assert_eq!(frame(replay.feed(tap_on(5, lower))).map(|frame| frame.field), Some(ClockField::Volume));
+        assert_eq!(frame(replay.feed(tap_on(6, lower))).map(|frame| frame.field), Some(ClockField::Mute));
+        assert_eq!(frame(replay.feed(tap_on(7, lower))).map(|frame| frame.field), Some(ClockField::Precision));
+        let outputs = replay.feed(tap_on(8, lower));
+        assert!(matches!(outputs.panel, Some(Panel(_, Payload::Timers(_)))));
+    }
+
+    #[test]
+    fn a_dump_replays_into_the_same_session() {
+        let mut device = replay();
+        for record in double_timer_session() {
+            device.feed(record);
+        }
+        device.feed(at(140, RecordedInput::SetMode(SessionMode::Stopwatch)));
+        device.feed(at(150, RecordedInput::Press(ButtonId::Main, PressDuration::Short)));
+        device.feed(at(170, RecordedInput::Scroll(-2)));
+        device.advance_to(Instant::from_secs(175));
+
+        let mut dump = StdString::from("INFO - inputs begin\n");
+        for record in device.device.inputs.iter() {
+            dump += &format!("INFO - {}\n", record);
+        }
+        dump += "INFO - inputs end\n";
+        assert_eq!(device.device.inputs.len(), 9);
+
+        let mut replay = replay();
+        replay.run(&dump);
+        replay.advance_to(Instant::from_secs(175));
+        let shown = |outputs: &ReplayOutputs| format!("{:?}", outputs.panel.as_ref().map(|panel| panel.1));
+        assert_eq!(shown(replay.outputs()), shown(device.outputs()));
+        assert!(matches!(replay.outputs().panel, Some(Panel(_, Payload::Stopwatch(_)))));
+        assert!(replay.device.inputs.iter().eq(device.device.inputs.iter()));
+    }
 }
@@ -0,0 +1,132 @@
+use heapless::String;
+
+use crate::{constants::CONSOLE_LINE_LEN, input_log::event_named, state_machine::SessionEvent};
+
+/*
+ * One command per line on the serial console:
+ *   "time 1760000000" sets the wall clock to UTC seconds since the Unix epoch (e.g. `date +%s`),
+ *   "clock" opens the clock settings, "presets" the preset picker, "history" exports the daily totals,
+ *   "inputs" dumps the input log for SessionReplay, and an event name from the input log ("work", "break", "pause", "undo", ...) sends that event.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    SetTime(u64),
+    OpenClockSettings,
+    OpenPresetPicker,
+    ExportHistory,
+    DumpInputs,
+    Event(SessionEvent)
+}
+
+impl ConsoleCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let command = match words.next()? {
+            "time" => Self::SetTime(words.next()?.parse().ok()?),
+            "clock" => Self::OpenClockSettings,
+            "presets" => Self::OpenPresetPicker,
+            "history" => Self::ExportHistory,
+            "inputs" => Self::DumpInputs,
+            name => Self::Event(event_named(name)?)
+        };
+        // Trailing words are more likely a typo than something to ignore
+        if words.next().is_some() {
+            return None
+        }
+        Some(command)
+    }
+}
+
+// Collects bytes into lines; a line that doesn't fit is dropped whole
+#[derive(Debug, Default)]
+pub struct LineReader {
+    line: String<CONSOLE_LINE_LEN>,
+    overflowed: bool
+}
+
+impl LineReader {
+    pub const fn new() -> Self {
+        Self { line: String::new(), overflowed: false }
+    }
+
+    // Returns the command once its line ends, unknown lines are logged and skipped
+    pub fn push(&mut self, byte: u8) -> Option<ConsoleCommand> {
+        if byte != b'\n' && byte != b'\r' {
+            if !byte.is_ascii() || self.line.push(char::from(byte)).is_err() {
+                self.overflowed = true;
+            }
+            return None
+        }
+
+        let command = match (self.overflowed, self.line.trim()) {
+            (_, "") => None,
+            (true, _) => {
+                log::warn!("console line too long");
+                None
+            }
+            (false, line) => {
+                let command = ConsoleCommand::parse(line);
+                if command.is_none() {
+                    log::warn!("unknown command {}", line);
+                }
+                command
+            }
+        };
+        self.line.clear();
+        self.overflowed = false;
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::SerialCommand;
+
+    fn feed(reader: &mut LineReader, text: &str) -> heapless::Vec<ConsoleCommand, 4> {
+        text.bytes().filter_map(|byte| reader.push(byte)).collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(ConsoleCommand::parse("time 1760000000"), Some(ConsoleCommand::SetTime(1_760_000_000)));
+        assert_eq!(ConsoleCommand::parse(" clock "), Some(ConsoleCommand::OpenClockSettings));
+        assert_eq!(ConsoleCommand::parse("presets"), Some(ConsoleCommand::OpenPresetPicker));
+        assert_eq!(ConsoleCommand::parse("history"), Some(ConsoleCommand::ExportHistory));
+        assert_eq!(ConsoleCommand::parse("inputs"), Some(ConsoleCommand::DumpInputs));
+        assert_eq!(ConsoleCommand::parse("break"), Some(ConsoleCommand::Event(SessionEvent::SerialCommand(SerialCommand::Break))));
+        assert_eq!(ConsoleCommand::parse("undo"), Some(ConsoleCommand::Event(SessionEvent::Undo)));
+    }
+
+    #[test]
+    fn malformed_commands() {
+        assert_eq!(ConsoleCommand::parse("time"), None);
+        assert_eq!(ConsoleCommand::parse("time -5"), None);
+        assert_eq!(ConsoleCommand::parse("time 12 34"), None);
+        assert_eq!(ConsoleCommand::parse("work now"), None);
+        assert_eq!(ConsoleCommand::parse("reboot"), None);
+    }
+
+    #[test]
+    fn lines_end_on_either_newline() {
+        let mut reader = LineReader::new();
+        assert_eq!(feed(&mut reader, "work\r\npause\n\nclock\r").as_slice(), [
+            ConsoleCommand::Event(SessionEvent::SerialCommand(SerialCommand::Work)),
+            ConsoleCommand::Event(SessionEvent::SerialCommand(SerialCommand::Pause)),
+            ConsoleCommand::OpenClockSettings
+        ]);
+    }
+
+    #[test]
+    fn long_lines_are_dropped_whole() {
+        let mut reader = LineReader::new();
+        let mut long: String<{ 2 * CONSOLE_LINE_LEN }> = String::new();
+        while long.len() < CONSOLE_LINE_LEN {
+            let _ = long.push_str("work ");
+        }
+        let _ = long.push_str("\nbreak\n");
+        assert_eq!(feed(&mut reader, &long).as_slice(), [
+            ConsoleCommand::Event(SessionEvent::SerialCommand(SerialCommand::Break))
+        ]);
+    }
+}
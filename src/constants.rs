@@ -1,5 +1,69 @@
 use embassy_time::Duration;
+use embedded_graphics::pixelcolor::Rgb565;
 
 pub const DEBOUNCE_DELAY: Duration = Duration::from_millis(10);
 pub const MAX_ANIMATIONS: usize = 6;
 pub const FRAME_RATE: usize = 30;
+pub const MAX_LAPS: usize = 32;
+pub const LAP_ROWS: usize = 4;
+pub const MAX_HISTORY: usize = 64;
+pub const UNDO_WINDOW: Duration = Duration::from_secs(10);
+// A second short press within this window turns the pair into an undo
+pub const DOUBLE_PRESS_WINDOW: Duration = Duration::from_millis(400);
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+pub const IDLE_PROMPT: Duration = Duration::from_secs(30);
+// Local hour at which the daily totals start over
+pub const ROLLOVER_HOUR: u8 = 4;
+// Unix time the firmware was compiled at, set by build.rs
+pub const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+pub const DAILY_GOAL: Duration = Duration::from_secs(5 * 3600);
+// Frames of the flashing bar once the goal is reached
+pub const CELEBRATION_FRAMES: usize = 24;
+pub const MAX_ROUND_SEGMENTS: usize = 4;
+// Light Blue
+pub const WORK_COLOR: Rgb565 = Rgb565::new(123, 191, 255);
+// Salmon Pink
+pub const BREAK_COLOR: Rgb565 = Rgb565::new(255, 148, 150);
+pub const MAX_PRESETS: usize = 7;
+pub const PICKER_TIMEOUT: Duration = Duration::from_secs(5);
+// Each save erases the preset sector, hourly keeps it well inside the flash's erase cycles
+pub const TIME_SAVE_INTERVAL: Duration = Duration::from_secs(3600);
+// Recorded button presses and encoder steps kept for serial dumps
+pub const MAX_INPUT_LOG: usize = 128;
+// Longest serial console line, enough for "time" and a Unix timestamp
+pub const CONSOLE_LINE_LEN: usize = 32;
+// Archived days listed on the stats screen under today's totals
+pub const STATS_DAYS: usize = 4;
+// How often the cell voltage is sampled
+pub const BATTERY_INTERVAL: Duration = Duration::from_secs(10);
+// Percentage points the reading has to move before the indicator follows it
+pub const BATTERY_HYSTERESIS: u8 = 3;
+// At or below this the battery icon turns red
+pub const BATTERY_LOW: u8 = 15;
+// How long the session may sit paused before the panel and CPU go to sleep
+pub const SLEEP_AFTER_PAUSE: Duration = Duration::from_secs(2 * 60);
+// The ILI9341 has to stay in Sleep In for 120ms before it accepts Sleep Out
+pub const PANEL_SLEEP_SETTLE: Duration = Duration::from_millis(120);
+// Backlight levels in percent
+pub const BRIGHTNESS: u8 = 100;
+pub const MIN_BRIGHTNESS: u8 = 5;
+pub const BRIGHTNESS_STEP: u8 = 5;
+pub const DIM_BRIGHTNESS: u8 = 20;
+// Time without input before the backlight dims
+pub const DIM_AFTER: Duration = Duration::from_secs(30);
+// Local hours between which the backlight is kept at NIGHT_BRIGHTNESS at most
+pub const NIGHT_START_HOUR: u8 = 22;
+pub const NIGHT_END_HOUR: u8 = 7;
+pub const NIGHT_BRIGHTNESS: u8 = 10;
+// Dimming is slow enough to notice, brightening on input is nearly immediate
+pub const BACKLIGHT_FADE: Duration = Duration::from_millis(800);
+pub const BACKLIGHT_WAKE_FADE: Duration = Duration::from_millis(80);
+// How often the duty cycle is updated while fading
+pub const BACKLIGHT_STEP: Duration = Duration::from_millis(10);
+// Longest melody whose repeats the sequencer keeps track of
+pub const MAX_MELODY_STEPS: usize = 16;
+// Buzzer loudness in percent, 50% duty is as loud as a piezo gets
+pub const VOLUME: u8 = 60;
+pub const VOLUME_STEP: u8 = 10;
+// A Flowtime break past its credit is reminded of right away and then this often
+pub const OVERTIME_REMINDER: Duration = Duration::from_secs(5 * 60);
@@ -1,12 +1,21 @@
-use embedded_graphics::{prelude::{Point, Size}, primitives::Rectangle};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::{Point, Size}, primitives::Rectangle};
 
-use crate::{animations::Animation, scenes::SceneData};
+use crate::{constants::{BREAK_COLOR, MAX_PRESETS, WORK_COLOR}, presets::PRESET_NAME_LEN, animations::Animation, battery::BatteryStatus, chess_clock::ChessFrame, clickable::ClickableElement, clock_setter::ClockFrame, clock_util::SessionState, goal::GoalProgress, history::StatsFrame, interval::IntervalFrame, scenes::{self, SceneData, UIType}, stopwatch::StopwatchFrame};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Payload {
     Time([u8; 20]),
+    Timers(TimersFrame),
     Animate(Animation),
     NewScene(SceneData),
+    Chess(ChessFrame),
+    Stopwatch(StopwatchFrame),
+    SetClock(ClockFrame),
+    Interval(IntervalFrame),
+    Presets(PresetsFrame),
+    Stats(StatsFrame),
+    // Panel off until the next payload
+    Sleep,
     Empty
 }
 
@@ -35,6 +44,100 @@ impl PanelPosition {
             },
         }
     }
+
+    // Digits are a small target for a finger, so a tap anywhere above or below the divider hits the panel
+    pub fn touch_area(&self) -> Rectangle {
+        match self {
+            PanelPosition::Top => Rectangle::new(Point::zero(), Size::new(320, 95)),
+            PanelPosition::Bottom => Rectangle::new(Point::new(0, 145), Size::new(320, 95)),
+            _ => self.get_rect()
+        }
+    }
+}
+
+// Work total on the top panel, break total on the bottom one
+#[derive(Debug, Clone, Copy)]
+pub struct TimersFrame {
+    pub work_time: [u8; 20],
+    pub break_time: [u8; 20],
+    pub state: SessionState,
+    // Total currently being corrected with the encoder
+    pub editing: Option<SessionState>,
+    // Seconds left to answer the idle prompt
+    pub idle_countdown: Option<u64>,
+    // Hours and minutes of the wall clock
+    pub time_of_day: Option<(u8, u8)>,
+    pub goal: Option<GoalProgress>,
+    pub battery: Option<BatteryStatus>,
+    pub palette: Palette
+}
+
+// Colours of the timer screen, chosen per preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub work: Rgb565,
+    pub rest: Rgb565
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            work: WORK_COLOR,
+            rest: BREAK_COLOR
+        }
+    }
+}
+
+impl TimersFrame {
+    // The work total, then the break total, each over the panel it's drawn in
+    pub fn elements() -> [UIType; 2] {
+        [
+            UIType::Clickable(ClickableElement::new(PanelPosition::Top.touch_area(), 1, 1)),
+            UIType::Clickable(ClickableElement::new(PanelPosition::Bottom.touch_area(), 0, 0))
+        ]
+    }
+
+    pub fn timer_at(point: Point) -> Option<SessionState> {
+        scenes::element_at(&Self::elements(), point)
+            .map(|index| if index == 0 { SessionState::Working } else { SessionState::Break })
+    }
+}
+
+// Preset picker: names padded with zeros, only the first `count` are used
+#[derive(Debug, Clone, Copy)]
+pub struct PresetsFrame {
+    pub names: [[u8; PRESET_NAME_LEN]; MAX_PRESETS],
+    pub count: usize,
+    pub selected: usize
+}
+
+impl PresetsFrame {
+    pub fn name(&self, index: usize) -> &str {
+        let name = &self.names[index];
+        let len = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+        str::from_utf8(&name[..len]).unwrap_or("error")
+    }
+
+    const fn row(index: usize) -> Rectangle {
+        Rectangle::new(Point::new(10, 30 + index as i32 * 30), Size::new(300, 28))
+    }
+
+    // One row per preset, drawn and tapped where its element says, the unused slots are empty
+    pub fn elements(&self) -> [UIType; MAX_PRESETS] {
+        let last = self.count.saturating_sub(1);
+        core::array::from_fn(|index| {
+            if index >= self.count {
+                return UIType::TextBox
+            }
+            let prev = if index == 0 { last } else { index - 1 };
+            let next = if index == last { 0 } else { index + 1 };
+            UIType::Clickable(ClickableElement::new(Self::row(index), prev as u8, next as u8))
+        })
+    }
+
+    pub fn row_at(&self, point: Point) -> Option<usize> {
+        scenes::element_at(&self.elements(), point)
+    }
 }
 
 pub struct Panel(pub PanelPosition, pub Payload);
@@ -51,5 +154,9 @@ impl Panel {
         let payload = Payload::Time(time);
         Panel(position, payload)
     }
+
+    pub fn from_timers(frame: TimersFrame) -> Self {
+        Panel(PanelPosition::FullScreen, Payload::Timers(frame))
+    }
 }
 
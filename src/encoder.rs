@@ -1,29 +1,233 @@
 use embassy_futures::select::select;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
 use rotary_encoder_hal::{DefaultPhase, Direction, Rotary};
-use esp_hal::gpio::Input;
 
-pub struct Encoder<'a>(Rotary<Input<'a>, Input<'a>, DefaultPhase>);
+/*
+ * Maps the time between detents to a step size.
+ * Each entry applies when detents come at most that far apart; the first match wins, anything slower is 1.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccelerationCurve {
+    pub steps: &'static [(Duration, i32)],
+    // A pause this long starts over at single steps
+    pub reset_after: Duration
+}
+
+const DEFAULT_STEPS: [(Duration, i32); 3] = [
+    (Duration::from_millis(20), 10),
+    (Duration::from_millis(40), 5),
+    (Duration::from_millis(80), 2)
+];
 
-impl<'a> Encoder<'a> {
-    pub fn new(pin_a: Input<'a>, pin_b: Input<'a>) -> Self {
-        let encoder = Rotary::new(pin_a, pin_b);
-        Encoder(encoder)
+impl Default for AccelerationCurve {
+    fn default() -> Self {
+        Self {
+            steps: &DEFAULT_STEPS,
+            reset_after: Duration::from_millis(250)
+        }
     }
+}
 
-    pub async fn wait_for_edge(&mut self) -> &mut Self {
-        let (pin_a, pin_b) = self.0.pins();
+impl AccelerationCurve {
+    // Single steps no matter how fast the encoder turns
+    pub const fn none() -> Self {
+        Self { steps: &[], reset_after: Duration::from_ticks(0) }
+    }
 
-        // Wait for either pin to change pull
-        select(pin_a.wait_for_any_edge(), pin_b.wait_for_any_edge()).await;
-        match self.0.update().unwrap() {
+    pub fn step_for(&self, interval: Duration) -> i32 {
+        self.steps
+            .iter()
+            .find(|(limit, _)| interval <= *limit)
+            .map_or(1, |(_, step)| *step)
+    }
+}
+
+/*
+ * Turns detents into signed steps that grow with rotation speed.
+ * Takes the detent's Instant so it can be driven with synthetic timestamps.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Accelerator {
+    curve: AccelerationCurve,
+    // Previous detent and its direction
+    last: Option<(Instant, Direction)>,
+    // Smoothed time between detents, so one quick flick doesn't jump straight to the top speed
+    interval: Option<Duration>
+}
+
+impl Accelerator {
+    pub const fn new(curve: AccelerationCurve) -> Self {
+        Self { curve, last: None, interval: None }
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.interval = None;
+    }
+
+    pub fn step(&mut self, direction: Direction, now: Instant) -> i32 {
+        let sign = match direction {
+            Direction::Clockwise => 1,
+            Direction::CounterClockwise => -1,
+            Direction::None => return 0
+        };
+
+        let interval = match self.last {
+            Some((at, previous)) if previous == direction => {
+                let since = now.saturating_duration_since(at);
+                (since < self.curve.reset_after).then_some(since)
+            }
+            // Turning back is always a fine adjustment
+            _ => None
+        };
+        self.last = Some((now, direction));
+        self.interval = interval.map(|since| match self.interval {
+            Some(smoothed) => (smoothed + since) / 2,
+            None => since
+        });
+
+        sign * self.interval.map_or(1, |interval| self.curve.step_for(interval))
+    }
+}
+
+pub struct Encoder<A, B> {
+    rotary: Rotary<A, B, DefaultPhase>,
+    accelerator: Accelerator
+}
+
+impl<A: Wait + InputPin, B: Wait + InputPin> Encoder<A, B> {
+    pub fn new(pin_a: A, pin_b: B) -> Self {
+        Self::with_acceleration(pin_a, pin_b, AccelerationCurve::default())
+    }
+
+    pub fn with_acceleration(pin_a: A, pin_b: B, curve: AccelerationCurve) -> Self {
+        Encoder {
+            rotary: Rotary::new(pin_a, pin_b),
+            accelerator: Accelerator::new(curve)
+        }
+    }
+
+    // For the board code that arms the pins as wake sources
+    #[cfg(target_os = "none")]
+    pub(crate) fn pins(&mut self) -> (&mut A, &mut B) {
+        self.rotary.pins()
+    }
+
+    pub async fn wait_for_edge(&mut self) -> &mut Self {
+        match self.wait_for_step().await {
             Direction::Clockwise => {
-                esp_println::println!("Moved Clockwise!")
+                log::debug!("Moved Clockwise!")
             }
             Direction::CounterClockwise => {
-                esp_println::println!("Moved Counter Clockwise!")
+                log::debug!("Moved Counter Clockwise!")
             }
             Direction::None => {}
         };
         self
     }
+
+    pub async fn wait_for_step(&mut self) -> Direction {
+        let (pin_a, pin_b) = self.rotary.pins();
+
+        // Wait for either pin to change pull
+        select(pin_a.wait_for_any_edge(), pin_b.wait_for_any_edge()).await;
+        self.rotary.update().unwrap()
+    }
+
+    // Waits until the encoder has actually moved a detent
+    pub async fn wait_for_turn(&mut self) -> Direction {
+        loop {
+            match self.wait_for_step().await {
+                Direction::None => continue,
+                direction => return direction
+            }
+        }
+    }
+
+    // Waits for a detent and scales it by how fast the encoder is turning
+    pub async fn wait_for_steps(&mut self) -> i32 {
+        let direction = self.wait_for_turn().await;
+        self.accelerator.step(direction, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CW: Direction = Direction::Clockwise;
+    const CCW: Direction = Direction::CounterClockwise;
+
+    // Feeds each detent its gap in milliseconds after the previous one and collects the steps
+    fn turn(accelerator: &mut Accelerator, start_ms: u64, detents: &[(Direction, u64)]) -> heapless::Vec<i32, 16> {
+        let mut at = start_ms;
+        detents
+            .iter()
+            .map(|&(direction, gap_ms)| {
+                at += gap_ms;
+                accelerator.step(direction, Instant::from_millis(at))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn curve_limits_are_inclusive() {
+        let curve = AccelerationCurve::default();
+        assert_eq!(curve.step_for(Duration::from_millis(0)), 10);
+        assert_eq!(curve.step_for(Duration::from_millis(20)), 10);
+        assert_eq!(curve.step_for(Duration::from_millis(21)), 5);
+        assert_eq!(curve.step_for(Duration::from_millis(40)), 5);
+        assert_eq!(curve.step_for(Duration::from_millis(41)), 2);
+        assert_eq!(curve.step_for(Duration::from_millis(80)), 2);
+        assert_eq!(curve.step_for(Duration::from_millis(81)), 1);
+        assert_eq!(AccelerationCurve::none().step_for(Duration::from_millis(0)), 1);
+    }
+
+    #[test]
+    fn steady_speeds_follow_the_curve() {
+        for (gap_ms, step) in [(10, 10), (30, 5), (60, 2), (120, 1)] {
+            let mut accelerator = Accelerator::new(AccelerationCurve::default());
+            let steps = turn(&mut accelerator, 1000, &[(CW, 0), (CW, gap_ms), (CW, gap_ms), (CW, gap_ms)]);
+            assert_eq!(steps.as_slice(), [1, step, step, step], "{} ms apart", gap_ms);
+        }
+    }
+
+    #[test]
+    fn speeding_up_is_smoothed() {
+        let mut accelerator = Accelerator::new(AccelerationCurve::default());
+        // Smoothed gaps: 100, 55, 32, 21, 15
+        let steps = turn(&mut accelerator, 0, &[(CW, 0), (CW, 100), (CW, 10), (CW, 10), (CW, 10), (CW, 10)]);
+        assert_eq!(steps.as_slice(), [1, 1, 2, 5, 5, 10]);
+    }
+
+    #[test]
+    fn a_pause_starts_over() {
+        let mut accelerator = Accelerator::new(AccelerationCurve::default());
+        let steps = turn(&mut accelerator, 0, &[(CW, 0), (CW, 10), (CW, 250), (CW, 60), (CW, 249)]);
+        // The gap after the pause isn't averaged with the fast turning before it
+        assert_eq!(steps.as_slice(), [1, 10, 1, 2, 1]);
+    }
+
+    #[test]
+    fn turning_back_starts_over() {
+        let mut accelerator = Accelerator::new(AccelerationCurve::default());
+        let steps = turn(&mut accelerator, 0, &[(CW, 0), (CW, 10), (CW, 10), (CCW, 10), (CCW, 30), (CW, 10)]);
+        assert_eq!(steps.as_slice(), [1, 10, 10, -1, -5, 1]);
+    }
+
+    #[test]
+    fn no_movement_and_reset() {
+        let mut accelerator = Accelerator::new(AccelerationCurve::default());
+        let steps = turn(&mut accelerator, 0, &[(CW, 0), (CW, 10), (Direction::None, 5), (CW, 5)]);
+        assert_eq!(steps.as_slice(), [1, 10, 0, 10]);
+
+        accelerator.reset();
+        assert_eq!(turn(&mut accelerator, 100, &[(CW, 0)]).as_slice(), [1]);
+
+        let mut plain = Accelerator::new(AccelerationCurve::none());
+        let steps = turn(&mut plain, 0, &[(CCW, 0), (CCW, 1), (CCW, 1)]);
+        assert_eq!(steps.as_slice(), [-1, -1, -1]);
+    }
 }
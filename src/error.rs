@@ -9,7 +9,10 @@ pub enum Error {
     TaskSpawn(#[error(not(source))] embassy_executor::SpawnError),
 
     #[display("Error setting state")]
-    SetStateError
+    SetStateError,
+
+    #[display("The board has no button to drive the timers with")]
+    NoButton
 }
 
 impl From<Infallible> for Error {
@@ -0,0 +1,159 @@
+use embassy_time::Duration;
+
+use crate::time_util::{format_duration_with, rounded_up, DisplayPrecision};
+
+const ZERO: Duration = Duration::from_ticks(0);
+
+// Minutes of work needed to earn `rest` minutes of break, 5:1 by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowRatio {
+    pub work: u16,
+    pub rest: u16
+}
+
+impl Default for FlowRatio {
+    fn default() -> Self {
+        Self { work: 5, rest: 1 }
+    }
+}
+
+impl FlowRatio {
+    // A zero work side would divide by zero, so it earns nothing instead
+    pub fn earned(&self, work: Duration) -> Duration {
+        if self.work == 0 {
+            return ZERO
+        }
+        let ticks = u128::from(work.as_ticks()) * u128::from(self.rest) / u128::from(self.work);
+        Duration::from_ticks(ticks.min(u128::from(u64::MAX)) as u64)
+    }
+
+    pub fn credit(&self, work: Duration, taken: Duration) -> BreakCredit {
+        BreakCredit::new(self.earned(work), taken)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakCredit {
+    Remaining(Duration),
+    // Break taken beyond what was earned
+    Overdrawn(Duration)
+}
+
+impl BreakCredit {
+    pub fn new(earned: Duration, taken: Duration) -> Self {
+        match earned.checked_sub(taken) {
+            Some(remaining) => Self::Remaining(remaining),
+            None => Self::Overdrawn(taken - earned)
+        }
+    }
+
+    /*
+     * Counts down like a timer while credit is left, so 0.4s shows as the 1s still being used,
+     * and counts up with a leading '-' once overdrawn.
+     */
+    pub fn format(&self, precision: DisplayPrecision) -> [u8; 20] {
+        match self {
+            Self::Remaining(remaining) => format_duration_with(rounded_up(*remaining, precision), precision),
+            Self::Overdrawn(overdrawn) => {
+                let digits = format_duration_with(*overdrawn, precision);
+                let mut text = [b' '; 20];
+                text[0] = b'-';
+                text[1..].copy_from_slice(&digits[..19]);
+                text
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::Duration;
+
+    use super::*;
+
+    const RATIOS: [FlowRatio; 6] = [
+        FlowRatio { work: 5, rest: 1 },
+        FlowRatio { work: 1, rest: 1 },
+        FlowRatio { work: 3, rest: 2 },
+        FlowRatio { work: 1, rest: 3 },
+        FlowRatio { work: 7, rest: 0 },
+        FlowRatio { work: u16::MAX, rest: u16::MAX }
+    ];
+
+    fn shown(credit: BreakCredit, precision: DisplayPrecision) -> std::string::String {
+        std::string::String::from_utf8(credit.format(precision).to_vec()).unwrap().trim_end().to_owned()
+    }
+
+    // Every second of the first half hour of work against every second of the first ten minutes of break
+    #[test]
+    fn every_second_of_work_and_break() {
+        for ratio in RATIOS {
+            for work in 0..=30 * 60 {
+                let work = Duration::from_secs(work);
+                let earned = ratio.earned(work);
+                // Exact in ticks, floored when the ratio does not divide evenly
+                let exact = u128::from(work.as_ticks()) * u128::from(ratio.rest);
+                assert_eq!(u128::from(earned.as_ticks()), exact / u128::from(ratio.work));
+
+                for taken in 0..=10 * 60 {
+                    let taken = Duration::from_secs(taken);
+                    match ratio.credit(work, taken) {
+                        BreakCredit::Remaining(left) => {
+                            assert!(taken <= earned);
+                            assert_eq!(left + taken, earned);
+                        }
+                        BreakCredit::Overdrawn(over) => {
+                            assert!(taken > earned);
+                            assert_eq!(earned + over, taken);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ratios() {
+        let hour = Duration::from_secs(3600);
+        assert_eq!(FlowRatio::default().earned(hour), Duration::from_secs(12 * 60));
+        assert_eq!(FlowRatio { work: 3, rest: 2 }.earned(Duration::from_secs(90)), Duration::from_secs(60));
+        assert_eq!(FlowRatio { work: 1, rest: 3 }.earned(hour), hour * 3);
+        // 1s of work at 5:1 is 0.2s of break, not rounded away
+        assert_eq!(FlowRatio::default().earned(Duration::from_secs(1)), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn degenerate_ratios_earn_nothing() {
+        let hour = Duration::from_secs(3600);
+        assert_eq!(FlowRatio { work: 0, rest: 5 }.earned(hour), Duration::from_ticks(0));
+        assert_eq!(FlowRatio { work: 5, rest: 0 }.earned(hour), Duration::from_ticks(0));
+        assert_eq!(FlowRatio { work: 0, rest: 1 }.credit(hour, hour), BreakCredit::Overdrawn(hour));
+    }
+
+    #[test]
+    fn huge_totals_saturate() {
+        let ratio = FlowRatio { work: 1, rest: u16::MAX };
+        assert_eq!(ratio.earned(Duration::MAX), Duration::MAX);
+        assert_eq!(FlowRatio { work: u16::MAX, rest: 1 }.earned(Duration::MAX), Duration::from_ticks(u64::MAX / u64::from(u16::MAX)));
+        assert_eq!(BreakCredit::new(Duration::from_ticks(0), Duration::MAX), BreakCredit::Overdrawn(Duration::MAX));
+    }
+
+    #[test]
+    fn exactly_used_up_is_remaining_zero() {
+        let ratio = FlowRatio::default();
+        let work = Duration::from_secs(25 * 60);
+        assert_eq!(ratio.credit(work, Duration::from_secs(5 * 60)), BreakCredit::Remaining(Duration::from_ticks(0)));
+        assert_eq!(ratio.credit(work, Duration::from_secs(5 * 60) + Duration::from_ticks(1)), BreakCredit::Overdrawn(Duration::from_ticks(1)));
+    }
+
+    #[test]
+    fn remaining_counts_down_and_overdrawn_counts_up() {
+        let seconds = DisplayPrecision::Seconds;
+        assert_eq!(shown(BreakCredit::Remaining(Duration::from_millis(400)), seconds), "00:00:01");
+        assert_eq!(shown(BreakCredit::Remaining(Duration::from_secs(60)), seconds), "00:01:00");
+        assert_eq!(shown(BreakCredit::Remaining(Duration::from_ticks(0)), seconds), "00:00:00");
+        assert_eq!(shown(BreakCredit::Overdrawn(Duration::from_millis(1400)), seconds), "-00:00:01");
+        assert_eq!(shown(BreakCredit::Remaining(Duration::from_millis(1210)), DisplayPrecision::Tenths), "00:00:01.3");
+        assert_eq!(shown(BreakCredit::Overdrawn(Duration::from_millis(1219)), DisplayPrecision::Hundredths), "-00:00:01.21");
+    }
+}
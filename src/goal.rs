@@ -0,0 +1,26 @@
+use embassy_time::Duration;
+
+// Work done today against the daily goal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoalProgress {
+    pub done: Duration,
+    pub goal: Duration
+}
+
+impl GoalProgress {
+    pub const fn new(done: Duration, goal: Duration) -> Self {
+        Self { done, goal }
+    }
+
+    pub fn reached(&self) -> bool {
+        self.done >= self.goal
+    }
+
+    // Capped at 1000 once the goal is reached
+    pub fn permille(&self) -> u16 {
+        if self.goal.as_ticks() == 0 {
+            return 1000
+        }
+        (self.done.as_ticks().saturating_mul(1000) / self.goal.as_ticks()).min(1000) as u16
+    }
+}
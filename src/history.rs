@@ -0,0 +1,204 @@
+use core::fmt::{self, Display};
+use embassy_time::{Duration, Instant};
+use heapless::Deque;
+
+use crate::{clock_util::SessionState, constants::{MAX_HISTORY, STATS_DAYS}, goal::GoalProgress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    Transition {
+        from: SessionState,
+        to: SessionState,
+        // State a short press would have resumed before this transition, so undo can restore it
+        paused_from: SessionState
+    },
+    // Manual edit of a timer total, in seconds actually added (negative when removed)
+    Correction {
+        timer: SessionState,
+        seconds: i64
+    },
+    // Totals of a finished day, archived at the rollover
+    DailyTotals {
+        day: u64,
+        work: Duration,
+        break_time: Duration,
+        goal: Option<Duration>
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub at: Instant,
+    pub kind: HistoryKind
+}
+
+impl HistoryEntry {
+    // How an archived day went against the goal it had
+    pub fn goal_progress(&self) -> Option<GoalProgress> {
+        match self.kind {
+            HistoryKind::DailyTotals { work, goal: Some(goal), .. } => Some(GoalProgress::new(work, goal)),
+            _ => None
+        }
+    }
+}
+
+// Most recent session events, oldest entries are dropped once MAX_HISTORY is reached
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Deque<HistoryEntry, MAX_HISTORY>
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self { entries: Deque::new() }
+    }
+
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if self.entries.is_full() {
+            self.entries.pop_front();
+        }
+        let _ = self.entries.push_back(entry);
+    }
+
+    pub fn pop_last(&mut self) -> Option<HistoryEntry> {
+        self.entries.pop_back()
+    }
+
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        self.entries.back()
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut HistoryEntry> {
+        self.entries.back_mut()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DayTotals {
+    pub day: u64,
+    pub work: Duration,
+    pub break_time: Duration,
+    pub goal: Option<Duration>
+}
+
+impl DayTotals {
+    pub fn goal_progress(&self) -> Option<GoalProgress> {
+        self.goal.map(|goal| GoalProgress::new(self.work, goal))
+    }
+}
+
+// Column names of the export, one DayTotals per line below them
+pub const EXPORT_HEADER: &str = "day,work,break,goal,goal percent";
+
+/*
+ * One CSV line of the export, durations in seconds, e.g. "20150,18000,3600,18000,100".
+ * Days without a goal leave its two columns empty.
+ */
+impl Display for DayTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},", self.day, self.work.as_secs(), self.break_time.as_secs())?;
+        match self.goal_progress() {
+            Some(progress) => write!(f, "{},{}", progress.goal.as_secs(), progress.permille() / 10),
+            None => write!(f, ",")
+        }
+    }
+}
+
+// Today's totals and the most recent archived days, newest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsFrame {
+    pub today: DayTotals,
+    pub past: [Option<DayTotals>; STATS_DAYS]
+}
+
+impl History {
+    // Archived days, oldest first
+    pub fn days(&self) -> impl DoubleEndedIterator<Item = DayTotals> + '_ {
+        self.entries.iter().filter_map(|entry| match entry.kind {
+            HistoryKind::DailyTotals { day, work, break_time, goal } => Some(DayTotals { day, work, break_time, goal }),
+            _ => None
+        })
+    }
+
+    pub fn stats(&self, today: DayTotals) -> StatsFrame {
+        let mut past = [None; STATS_DAYS];
+        for (row, day) in past.iter_mut().zip(self.days().rev()) {
+            *row = Some(day);
+        }
+        StatsFrame { today, past }
+    }
+
+    // Logs the archived days and today as CSV between markers, so the export can be cut out of the serial log
+    pub fn export(&self, today: DayTotals) {
+        log::info!("history begin");
+        log::info!("{}", EXPORT_HEADER);
+        for day in self.days().chain([today]) {
+            log::info!("{}", day);
+        }
+        log::info!("history end");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+    use embassy_time::{Duration, Instant};
+    use heapless::String;
+
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn archived(day: u64, work_hours: u64, goal: Option<Duration>) -> HistoryEntry {
+        HistoryEntry {
+            at: Instant::from_secs(day),
+            kind: HistoryKind::DailyTotals { day, work: HOUR * work_hours as u32, break_time: HOUR, goal }
+        }
+    }
+
+    fn csv(day: DayTotals) -> String<48> {
+        let mut line = String::new();
+        write!(line, "{}", day).unwrap();
+        line
+    }
+
+    #[test]
+    fn export_lines() {
+        let day = DayTotals { day: 20_150, work: HOUR * 4, break_time: HOUR, goal: Some(HOUR * 5) };
+        assert_eq!(csv(day), "20150,14400,3600,18000,80");
+        assert_eq!(csv(DayTotals { work: HOUR * 6, ..day }), "20150,21600,3600,18000,100");
+        assert_eq!(csv(DayTotals { goal: None, ..day }), "20150,14400,3600,,");
+        assert_eq!(EXPORT_HEADER.split(',').count(), csv(day).split(',').count());
+    }
+
+    #[test]
+    fn stats_list_the_newest_days_with_their_goals() {
+        let mut history = History::new();
+        for day in 1..=STATS_DAYS as u64 + 2 {
+            history.push(archived(day, day, Some(HOUR * 5)));
+            let kind = HistoryKind::Correction { timer: SessionState::Working, seconds: 60 };
+            history.push(HistoryEntry { at: Instant::from_secs(day), kind });
+        }
+        history.push(archived(STATS_DAYS as u64 + 3, 2, None));
+
+        assert_eq!(history.days().count(), STATS_DAYS + 3);
+        assert_eq!(history.days().next().map(|day| day.day), Some(1));
+
+        let today = DayTotals { day: 9, work: HOUR, break_time: HOUR, goal: Some(HOUR * 5) };
+        let stats = history.stats(today);
+        let days = stats.past.map(|day| day.map(|day| (day.day, day.goal_progress().map(|progress| progress.permille()))));
+        assert_eq!(days, [Some((7, None)), Some((6, Some(1000))), Some((5, Some(1000))), Some((4, Some(800)))]);
+    }
+}
@@ -0,0 +1,105 @@
+use embassy_time::{Duration, Instant};
+
+use crate::settings::Settings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleStatus {
+    Active,
+    // Asking whether anyone is still there, with the time left to answer
+    Prompting(Duration),
+    TimedOut
+}
+
+/*
+ * Tracks the last button or encoder input.
+ * Only works on Instants handed in by the caller, so it runs the same against a fake clock.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleWatch {
+    last_input: Instant
+}
+
+impl IdleWatch {
+    pub const fn new(now: Instant) -> Self {
+        Self { last_input: now }
+    }
+
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+
+    pub const fn last_input(&self) -> Instant {
+        self.last_input
+    }
+
+    // The prompt takes up the last IDLE_PROMPT of the timeout
+    pub fn status(&self, now: Instant, settings: &Settings) -> IdleStatus {
+        let Some(timeout) = settings.idle_timeout else {
+            return IdleStatus::Active
+        };
+        let idle = now.saturating_duration_since(self.last_input);
+        let total = timeout + settings.idle_prompt;
+
+        if idle >= total {
+            IdleStatus::TimedOut
+        } else if idle >= timeout {
+            IdleStatus::Prompting(total - idle)
+        } else {
+            IdleStatus::Active
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::{IdleStatus, IdleWatch};
+    use crate::settings::Settings;
+
+    fn settings() -> Settings {
+        Settings {
+            idle_timeout: Some(Duration::from_secs(600)),
+            idle_prompt: Duration::from_secs(30),
+            ..Settings::default()
+        }
+    }
+
+    fn at(seconds: u64) -> Instant {
+        Instant::from_secs(seconds)
+    }
+
+    #[test]
+    fn prompts_then_times_out() {
+        let watch = IdleWatch::new(at(100));
+        assert_eq!(watch.status(at(100), &settings()), IdleStatus::Active);
+        assert_eq!(watch.status(at(699), &settings()), IdleStatus::Active);
+        assert_eq!(watch.status(at(700), &settings()), IdleStatus::Prompting(Duration::from_secs(30)));
+        assert_eq!(watch.status(at(729), &settings()), IdleStatus::Prompting(Duration::from_secs(1)));
+        assert_eq!(watch.status(at(730), &settings()), IdleStatus::TimedOut);
+        assert_eq!(watch.status(at(100_000), &settings()), IdleStatus::TimedOut);
+    }
+
+    #[test]
+    fn input_starts_over() {
+        let mut watch = IdleWatch::new(at(0));
+        assert!(matches!(watch.status(at(610), &settings()), IdleStatus::Prompting(_)));
+        watch.input(at(610));
+        assert_eq!(watch.last_input(), at(610));
+        assert_eq!(watch.status(at(1_209), &settings()), IdleStatus::Active);
+        assert_eq!(watch.status(at(1_240), &settings()), IdleStatus::TimedOut);
+    }
+
+    #[test]
+    fn disabled_timeout_never_prompts() {
+        let settings = Settings { idle_timeout: None, ..settings() };
+        let watch = IdleWatch::new(at(0));
+        assert_eq!(watch.status(at(u64::MAX / 2_000_000), &settings), IdleStatus::Active);
+    }
+
+    #[test]
+    fn clock_behind_the_last_input_is_active() {
+        let watch = IdleWatch::new(at(500));
+        assert_eq!(watch.status(at(10), &settings()), IdleStatus::Active);
+    }
+}
@@ -0,0 +1,343 @@
+use core::fmt::{self, Display};
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::Point;
+use heapless::Deque;
+
+use crate::{battery::{BatteryStatus, ChargeState}, button::{ButtonId, PressDuration}, clock_util::{SessionMode, SessionState}, constants::MAX_INPUT_LOG, presets::{Preset, PRESET_SIZE}, settings::Settings, state_machine::{SerialCommand, SessionEvent}, time_util::DisplayPrecision, touch::{SwipeDirection, TouchGesture}};
+
+/*
+ * Anything that changed the session, before any mapping or menu handling.
+ * Replaying these in order from boot gives the same session.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedInput {
+    Press(ButtonId, PressDuration),
+    Scroll(i32),
+    Touch(TouchGesture),
+    // Events sent through the session API rather than a button
+    Event(SessionEvent),
+    SetMode(SessionMode),
+    SetSettings(Settings),
+    SetPrecision(DisplayPrecision),
+    AdjustTimer(SessionState, i32),
+    SetWallClock(u64),
+    OpenClockSettings,
+    OpenPresetPicker,
+    Battery(BatteryStatus),
+    // Time Instant missed while the CPU slept
+    Woke(Duration)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputRecord {
+    pub at: Instant,
+    pub input: RecordedInput
+}
+
+const EVENT_NAMES: [(SessionEvent, &str); 10] = [
+    (SessionEvent::ShortPress, "short"),
+    (SessionEvent::LongPress, "long"),
+    (SessionEvent::TimerExpired, "expired"),
+    (SessionEvent::IdleTimeout, "idle"),
+    (SessionEvent::Undo, "undo"),
+    (SessionEvent::Select, "select"),
+    (SessionEvent::Menu, "menu"),
+    (SessionEvent::SerialCommand(SerialCommand::Work), "work"),
+    (SessionEvent::SerialCommand(SerialCommand::Break), "break"),
+    (SessionEvent::SerialCommand(SerialCommand::Pause), "pause")
+];
+
+fn event_name(event: SessionEvent) -> &'static str {
+    name_of(&EVENT_NAMES, event)
+}
+
+pub(crate) fn event_named(name: &str) -> Option<SessionEvent> {
+    named(&EVENT_NAMES, name)
+}
+
+const STATE_NAMES: [(SessionState, &str); 3] = [
+    (SessionState::Working, "work"),
+    (SessionState::Break, "break"),
+    (SessionState::Paused, "pause")
+];
+
+const PRECISION_NAMES: [(DisplayPrecision, &str); 3] = [
+    (DisplayPrecision::Seconds, "seconds"),
+    (DisplayPrecision::Tenths, "tenths"),
+    (DisplayPrecision::Hundredths, "hundredths")
+];
+
+const CHARGE_NAMES: [(ChargeState, &str); 3] = [
+    (ChargeState::Discharging, "discharging"),
+    (ChargeState::Charging, "charging"),
+    (ChargeState::Full, "full")
+];
+
+fn name_of<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names
+        .iter()
+        .find(|(known, _)| *known == value)
+        .map_or("?", |(_, name)| name)
+}
+
+fn named<T: Copy>(names: &[(T, &'static str)], name: &str) -> Option<T> {
+    names
+        .iter()
+        .find(|(_, known)| *known == name)
+        .map(|(value, _)| *value)
+}
+
+// Modes and settings are written as the hex of a preset record, so they share its compatibility rules
+fn write_preset(f: &mut fmt::Formatter<'_>, preset: &Preset) -> fmt::Result {
+    for byte in preset.encode() {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+fn parse_preset(hex: &str) -> Option<Preset> {
+    if hex.len() != 2 * PRESET_SIZE {
+        return None
+    }
+    let mut bytes = [0; PRESET_SIZE];
+    for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Preset::decode(&bytes).ok()
+}
+
+/*
+ * One record per serial line, e.g. "input 1250000 press switch long", "input 1300000 scroll -2"
+ * or "input 1400000 touch tap 120 64".
+ * The timestamp is in embassy ticks since boot, and so is the time missed in "woke".
+ */
+impl Display for InputRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input {} ", self.at.as_ticks())?;
+        match self.input {
+            RecordedInput::Press(button, press) => {
+                let button = match button {
+                    ButtonId::Main => "main",
+                    ButtonId::EncoderSwitch => "switch"
+                };
+                let press = match press {
+                    PressDuration::Short => "short",
+                    PressDuration::Long => "long"
+                };
+                write!(f, "press {} {}", button, press)
+            }
+            RecordedInput::Scroll(steps) => write!(f, "scroll {}", steps),
+            RecordedInput::Touch(TouchGesture::Tap(point)) => write!(f, "touch tap {} {}", point.x, point.y),
+            RecordedInput::Touch(TouchGesture::LongPress(point)) => write!(f, "touch long {} {}", point.x, point.y),
+            RecordedInput::Touch(TouchGesture::Swipe(direction)) => {
+                let direction = match direction {
+                    SwipeDirection::Left => "left",
+                    SwipeDirection::Right => "right",
+                    SwipeDirection::Up => "up",
+                    SwipeDirection::Down => "down"
+                };
+                write!(f, "touch swipe {}", direction)
+            }
+            RecordedInput::Event(event) => write!(f, "event {}", event_name(event)),
+            RecordedInput::SetMode(mode) => {
+                write!(f, "mode ")?;
+                write_preset(f, &Preset::new("", mode))
+            }
+            RecordedInput::SetSettings(settings) => {
+                write!(f, "settings ")?;
+                write_preset(f, &Preset { settings, ..Preset::new("", SessionMode::DoubleTimer) })
+            }
+            RecordedInput::SetPrecision(precision) => write!(f, "precision {}", name_of(&PRECISION_NAMES, precision)),
+            RecordedInput::AdjustTimer(timer, minutes) => write!(f, "adjust {} {}", name_of(&STATE_NAMES, timer), minutes),
+            RecordedInput::SetWallClock(utc_seconds) => write!(f, "time {}", utc_seconds),
+            RecordedInput::OpenClockSettings => write!(f, "open clock"),
+            RecordedInput::OpenPresetPicker => write!(f, "open presets"),
+            RecordedInput::Battery(status) => write!(f, "battery {} {}", status.percent, name_of(&CHARGE_NAMES, status.charge)),
+            RecordedInput::Woke(missed) => write!(f, "woke {}", missed.as_ticks())
+        }
+    }
+}
+
+impl InputRecord {
+    // Reads back a line written by Display, for feeding a dump into a replay
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        if words.next()? != "input" {
+            return None
+        }
+        let at = Instant::from_ticks(words.next()?.parse().ok()?);
+        let input = match words.next()? {
+            "press" => {
+                let button = match words.next()? {
+                    "main" => ButtonId::Main,
+                    "switch" => ButtonId::EncoderSwitch,
+                    _ => return None
+                };
+                let press = match words.next()? {
+                    "short" => PressDuration::Short,
+                    "long" => PressDuration::Long,
+                    _ => return None
+                };
+                RecordedInput::Press(button, press)
+            }
+            "scroll" => RecordedInput::Scroll(words.next()?.parse().ok()?),
+            "touch" => {
+                let gesture = words.next()?;
+                if gesture == "swipe" {
+                    let direction = match words.next()? {
+                        "left" => SwipeDirection::Left,
+                        "right" => SwipeDirection::Right,
+                        "up" => SwipeDirection::Up,
+                        "down" => SwipeDirection::Down,
+                        _ => return None
+                    };
+                    RecordedInput::Touch(TouchGesture::Swipe(direction))
+                } else {
+                    let point = Point::new(words.next()?.parse().ok()?, words.next()?.parse().ok()?);
+                    match gesture {
+                        "tap" => RecordedInput::Touch(TouchGesture::Tap(point)),
+                        "long" => RecordedInput::Touch(TouchGesture::LongPress(point)),
+                        _ => return None
+                    }
+                }
+            }
+            "event" => RecordedInput::Event(event_named(words.next()?)?),
+            "mode" => RecordedInput::SetMode(parse_preset(words.next()?)?.mode),
+            "settings" => RecordedInput::SetSettings(parse_preset(words.next()?)?.settings),
+            "precision" => RecordedInput::SetPrecision(named(&PRECISION_NAMES, words.next()?)?),
+            "adjust" => RecordedInput::AdjustTimer(named(&STATE_NAMES, words.next()?)?, words.next()?.parse().ok()?),
+            "time" => RecordedInput::SetWallClock(words.next()?.parse().ok()?),
+            "open" => match words.next()? {
+                "clock" => RecordedInput::OpenClockSettings,
+                "presets" => RecordedInput::OpenPresetPicker,
+                _ => return None
+            },
+            "battery" => RecordedInput::Battery(BatteryStatus {
+                percent: words.next()?.parse().ok()?,
+                charge: named(&CHARGE_NAMES, words.next()?)?
+            }),
+            "woke" => RecordedInput::Woke(Duration::from_ticks(words.next()?.parse().ok()?)),
+            _ => return None
+        };
+        if words.next().is_some() {
+            return None
+        }
+        Some(Self { at, input })
+    }
+}
+
+// Most recent inputs, oldest records are dropped once MAX_INPUT_LOG is reached
+#[derive(Debug, Default)]
+pub struct InputLog {
+    records: Deque<InputRecord, MAX_INPUT_LOG>,
+    // A log that lost its start no longer replays the same session
+    dropped: usize
+}
+
+impl InputLog {
+    pub const fn new() -> Self {
+        Self { records: Deque::new(), dropped: 0 }
+    }
+
+    pub fn push(&mut self, record: InputRecord) {
+        if self.records.is_full() {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        let _ = self.records.push_back(record);
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &InputRecord> {
+        self.records.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub const fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // Prints every record between markers, so a dump can be cut out of the serial log and replayed
+    pub fn dump(&self) {
+        log::info!("inputs begin ({} records, {} dropped)", self.records.len(), self.dropped);
+        for record in &self.records {
+            log::info!("{}", record);
+        }
+        log::info!("inputs end");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{format, string::ToString};
+
+    use super::*;
+    use crate::{chess_clock::ChessConfig, interval::IntervalProgram};
+
+    #[test]
+    fn every_input_reads_back() {
+        let settings = Settings { volume: 0, daily_goal: None, night: None, ..Settings::default() };
+        let inputs = [
+            RecordedInput::Press(ButtonId::Main, PressDuration::Short),
+            RecordedInput::Press(ButtonId::EncoderSwitch, PressDuration::Long),
+            RecordedInput::Scroll(-3),
+            RecordedInput::Touch(TouchGesture::Tap(Point::new(120, 64))),
+            RecordedInput::Touch(TouchGesture::LongPress(Point::new(0, 239))),
+            RecordedInput::Touch(TouchGesture::Swipe(SwipeDirection::Up)),
+            RecordedInput::Event(SessionEvent::Undo),
+            RecordedInput::SetMode(SessionMode::ChessClock(ChessConfig::default())),
+            RecordedInput::SetMode(SessionMode::Interval(IntervalProgram::default())),
+            RecordedInput::SetSettings(settings),
+            RecordedInput::SetPrecision(DisplayPrecision::Hundredths),
+            RecordedInput::AdjustTimer(SessionState::Break, -5),
+            RecordedInput::SetWallClock(1_760_000_000),
+            RecordedInput::OpenClockSettings,
+            RecordedInput::OpenPresetPicker,
+            RecordedInput::Battery(BatteryStatus { percent: 100, charge: ChargeState::Full }),
+            RecordedInput::Woke(Duration::from_millis(3_600_123))
+        ];
+        for (index, input) in inputs.into_iter().enumerate() {
+            let record = InputRecord { at: Instant::from_millis(1000 * index as u64 + 7), input };
+            let line = record.to_string();
+            assert_eq!(InputRecord::parse(&line), Some(record), "{}", line);
+        }
+    }
+
+    #[test]
+    fn lines_that_are_not_records() {
+        for line in [
+            "",
+            "inputs begin (3 records, 0 dropped)",
+            "input",
+            "input 12",
+            "input -5 scroll 1",
+            "input 12 press main",
+            "input 12 press main short now",
+            "input 12 touch tap 3",
+            "input 12 adjust lunch 5",
+            "input 12 open door",
+            "input 12 mode 5050",
+            "input 12 battery 80 leaking"
+        ] {
+            assert_eq!(InputRecord::parse(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn full_log_drops_the_oldest() {
+        let mut log = InputLog::new();
+        for step in 0..MAX_INPUT_LOG as i32 + 2 {
+            log.push(InputRecord { at: Instant::from_ticks(step as u64), input: RecordedInput::Scroll(step) });
+        }
+        assert_eq!(log.len(), MAX_INPUT_LOG);
+        assert_eq!(log.dropped(), 2);
+        assert_eq!(log.iter().next().map(|record| record.input), Some(RecordedInput::Scroll(2)));
+        assert_eq!(format!("{}", log.iter().next_back().unwrap()), format!("input {} scroll {}", MAX_INPUT_LOG + 1, MAX_INPUT_LOG + 1));
+    }
+}
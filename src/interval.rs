@@ -0,0 +1,598 @@
+use core::fmt::{self, Write};
+use embassy_time::{Duration, Instant};
+use heapless::String;
+
+use crate::{button::PressDuration, clock_util::SessionState, constants::MAX_ROUND_SEGMENTS, draw_panels::{Panel, PanelPosition, Payload}, time_util::{format_duration, rounded_up, DisplayPrecision}};
+
+const ZERO: Duration = Duration::from_ticks(0);
+// Encoder step when editing a segment length
+const EDIT_STEP: Duration = Duration::from_secs(5);
+const MAX_ROUNDS: u8 = 99;
+pub const SEGMENT_NAME_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Work,
+    Rest
+}
+
+impl SegmentKind {
+    pub const fn divider_state(self) -> SessionState {
+        match self {
+            Self::Work => SessionState::Working,
+            Self::Rest => SessionState::Break
+        }
+    }
+}
+
+// Zero padded so programs can be stored and edited, longer names are cut short
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentName([u8; SEGMENT_NAME_LEN]);
+
+impl SegmentName {
+    pub const fn new(name: &str) -> Self {
+        let bytes = name.as_bytes();
+        let mut padded = [0; SEGMENT_NAME_LEN];
+        let mut index = 0;
+        while index < bytes.len() && index < SEGMENT_NAME_LEN {
+            padded[index] = bytes[index];
+            index += 1;
+        }
+        Self(padded)
+    }
+
+    pub const fn from_bytes(bytes: [u8; SEGMENT_NAME_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn as_bytes(&self) -> &[u8; SEGMENT_NAME_LEN] {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|byte| *byte == 0).unwrap_or(SEGMENT_NAME_LEN);
+        str::from_utf8(&self.0[..len]).unwrap_or("error")
+    }
+}
+
+impl fmt::Display for SegmentName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub name: SegmentName,
+    pub duration: Duration,
+    pub kind: SegmentKind
+}
+
+impl Segment {
+    pub const fn new(name: &str, seconds: u64, kind: SegmentKind) -> Self {
+        Self {
+            name: SegmentName::new(name),
+            duration: Duration::from_secs(seconds),
+            kind
+        }
+    }
+}
+
+// A segment of the program together with the round it belongs to (None for warm-up and cool-down)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramStep {
+    pub segment: Segment,
+    pub round: Option<u8>
+}
+
+/*
+ * Warm-up, `rounds` repetitions of the round segments, then cool-down.
+ * Fixed size and Copy so a program can be a const or travel inside SessionMode.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntervalProgram {
+    pub warm_up: Option<Segment>,
+    segments: [Segment; MAX_ROUND_SEGMENTS],
+    segment_count: usize,
+    pub rounds: u8,
+    pub cool_down: Option<Segment>
+}
+
+impl Default for IntervalProgram {
+    // 8 rounds of 40s work and 20s rest between 5 minute warm-up and cool-down
+    fn default() -> Self {
+        Self::new(&[
+            Segment::new("work", 40, SegmentKind::Work),
+            Segment::new("rest", 20, SegmentKind::Rest)
+        ], 8)
+            .with_warm_up(Segment::new("warm-up", 5 * 60, SegmentKind::Rest))
+            .with_cool_down(Segment::new("cool-down", 5 * 60, SegmentKind::Rest))
+    }
+}
+
+impl IntervalProgram {
+    // Segments beyond MAX_ROUND_SEGMENTS are ignored
+    pub const fn new(round: &[Segment], rounds: u8) -> Self {
+        let mut segments = [Segment::new("", 0, SegmentKind::Rest); MAX_ROUND_SEGMENTS];
+        let mut count = 0;
+        while count < round.len() && count < MAX_ROUND_SEGMENTS {
+            segments[count] = round[count];
+            count += 1;
+        }
+
+        Self {
+            warm_up: None,
+            segments,
+            segment_count: count,
+            rounds,
+            cool_down: None
+        }
+    }
+
+    pub const fn with_warm_up(mut self, segment: Segment) -> Self {
+        self.warm_up = Some(segment);
+        self
+    }
+
+    pub const fn with_cool_down(mut self, segment: Segment) -> Self {
+        self.cool_down = Some(segment);
+        self
+    }
+
+    pub fn round(&self) -> &[Segment] {
+        &self.segments[..self.segment_count]
+    }
+
+    pub fn round_mut(&mut self) -> &mut [Segment] {
+        &mut self.segments[..self.segment_count]
+    }
+
+    pub fn step_count(&self) -> usize {
+        usize::from(self.warm_up.is_some())
+            + usize::from(self.rounds) * self.segment_count
+            + usize::from(self.cool_down.is_some())
+    }
+
+    pub fn step(&self, index: usize) -> Option<ProgramStep> {
+        let mut index = index;
+        if let Some(segment) = self.warm_up {
+            if index == 0 {
+                return Some(ProgramStep { segment, round: None })
+            }
+            index -= 1;
+        }
+
+        let in_rounds = usize::from(self.rounds) * self.segment_count;
+        if index < in_rounds {
+            return Some(ProgramStep {
+                segment: self.segments[index % self.segment_count],
+                round: Some((index / self.segment_count) as u8 + 1)
+            })
+        }
+
+        match index - in_rounds {
+            0 => self.cool_down.map(|segment| ProgramStep { segment, round: None }),
+            _ => None
+        }
+    }
+
+    // Sum of every step from `index` on
+    pub fn duration_from(&self, index: usize) -> Duration {
+        (index..self.step_count())
+            .filter_map(|step| self.step(step))
+            .fold(ZERO, |total, step| total + step.segment.duration)
+    }
+}
+
+// What the encoder changes while the program is being edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramField {
+    WarmUp,
+    Segment(usize),
+    Rounds,
+    CoolDown
+}
+
+impl ProgramField {
+    fn first(program: &IntervalProgram) -> Self {
+        if program.warm_up.is_some() {
+            Self::WarmUp
+        } else {
+            Self::next_after_warm_up(program)
+        }
+    }
+
+    fn next_after_warm_up(program: &IntervalProgram) -> Self {
+        if program.segment_count > 0 {
+            Self::Segment(0)
+        } else {
+            Self::Rounds
+        }
+    }
+
+    // None once every field was visited
+    fn next(self, program: &IntervalProgram) -> Option<Self> {
+        match self {
+            Self::WarmUp => Some(Self::next_after_warm_up(program)),
+            Self::Segment(index) if index + 1 < program.segment_count => Some(Self::Segment(index + 1)),
+            Self::Segment(_) => Some(Self::Rounds),
+            Self::Rounds if program.cool_down.is_some() => Some(Self::CoolDown),
+            Self::Rounds | Self::CoolDown => None
+        }
+    }
+}
+
+/*
+ * Runs an IntervalProgram.
+ * Short press starts or pauses, long press skips the segment while running and restarts when paused.
+ * Before the first start the encoder edits the program, with short presses moving between fields.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalTimer {
+    program: IntervalProgram,
+    step: usize,
+    step_elapsed: Duration,
+    last_update: Instant,
+    running: bool,
+    started: bool,
+    work_done: Duration,
+    rest_done: Duration,
+    // Kind of the segment that ended since the last take_alert
+    alert: Option<SegmentKind>,
+    editing: Option<ProgramField>
+}
+
+impl IntervalTimer {
+    pub fn new(program: IntervalProgram, now: Instant) -> Self {
+        Self {
+            program,
+            step: 0,
+            step_elapsed: ZERO,
+            last_update: now,
+            running: false,
+            started: false,
+            work_done: ZERO,
+            rest_done: ZERO,
+            alert: None,
+            editing: None
+        }
+    }
+
+    pub const fn program(&self) -> &IntervalProgram {
+        &self.program
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.program.step_count()
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        let mut delta = if self.running {
+            now.saturating_duration_since(self.last_update)
+        } else {
+            ZERO
+        };
+        self.last_update = now;
+
+        // A long gap can span several segments, each one gets its share
+        while delta > ZERO {
+            let Some(current) = self.program.step(self.step) else {
+                break
+            };
+            let left = current.segment.duration.checked_sub(self.step_elapsed).unwrap_or(ZERO);
+            let used = delta.min(left);
+            self.step_elapsed += used;
+            delta -= used;
+            match current.segment.kind {
+                SegmentKind::Work => self.work_done += used,
+                SegmentKind::Rest => self.rest_done += used
+            }
+            if self.step_elapsed >= current.segment.duration {
+                self.advance();
+            }
+        }
+        if self.is_finished() {
+            self.running = false;
+        }
+    }
+
+    fn advance(&mut self) {
+        self.alert = self.current_kind();
+        self.step += 1;
+        self.step_elapsed = ZERO;
+    }
+
+    pub fn take_alert(&mut self) -> Option<SegmentKind> {
+        self.alert.take()
+    }
+
+    // None once the program has finished
+    pub fn current_kind(&self) -> Option<SegmentKind> {
+        self.program.step(self.step).map(|step| step.segment.kind)
+    }
+
+    pub fn press(&mut self, press: PressDuration, now: Instant) {
+        self.update(now);
+        if let Some(field) = self.editing {
+            self.editing = match press {
+                PressDuration::Short => field.next(&self.program),
+                PressDuration::Long => None
+            };
+            return
+        }
+
+        match (press, self.running) {
+            (PressDuration::Short, _) if self.is_finished() => *self = Self::new(self.program, now),
+            (PressDuration::Short, running) => {
+                self.running = !running;
+                self.started = true;
+            }
+            (PressDuration::Long, true) => {
+                self.advance();
+                self.running = !self.is_finished();
+            }
+            (PressDuration::Long, false) => *self = Self::new(self.program, now)
+        }
+    }
+
+    // Only a program that hasn't started yet can be edited
+    pub fn scroll_by(&mut self, steps: i32) {
+        if self.started {
+            return
+        }
+        let field = *self.editing.get_or_insert(ProgramField::first(&self.program));
+        let change = |duration: Duration| {
+            let seconds = duration.as_secs() as i64 + i64::from(steps) * EDIT_STEP.as_secs() as i64;
+            Duration::from_secs(seconds.max(EDIT_STEP.as_secs() as i64) as u64)
+        };
+
+        match field {
+            ProgramField::WarmUp => {
+                if let Some(segment) = &mut self.program.warm_up {
+                    segment.duration = change(segment.duration);
+                }
+            }
+            ProgramField::Segment(index) => {
+                if let Some(segment) = self.program.round_mut().get_mut(index) {
+                    segment.duration = change(segment.duration);
+                }
+            }
+            ProgramField::Rounds => {
+                self.program.rounds = (i32::from(self.program.rounds) + steps).clamp(1, i32::from(MAX_ROUNDS)) as u8;
+            }
+            ProgramField::CoolDown => {
+                if let Some(segment) = &mut self.program.cool_down {
+                    segment.duration = change(segment.duration);
+                }
+            }
+        }
+    }
+
+    pub fn render(&mut self, now: Instant) -> (Panel, Duration) {
+        self.update(now);
+        let frame = match (self.editing, self.program.step(self.step)) {
+            (Some(field), _) => self.edit_frame(field),
+            (None, Some(current)) => {
+                let left = current.segment.duration.checked_sub(self.step_elapsed).unwrap_or(ZERO);
+                let total_left = self.program.duration_from(self.step + 1) + left;
+                IntervalFrame {
+                    top: format_duration(rounded_up(left, DisplayPrecision::Seconds)),
+                    bottom: format_duration(rounded_up(total_left, DisplayPrecision::Seconds)),
+                    kind: current.segment.kind,
+                    view: IntervalView::Running {
+                        name: current.segment.name,
+                        round: current.round,
+                        rounds: self.program.rounds
+                    },
+                    running: self.running
+                }
+            }
+            // The summary shows how the time was spent
+            (None, None) => IntervalFrame {
+                top: format_duration(self.work_done),
+                bottom: format_duration(self.rest_done),
+                kind: SegmentKind::Work,
+                view: IntervalView::Summary { rounds: self.program.rounds },
+                running: false
+            }
+        };
+
+        // Wake up exactly when the displayed second of the segment changes
+        let sleep_dur = if self.running {
+            let tick = Duration::from_secs(1).as_ticks();
+            let left = self.program.step(self.step)
+                .map_or(ZERO, |current| current.segment.duration.checked_sub(self.step_elapsed).unwrap_or(ZERO));
+            let until_change = left.as_ticks() % tick;
+            Duration::from_ticks(if until_change == 0 { tick } else { until_change })
+        } else {
+            Duration::from_secs(1)
+        };
+
+        (Panel(PanelPosition::FullScreen, Payload::Interval(frame)), sleep_dur)
+    }
+
+    fn edit_frame(&self, field: ProgramField) -> IntervalFrame {
+        let segment = match field {
+            ProgramField::WarmUp => self.program.warm_up,
+            ProgramField::Segment(index) => self.program.round().get(index).copied(),
+            ProgramField::Rounds => None,
+            ProgramField::CoolDown => self.program.cool_down
+        };
+        let top = match segment {
+            Some(segment) => format_duration(segment.duration),
+            None => {
+                let mut top = [b' '; 20];
+                let mut text: String<20> = String::new();
+                let _ = write!(text, "{}", self.program.rounds);
+                top[..text.len()].copy_from_slice(text.as_bytes());
+                top
+            }
+        };
+
+        IntervalFrame {
+            top,
+            bottom: format_duration(self.program.duration_from(0)),
+            kind: segment.map_or(SegmentKind::Work, |segment| segment.kind),
+            view: IntervalView::Editing { name: segment.map_or(SegmentName::new("rounds"), |segment| segment.name) },
+            running: false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IntervalView {
+    Running {
+        name: SegmentName,
+        round: Option<u8>,
+        rounds: u8
+    },
+    Summary {
+        rounds: u8
+    },
+    Editing {
+        name: SegmentName
+    }
+}
+
+// Segment countdown on top, rest of the program below
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalFrame {
+    pub top: [u8; 20],
+    pub bottom: [u8; 20],
+    pub kind: SegmentKind,
+    pub view: IntervalView,
+    pub running: bool
+}
+
+impl IntervalFrame {
+    pub fn label(&self) -> String<16> {
+        let mut label = String::new();
+        let _ = match self.view {
+            IntervalView::Running { name, round: Some(round), rounds } => write!(label, "{}/{} {}", round, rounds, name),
+            IntervalView::Running { name, round: None, .. } => write!(label, "{}", name),
+            IntervalView::Summary { rounds } => write!(label, "done {} rounds", rounds),
+            IntervalView::Editing { name } => write!(label, "set {}", name)
+        };
+        label
+    }
+
+    pub const fn divider_state(&self) -> SessionState {
+        match self.view {
+            IntervalView::Running { .. } if self.running => self.kind.divider_state(),
+            _ => SessionState::Paused
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::*;
+
+    fn program() -> IntervalProgram {
+        IntervalProgram::new(&[
+            Segment::new("sprint", 30, SegmentKind::Work),
+            Segment::new("jog", 60, SegmentKind::Rest),
+            Segment::new("walk", 30, SegmentKind::Rest)
+        ], 2)
+            .with_warm_up(Segment::new("warm-up", 120, SegmentKind::Rest))
+    }
+
+    fn label(timer: &mut IntervalTimer, now: Instant) -> String<16> {
+        let (panel, _) = timer.render(now);
+        let Payload::Interval(frame) = panel.1 else {
+            panic!("not an interval frame")
+        };
+        frame.label()
+    }
+
+    fn started(now: Instant) -> IntervalTimer {
+        let mut timer = IntervalTimer::new(program(), now);
+        timer.press(PressDuration::Short, now);
+        timer
+    }
+
+    #[test]
+    fn steps_run_warm_up_then_the_rounds() {
+        let program = program();
+        assert_eq!(program.step_count(), 7);
+        let steps: heapless::Vec<(SegmentName, Option<u8>), 8> = (0..program.step_count())
+            .map(|index| program.step(index).unwrap())
+            .map(|step| (step.segment.name, step.round))
+            .collect();
+        let expected = [
+            ("warm-up", None),
+            ("sprint", Some(1)), ("jog", Some(1)), ("walk", Some(1)),
+            ("sprint", Some(2)), ("jog", Some(2)), ("walk", Some(2))
+        ].map(|(name, round)| (SegmentName::new(name), round));
+        assert_eq!(steps.as_slice(), expected);
+        assert_eq!(program.step(7), None);
+    }
+
+    #[test]
+    fn each_segment_change_alerts_with_the_kind_that_ended() {
+        let start = Instant::from_secs(10);
+        let mut timer = started(start);
+        assert_eq!(label(&mut timer, start), "warm-up");
+        assert_eq!(timer.take_alert(), None);
+
+        let sprinting = start + Duration::from_secs(121);
+        assert_eq!(label(&mut timer, sprinting), "1/2 sprint");
+        assert_eq!(timer.take_alert(), Some(SegmentKind::Rest));
+        assert_eq!(timer.take_alert(), None);
+
+        let jogging = sprinting + Duration::from_secs(30);
+        assert_eq!(label(&mut timer, jogging), "1/2 jog");
+        assert_eq!(timer.take_alert(), Some(SegmentKind::Work));
+
+        // Rest after rest still alerts, with the rest tune
+        let walking = jogging + Duration::from_secs(60);
+        assert_eq!(label(&mut timer, walking), "1/2 walk");
+        assert_eq!(timer.take_alert(), Some(SegmentKind::Rest));
+    }
+
+    #[test]
+    fn a_long_gap_is_split_over_the_segments() {
+        let start = Instant::from_secs(0);
+        let mut timer = started(start);
+        let end = start + Duration::from_secs(120 + 2 * 120 + 5);
+        assert_eq!(label(&mut timer, end), "done 2 rounds");
+        assert!(timer.is_finished());
+        assert_eq!(timer.work_done, Duration::from_secs(60));
+        assert_eq!(timer.rest_done, Duration::from_secs(120 + 2 * 90));
+        assert_eq!(timer.take_alert(), Some(SegmentKind::Rest));
+    }
+
+    #[test]
+    fn countdown_shows_the_second_in_use() {
+        let start = Instant::from_secs(0);
+        let mut timer = started(start);
+        let (panel, sleep) = timer.render(start + Duration::from_millis(119_600));
+        let Payload::Interval(frame) = panel.1 else {
+            panic!("not an interval frame")
+        };
+        assert_eq!(&frame.top[..8], b"00:00:01");
+        assert_eq!(sleep, Duration::from_millis(400));
+    }
+
+    #[test]
+    fn long_press_skips_and_pausing_stops_the_clock() {
+        let start = Instant::from_secs(0);
+        let mut timer = started(start);
+        timer.press(PressDuration::Long, start + Duration::from_secs(5));
+        assert_eq!(timer.take_alert(), Some(SegmentKind::Rest));
+        assert_eq!(label(&mut timer, start + Duration::from_secs(5)), "1/2 sprint");
+
+        timer.press(PressDuration::Short, start + Duration::from_secs(10));
+        assert_eq!(label(&mut timer, start + Duration::from_secs(600)), "1/2 sprint");
+        assert_eq!(timer.work_done, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn names_are_cut_to_fit() {
+        assert_eq!(SegmentName::new("cool-down").as_str(), "cool-dow");
+        assert_eq!(SegmentName::new("").as_str(), "");
+        assert_eq!(SegmentName::from_bytes(*b"work\0\0\0\0"), SegmentName::new("work"));
+    }
+}
@@ -1,10 +1,16 @@
-#![no_std]
-#![feature(impl_trait_in_assoc_type)]
+/*
+ * Everything that touches the chip is only built for it. The rest also builds on the host,
+ * where `cargo test --lib --target x86_64-unknown-linux-gnu` runs the tests.
+ */
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(target_os = "none", feature(impl_trait_in_assoc_type))]
 pub mod clock_util;
 pub mod encoder;
 pub mod draw_panels;
+#[cfg(target_os = "none")]
 pub mod tft;
 pub mod button;
+#[cfg(target_os = "none")]
 pub mod render_display;
 pub mod error;
 //pub mod double_timer;
@@ -15,3 +21,32 @@ pub mod scenes;
 pub mod clickable;
 pub mod animations;
 pub mod raw_sprites;
+pub mod chess_clock;
+pub mod stopwatch;
+pub mod state_machine;
+pub mod history;
+pub mod settings;
+pub mod timer_edit;
+pub mod idle;
+pub mod wall_clock;
+pub mod clock_setter;
+pub mod goal;
+pub mod flowtime;
+pub mod interval;
+pub mod presets;
+#[cfg(target_os = "none")]
+pub mod board;
+pub mod input_log;
+pub mod console;
+pub mod touch;
+pub mod battery;
+pub mod power;
+pub mod backlight;
+pub mod melody;
+pub mod buzzer;
+#[cfg(target_os = "none")]
+pub mod pwm;
+#[cfg(target_os = "none")]
+pub mod session;
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,200 @@
+use embassy_time::Duration;
+
+use crate::constants::MAX_MELODY_STEPS;
+
+// Note frequencies in Hz, equal temperament with A4 at 440
+pub const C5: u16 = 523;
+pub const D5: u16 = 587;
+pub const E5: u16 = 659;
+pub const G5: u16 = 784;
+pub const A5: u16 = 880;
+pub const C6: u16 = 1047;
+pub const E6: u16 = 1319;
+pub const G6: u16 = 1568;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Note { frequency: u16, millis: u16 },
+    Rest { millis: u16 },
+    // Goes back to step `to` and plays from there `times` more times
+    Repeat { to: u8, times: u8 }
+}
+
+pub const fn note(frequency: u16, millis: u16) -> Step {
+    Step::Note { frequency, millis }
+}
+
+pub const fn rest(millis: u16) -> Step {
+    Step::Rest { millis }
+}
+
+pub const fn repeat(to: u8, times: u8) -> Step {
+    Step::Repeat { to, times }
+}
+
+pub type Melody = &'static [Step];
+
+// Falling, time to rest
+const WORK_DONE: [Step; 3] = [note(G5, 150), note(E5, 150), note(C5, 300)];
+// Rising, back to work
+const BREAK_DONE: [Step; 4] = [note(C5, 120), note(E5, 120), note(G5, 120), note(C6, 250)];
+// Three short beeps, easy to tell apart from the end of a segment
+const OVERTIME: [Step; 3] = [note(A5, 80), rest(80), repeat(0, 2)];
+const GOAL_REACHED: [Step; 7] = [
+    note(C6, 90),
+    rest(40),
+    repeat(0, 1),
+    note(G5, 120),
+    note(C6, 120),
+    note(E6, 160),
+    note(G6, 400)
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tune {
+    WorkDone,
+    BreakDone,
+    Overtime,
+    GoalReached
+}
+
+impl Tune {
+    pub const fn melody(self) -> Melody {
+        match self {
+            Self::WorkDone => &WORK_DONE,
+            Self::BreakDone => &BREAK_DONE,
+            Self::Overtime => &OVERTIME,
+            Self::GoalReached => &GOAL_REACHED
+        }
+    }
+}
+
+// What the buzzer is told to do next, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneCommand {
+    Tone { frequency: u16, volume: u8, duration: Duration },
+    Silence(Duration)
+}
+
+/*
+ * Walks a melody with its repeats expanded and yields one command per note or rest.
+ * It's a plain iterator, so a tune can be collected on the host and checked command by command.
+ */
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    melody: Melody,
+    position: usize,
+    volume: u8,
+    // Passes still to go for each repeat step that has been reached
+    repeats_left: [Option<u8>; MAX_MELODY_STEPS]
+}
+
+impl Sequencer {
+    pub const fn new(melody: Melody, volume: u8) -> Self {
+        Self { melody, position: 0, volume, repeats_left: [None; MAX_MELODY_STEPS] }
+    }
+}
+
+impl Iterator for Sequencer {
+    type Item = ToneCommand;
+
+    fn next(&mut self) -> Option<ToneCommand> {
+        loop {
+            let index = self.position;
+            let step = *self.melody.get(index)?;
+            self.position += 1;
+            match step {
+                Step::Note { frequency, millis } => {
+                    let duration = Duration::from_millis(u64::from(millis));
+                    return Some(if self.volume == 0 || frequency == 0 {
+                        ToneCommand::Silence(duration)
+                    } else {
+                        ToneCommand::Tone { frequency, volume: self.volume, duration }
+                    })
+                }
+                Step::Rest { millis } => return Some(ToneCommand::Silence(Duration::from_millis(u64::from(millis)))),
+                Step::Repeat { to, times } => {
+                    // Repeats past MAX_MELODY_STEPS are played once
+                    let Some(left) = self.repeats_left.get_mut(index) else {
+                        continue
+                    };
+                    let passes = left.get_or_insert(times);
+                    if *passes > 0 {
+                        *passes -= 1;
+                        self.position = usize::from(to);
+                    } else {
+                        // Cleared so an enclosing repeat plays this section in full again
+                        *left = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::Duration;
+    use std::vec::Vec;
+
+    use super::{note, repeat, rest, Melody, Sequencer, Step, ToneCommand, Tune, A5, C5, C6, E5, E6, G5, G6};
+
+    fn tone(frequency: u16, millis: u64) -> ToneCommand {
+        ToneCommand::Tone { frequency, volume: 50, duration: Duration::from_millis(millis) }
+    }
+
+    fn silence(millis: u64) -> ToneCommand {
+        ToneCommand::Silence(Duration::from_millis(millis))
+    }
+
+    fn played(melody: Melody) -> Vec<ToneCommand> {
+        Sequencer::new(melody, 50).collect()
+    }
+
+    #[test]
+    fn work_and_break_done_play_straight_through() {
+        assert_eq!(played(Tune::WorkDone.melody()), [tone(G5, 150), tone(E5, 150), tone(C5, 300)]);
+        assert_eq!(played(Tune::BreakDone.melody()), [tone(C5, 120), tone(E5, 120), tone(G5, 120), tone(C6, 250)]);
+    }
+
+    #[test]
+    fn overtime_beeps_three_times() {
+        let beep = [tone(A5, 80), silence(80)];
+        assert_eq!(played(Tune::Overtime.melody()), beep.repeat(3));
+    }
+
+    #[test]
+    fn goal_reached_beeps_twice_then_climbs() {
+        assert_eq!(played(Tune::GoalReached.melody()), [
+            tone(C6, 90),
+            silence(40),
+            tone(C6, 90),
+            silence(40),
+            tone(G5, 120),
+            tone(C6, 120),
+            tone(E6, 160),
+            tone(G6, 400)
+        ]);
+    }
+
+    #[test]
+    fn inner_repeats_play_in_full_on_every_outer_pass() {
+        static NESTED: [Step; 5] = [note(C5, 10), note(E5, 10), repeat(1, 1), note(G5, 10), repeat(0, 1)];
+        let frequencies: Vec<u16> = Sequencer::new(&NESTED, 50)
+            .map(|command| match command {
+                ToneCommand::Tone { frequency, .. } => frequency,
+                ToneCommand::Silence(_) => 0
+            })
+            .collect();
+        assert_eq!(frequencies, [C5, E5, E5, G5, C5, E5, E5, G5]);
+    }
+
+    #[test]
+    fn no_volume_keeps_the_timing_in_silence() {
+        let quiet: Vec<ToneCommand> = Sequencer::new(Tune::WorkDone.melody(), 0).collect();
+        assert_eq!(quiet, [silence(150), silence(150), silence(300)]);
+        // A zero frequency note is a rest too
+        static UNPITCHED: [Step; 2] = [note(0, 70), rest(30)];
+        assert_eq!(played(&UNPITCHED), [silence(70), silence(30)]);
+    }
+}
@@ -0,0 +1,432 @@
+use core::cell::Cell;
+
+use embassy_sync::{blocking_mutex::{raw::CriticalSectionRawMutex, Mutex}, signal::Signal, watch::{Receiver, Watch}};
+use embassy_time::{Duration, Instant};
+
+use crate::constants::SLEEP_AFTER_PAUSE;
+
+const ZERO: Duration = Duration::from_ticks(0);
+
+// The encoder, its switch and the touch panel, the main button is armed by the task that sleeps
+const WAKE_SOURCES: usize = 3;
+
+// What the power policy needs from the hardware, so it can be driven without any
+pub trait PowerControl {
+    // Switches the panel off, the next frame drawn switches it back on
+    fn blank_display(&mut self);
+    // Lets the CPU sleep until a wake source fires, which is reported back through `PowerManager::woke`
+    fn light_sleep(&mut self);
+    // An input came in before the CPU got to sleep, so it has to stay up
+    fn cancel_sleep(&mut self);
+}
+
+/*
+ * Sleeps once the session has sat in a state that allows it for SLEEP_AFTER_PAUSE without input.
+ * All methods take the current Instant so the policy can run on synthetic time.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct PowerManager {
+    // Last input, or when sleeping last became possible
+    awake_since: Instant,
+    asleep: bool,
+    // An input called off the sleep and the hardware hasn't been told yet
+    cancelled: bool
+}
+
+impl PowerManager {
+    pub const fn new(now: Instant) -> Self {
+        Self { awake_since: now, asleep: false, cancelled: false }
+    }
+
+    pub const fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /*
+     * Returns true when the input came in after the panel was blanked.
+     * That calls the sleep off, and the input shouldn't count for anything else since nothing was on screen.
+     */
+    pub fn input(&mut self, now: Instant) -> bool {
+        self.awake_since = now;
+        let woke = self.asleep;
+        if woke {
+            self.asleep = false;
+            self.cancelled = true;
+        }
+        woke
+    }
+
+    // Returns how long until the device would sleep, so the caller can wake up for it
+    pub fn update<C: PowerControl>(&mut self, control: &mut C, can_sleep: bool, now: Instant) -> Option<Duration> {
+        if self.asleep {
+            return None
+        }
+        if core::mem::take(&mut self.cancelled) {
+            control.cancel_sleep();
+        }
+        if !can_sleep {
+            self.awake_since = now;
+            return None
+        }
+
+        let awake = now.saturating_duration_since(self.awake_since);
+        match SLEEP_AFTER_PAUSE.checked_sub(awake) {
+            Some(left) if left > ZERO => Some(left),
+            _ => {
+                control.blank_display();
+                control.light_sleep();
+                self.asleep = true;
+                None
+            }
+        }
+    }
+
+    // Starts the timeout over, so a session left paused goes back to sleep
+    pub fn woke(&mut self, now: Instant) {
+        self.asleep = false;
+        self.cancelled = false;
+        self.awake_since = now;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SleepPhase {
+    Awake,
+    Requested,
+    // Waiting on the wake sources to arm their pins
+    Arming,
+    Cancelled
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GateState {
+    phase: SleepPhase,
+    sources: u8,
+    armed: u8
+}
+
+/*
+ * The handshake between device_loop asking for sleep, the task that puts the CPU to sleep,
+ * and the input tasks whose pins have to be armed as wake sources first.
+ * An input anywhere between the request and the CPU going down calls the sleep off instead of being slept through.
+ */
+pub struct SleepGate {
+    state: Mutex<CriticalSectionRawMutex, Cell<GateState>>,
+    requested: Signal<CriticalSectionRawMutex, ()>,
+    // Every wake source armed, or the sleep called off
+    settled: Signal<CriticalSectionRawMutex, ()>,
+    // True while the wake sources should stay armed
+    arming: Watch<CriticalSectionRawMutex, bool, WAKE_SOURCES>
+}
+
+impl SleepGate {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(Cell::new(GateState { phase: SleepPhase::Awake, sources: 0, armed: 0 })),
+            requested: Signal::new(),
+            settled: Signal::new(),
+            arming: Watch::new()
+        }
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut GateState) -> R) -> R {
+        self.state.lock(|cell| {
+            let mut state = cell.get();
+            let result = f(&mut state);
+            cell.set(state);
+            result
+        })
+    }
+
+    // For an input task whose pins can wake the chip, it keeps the WakeSource for as long as it runs
+    pub fn wake_source(&self) -> WakeSource<'_> {
+        let receiver = self.arming.receiver().expect("more wake sources than WAKE_SOURCES");
+        self.update(|state| state.sources += 1);
+        WakeSource { gate: self, receiver }
+    }
+
+    // device_loop has blanked the panel
+    pub fn request(&self) {
+        self.update(|state| state.phase = SleepPhase::Requested);
+        self.requested.signal(());
+    }
+
+    // A no-op unless a sleep is pending
+    pub fn cancel(&self) {
+        self.update(|state| {
+            if matches!(state.phase, SleepPhase::Requested | SleepPhase::Arming) {
+                state.phase = SleepPhase::Cancelled;
+                self.settled.signal(());
+            }
+        });
+    }
+
+    // Resolves once device_loop asked for sleep, which may have been called off again by the time this returns
+    pub async fn requested(&self) {
+        self.requested.wait().await;
+    }
+
+    /*
+     * Has the wake sources arm their pins, then returns whether the CPU may go to sleep.
+     * Nothing can call the sleep off between this returning true and the CPU sleeping as long as no await is in between,
+     * inputs after that fire an armed wake source instead.
+     */
+    pub async fn arm(&self) -> bool {
+        let sources = self.update(|state| {
+            if state.phase != SleepPhase::Requested {
+                return None
+            }
+            state.phase = SleepPhase::Arming;
+            state.armed = 0;
+            self.settled.reset();
+            Some(state.sources)
+        });
+        match sources {
+            None => return false,
+            Some(0) => (),
+            Some(_) => {
+                self.arming.sender().send(true);
+                self.settled.wait().await;
+            }
+        }
+        self.update(|state| state.phase == SleepPhase::Arming)
+    }
+
+    // After the sleep or after it was called off, lets the wake sources go back to their inputs
+    pub fn disarm(&self) {
+        self.update(|state| {
+            state.phase = SleepPhase::Awake;
+            state.armed = 0;
+        });
+        self.arming.sender().send(false);
+    }
+
+    fn armed(&self) {
+        self.update(|state| {
+            if state.phase == SleepPhase::Arming {
+                state.armed += 1;
+                if state.armed == state.sources {
+                    self.settled.signal(());
+                }
+            }
+        });
+    }
+}
+
+impl Default for SleepGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// An input task's end of the SleepGate
+pub struct WakeSource<'a> {
+    gate: &'a SleepGate,
+    receiver: Receiver<'a, CriticalSectionRawMutex, bool, WAKE_SOURCES>
+}
+
+impl WakeSource<'_> {
+    // Resolves when the CPU is about to sleep, the task then arms its pins and calls `armed`
+    pub async fn sleeping(&mut self) {
+        self.receiver.changed_and(|arming| *arming).await;
+    }
+
+    // Waits until the CPU is back up, so the task can disarm its pins and go back to its input
+    pub async fn armed(&mut self) {
+        self.gate.armed();
+        self.receiver.changed_and(|arming| !*arming).await;
+    }
+
+    // Every input goes through here before it's sent, so a sleep that is still pending is called off
+    pub fn input(&self) {
+        self.gate.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::join::{join, join3};
+    use embassy_time::{Duration, Instant, Timer};
+
+    use super::*;
+    use crate::testing::run_timed;
+
+    #[derive(Default)]
+    struct Control {
+        blanks: usize,
+        sleeps: usize,
+        cancels: usize
+    }
+
+    impl PowerControl for Control {
+        fn blank_display(&mut self) {
+            self.blanks += 1;
+        }
+
+        fn light_sleep(&mut self) {
+            self.sleeps += 1;
+        }
+
+        fn cancel_sleep(&mut self) {
+            self.cancels += 1;
+        }
+    }
+
+    const fn at_secs(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    fn asleep_at(power: &mut PowerManager, control: &mut Control, secs: u64) {
+        assert_eq!(power.update(control, true, at_secs(secs)), None);
+        assert!(power.is_asleep());
+    }
+
+    #[test]
+    fn sleeps_once_the_pause_has_lasted() {
+        let mut power = PowerManager::new(at_secs(0));
+        let mut control = Control::default();
+        assert_eq!(power.update(&mut control, true, at_secs(0)), Some(SLEEP_AFTER_PAUSE));
+        assert_eq!(power.update(&mut control, true, at_secs(90)), Some(SLEEP_AFTER_PAUSE - Duration::from_secs(90)));
+        assert_eq!(control.sleeps, 0);
+        asleep_at(&mut power, &mut control, 120);
+        assert_eq!((control.blanks, control.sleeps, control.cancels), (1, 1, 0));
+        // Asleep it leaves the hardware alone
+        assert_eq!(power.update(&mut control, true, at_secs(500)), None);
+        assert_eq!(control.sleeps, 1);
+    }
+
+    #[test]
+    fn running_starts_the_timeout_over() {
+        let mut power = PowerManager::new(at_secs(0));
+        let mut control = Control::default();
+        power.update(&mut control, true, at_secs(0));
+        assert_eq!(power.update(&mut control, false, at_secs(100)), None);
+        assert_eq!(power.update(&mut control, false, at_secs(300)), None);
+        assert_eq!(power.update(&mut control, true, at_secs(310)), Some(SLEEP_AFTER_PAUSE - Duration::from_secs(10)));
+        assert_eq!(control.sleeps, 0);
+    }
+
+    #[test]
+    fn input_starts_the_timeout_over() {
+        let mut power = PowerManager::new(at_secs(0));
+        let mut control = Control::default();
+        power.update(&mut control, true, at_secs(0));
+        assert!(!power.input(at_secs(100)));
+        assert_eq!(power.update(&mut control, true, at_secs(150)), Some(SLEEP_AFTER_PAUSE - Duration::from_secs(50)));
+        asleep_at(&mut power, &mut control, 220);
+    }
+
+    #[test]
+    fn input_after_the_blank_calls_the_sleep_off() {
+        let mut power = PowerManager::new(at_secs(0));
+        let mut control = Control::default();
+        asleep_at(&mut power, &mut control, 120);
+        assert!(power.input(at_secs(121)));
+        assert!(!power.is_asleep());
+        assert_eq!(power.update(&mut control, true, at_secs(121)), Some(SLEEP_AFTER_PAUSE));
+        assert_eq!(control.cancels, 1);
+        // Only the once
+        power.update(&mut control, true, at_secs(122));
+        assert_eq!(control.cancels, 1);
+    }
+
+    #[test]
+    fn waking_starts_the_timeout_over() {
+        let mut power = PowerManager::new(at_secs(0));
+        let mut control = Control::default();
+        asleep_at(&mut power, &mut control, 120);
+        power.woke(at_secs(4000));
+        assert_eq!(power.update(&mut control, true, at_secs(4000)), Some(SLEEP_AFTER_PAUSE));
+        assert_eq!(control.cancels, 0);
+        asleep_at(&mut power, &mut control, 4120);
+        assert_eq!(control.sleeps, 2);
+    }
+
+    // Arms when asked and stays armed until the gate is disarmed, like the input tasks
+    async fn armed_source(source: &mut WakeSource<'_>) {
+        source.sleeping().await;
+        source.armed().await;
+    }
+
+    #[test]
+    fn sleep_goes_ahead_once_every_source_is_armed() {
+        let gate = SleepGate::new();
+        let (mut encoder, mut switch) = (gate.wake_source(), gate.wake_source());
+        gate.request();
+        let ((slept, _, _), _) = run_timed(join3(
+            async {
+                gate.requested().await;
+                let slept = gate.arm().await;
+                gate.disarm();
+                slept
+            },
+            armed_source(&mut encoder),
+            armed_source(&mut switch)
+        ));
+        assert!(slept);
+    }
+
+    #[test]
+    fn without_wake_sources_only_the_request_counts() {
+        let gate = SleepGate::new();
+        assert!(!run_timed(gate.arm()).0);
+        gate.request();
+        assert!(run_timed(gate.arm()).0);
+    }
+
+    #[test]
+    fn input_before_arming_calls_the_sleep_off() {
+        let gate = SleepGate::new();
+        let encoder = gate.wake_source();
+        gate.request();
+        encoder.input();
+        assert!(!run_timed(gate.arm()).0);
+        // And a cancel that comes from device_loop
+        gate.disarm();
+        gate.request();
+        gate.cancel();
+        assert!(!run_timed(gate.arm()).0);
+    }
+
+    #[test]
+    fn input_while_arming_calls_the_sleep_off() {
+        let gate = SleepGate::new();
+        let (mut encoder, switch) = (gate.wake_source(), gate.wake_source());
+        gate.request();
+        let ((slept, _, _), _) = run_timed(join3(
+            async {
+                let slept = gate.arm().await;
+                gate.disarm();
+                slept
+            },
+            armed_source(&mut encoder),
+            // Turned before it got to arm
+            async {
+                Timer::after_millis(5).await;
+                switch.input();
+            }
+        ));
+        assert!(!slept);
+    }
+
+    #[test]
+    fn a_source_that_missed_a_sleep_waits_for_the_next() {
+        let gate = SleepGate::new();
+        let (mut encoder, mut switch) = (gate.wake_source(), gate.wake_source());
+        gate.request();
+        switch.input();
+        assert!(!run_timed(gate.arm()).0);
+        gate.disarm();
+
+        gate.request();
+        let ((slept, _), _) = run_timed(join(
+            async {
+                let slept = gate.arm().await;
+                gate.disarm();
+                slept
+            },
+            join(armed_source(&mut encoder), armed_source(&mut switch))
+        ));
+        assert!(slept);
+    }
+}
@@ -0,0 +1,929 @@
+use embassy_time::{Duration, Instant};
+use embedded_graphics::{pixelcolor::{raw::RawU16, Rgb565}, prelude::RawData};
+use embedded_storage::{ReadStorage, Storage};
+use heapless::{String, Vec};
+
+use crate::{chess_clock::{ChessConfig, TimeControl}, clock_util::SessionMode, constants::{MAX_PRESETS, MAX_ROUND_SEGMENTS, MIN_BRIGHTNESS, PICKER_TIMEOUT}, draw_panels::Palette, flowtime::FlowRatio, interval::{IntervalProgram, Segment, SegmentKind, SegmentName, SEGMENT_NAME_LEN}, scenes::Scene, settings::{NightSchedule, Settings}, time_util::DisplayPrecision};
+
+pub const PRESET_NAME_LEN: usize = 12;
+// Bytes reserved for each preset; a version can grow into the spare room without moving slots
+pub const PRESET_SIZE: usize = 128;
+const HEADER_SIZE: usize = 16;
+// The wall clock takes the second half of the header, so saving it leaves the rest alone
+const TIME_OFFSET: usize = 8;
+pub const STORE_SIZE: usize = HEADER_SIZE + MAX_PRESETS * PRESET_SIZE;
+
+// A change that older firmware can't skip over needs a new magic rather than a new version
+const PRESET_MAGIC: u8 = b'P';
+const STORE_MAGIC: [u8; 2] = *b"TS";
+/*
+ * Version 1 layout, all numbers little endian:
+ *   magic, version, name[12], mode tag and mode fields, settings, precision
+ * Version 2 appends the backlight: brightness, dim after, dim brightness, night start, end and brightness.
+ * Version 3 appends the buzzer volume.
+ * Version 4 appends the names of the round segments of interval programs, SEGMENT_NAME_LEN bytes each.
+ * Version 5 appends whether the buzzer is muted.
+ * Version 6 appends the timer colours as RGB565, work then break.
+ * Versions only ever append fields: older firmware reads the fields it knows and skips the rest,
+ * and fields missing from older data fall back to their defaults.
+ * Saving from older firmware drops the newer fields.
+ */
+pub const PRESET_VERSION: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetError {
+    // Erased or foreign data
+    BadMagic,
+    // Version 0 was never written
+    UnsupportedVersion(u8),
+    Truncated
+}
+
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub name: String<PRESET_NAME_LEN>,
+    pub mode: SessionMode,
+    pub settings: Settings,
+    pub precision: DisplayPrecision,
+    pub palette: Palette
+}
+
+impl Preset {
+    pub fn new(name: &str, mode: SessionMode) -> Self {
+        let mut preset_name = String::new();
+        for c in name.chars() {
+            if preset_name.push(c).is_err() {
+                break
+            }
+        }
+
+        Self {
+            name: preset_name,
+            mode,
+            settings: Settings::default(),
+            precision: DisplayPrecision::default(),
+            palette: Palette::default()
+        }
+    }
+
+    // Four rounds of fixed work and break lengths
+    fn pomodoro(name: &str, work_minutes: u64, rest_minutes: u64) -> Self {
+        let round = [
+            Segment::new("work", work_minutes * 60, SegmentKind::Work),
+            Segment::new("break", rest_minutes * 60, SegmentKind::Rest)
+        ];
+        Self::new(name, SessionMode::Interval(IntervalProgram::new(&round, 4)))
+    }
+
+    // What a fresh device offers
+    pub fn builtin() -> Vec<Self, MAX_PRESETS> {
+        let mut presets = Vec::new();
+        let _ = presets.push(Self::new("count up", Scene::ConfigTaro.into()));
+        let _ = presets.push(Self::new("stopwatch", Scene::ConfigCountingUp.into()));
+        let _ = presets.push(Self::pomodoro("25/5", 25, 5));
+        let _ = presets.push(Self::pomodoro("50/10", 50, 10));
+        let _ = presets.push(Self::new("flowtime", SessionMode::Flowtime(FlowRatio::default())));
+        let _ = presets.push(Self::new("chess", SessionMode::ChessClock(ChessConfig::default())));
+        let _ = presets.push(Self::new("intervals", Scene::ConfigInterval.into()));
+        presets
+    }
+
+    pub fn encode(&self) -> [u8; PRESET_SIZE] {
+        let mut buffer = [0; PRESET_SIZE];
+        let mut writer = ByteWriter::new(&mut buffer);
+        writer.u8(PRESET_MAGIC);
+        writer.u8(PRESET_VERSION);
+        let mut name = [0; PRESET_NAME_LEN];
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        writer.bytes(&name);
+
+        match self.mode {
+            SessionMode::DoubleTimer => writer.u8(0),
+            SessionMode::ChessClock(config) => {
+                writer.u8(1);
+                writer.seconds(config.top_budget);
+                writer.seconds(config.bottom_budget);
+                let (tag, extra) = match config.control {
+                    TimeControl::SuddenDeath => (0, Duration::from_ticks(0)),
+                    TimeControl::Fischer(increment) => (1, increment),
+                    TimeControl::Bronstein(delay) => (2, delay),
+                    TimeControl::SimpleDelay(delay) => (3, delay)
+                };
+                writer.u8(tag);
+                writer.seconds(extra);
+            }
+            SessionMode::Stopwatch => writer.u8(2),
+            SessionMode::Flowtime(ratio) => {
+                writer.u8(3);
+                writer.u16(ratio.work);
+                writer.u16(ratio.rest);
+            }
+            SessionMode::Interval(program) => {
+                writer.u8(4);
+                writer.u8(program.rounds);
+                writer.optional_seconds(program.warm_up.map(|segment| segment.duration));
+                writer.optional_seconds(program.cool_down.map(|segment| segment.duration));
+                writer.u8(program.round().len() as u8);
+                for segment in program.round() {
+                    writer.u8(match segment.kind {
+                        SegmentKind::Work => 0,
+                        SegmentKind::Rest => 1
+                    });
+                    writer.seconds(segment.duration);
+                }
+            }
+        }
+
+        let settings = &self.settings;
+        writer.seconds(settings.undo_window);
+        writer.optional_seconds(settings.idle_timeout);
+        writer.seconds(settings.idle_prompt);
+        writer.u8(settings.rollover_hour);
+        writer.u16(settings.utc_offset_minutes as u16);
+        writer.optional_seconds(settings.daily_goal);
+
+        writer.u8(match self.precision {
+            DisplayPrecision::Seconds => 0,
+            DisplayPrecision::Tenths => 1,
+            DisplayPrecision::Hundredths => 2
+        });
+
+        writer.u8(settings.brightness);
+        writer.optional_seconds(settings.dim_after);
+        writer.u8(settings.dim_brightness);
+        // 0xFF for the start hour marks no schedule
+        let night = settings.night;
+        writer.u8(night.map_or(u8::MAX, |night| night.start_hour));
+        writer.u8(night.map_or(0, |night| night.end_hour));
+        writer.u8(night.map_or(0, |night| night.brightness));
+
+        writer.u8(settings.volume);
+
+        if let SessionMode::Interval(program) = self.mode {
+            for segment in program.round() {
+                writer.bytes(segment.name.as_bytes());
+            }
+        }
+
+        writer.u8(u8::from(settings.muted));
+
+        writer.u16(RawU16::from(self.palette.work).into_inner());
+        writer.u16(RawU16::from(self.palette.rest).into_inner());
+        buffer
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, PresetError> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.u8() != Some(PRESET_MAGIC) {
+            return Err(PresetError::BadMagic)
+        }
+        let version = match reader.u8() {
+            Some(0) => return Err(PresetError::UnsupportedVersion(0)),
+            Some(version) => version,
+            None => return Err(PresetError::Truncated)
+        };
+
+        let name_bytes = reader.bytes(PRESET_NAME_LEN).ok_or(PresetError::Truncated)?;
+        let name_len = name_bytes.iter().position(|byte| *byte == 0).unwrap_or(PRESET_NAME_LEN);
+        let name = str::from_utf8(&name_bytes[..name_len]).unwrap_or("preset");
+
+        let mode = match reader.u8().ok_or(PresetError::Truncated)? {
+            1 => {
+                let defaults = ChessConfig::default();
+                let top_budget = reader.seconds().unwrap_or(defaults.top_budget);
+                let bottom_budget = reader.seconds().unwrap_or(defaults.bottom_budget);
+                let tag = reader.u8();
+                let extra = reader.seconds().unwrap_or(Duration::from_ticks(0));
+                let control = match tag {
+                    Some(0) => TimeControl::SuddenDeath,
+                    Some(1) => TimeControl::Fischer(extra),
+                    Some(2) => TimeControl::Bronstein(extra),
+                    Some(3) => TimeControl::SimpleDelay(extra),
+                    _ => defaults.control
+                };
+                SessionMode::ChessClock(ChessConfig { top_budget, bottom_budget, control })
+            }
+            2 => SessionMode::Stopwatch,
+            3 => {
+                let defaults = FlowRatio::default();
+                SessionMode::Flowtime(FlowRatio {
+                    work: reader.u16().unwrap_or(defaults.work),
+                    rest: reader.u16().unwrap_or(defaults.rest)
+                })
+            }
+            4 => SessionMode::Interval(Self::decode_program(&mut reader)),
+            _ => SessionMode::DoubleTimer
+        };
+
+        let mut preset = Self::new(name, mode);
+        let settings = &mut preset.settings;
+        let defaults = Settings::default();
+        settings.undo_window = reader.seconds().unwrap_or(defaults.undo_window);
+        settings.idle_timeout = reader.optional_seconds().unwrap_or(defaults.idle_timeout);
+        settings.idle_prompt = reader.seconds().unwrap_or(defaults.idle_prompt);
+        settings.rollover_hour = reader.u8().unwrap_or(defaults.rollover_hour);
+        settings.utc_offset_minutes = reader.u16().map_or(defaults.utc_offset_minutes, |offset| offset as i16);
+        settings.daily_goal = reader.optional_seconds().unwrap_or(defaults.daily_goal);
+
+        preset.precision = match reader.u8() {
+            Some(1) => DisplayPrecision::Tenths,
+            Some(2) => DisplayPrecision::Hundredths,
+            _ => DisplayPrecision::Seconds
+        };
+
+        // Version 1 presets are zero padded here, which would read as a black screen
+        if version >= 2 {
+            let settings = &mut preset.settings;
+            let defaults = Settings::default();
+            settings.brightness = reader.u8().map_or(defaults.brightness, |brightness| brightness.clamp(MIN_BRIGHTNESS, 100));
+            settings.dim_after = reader.optional_seconds().unwrap_or(defaults.dim_after);
+            settings.dim_brightness = reader.u8().unwrap_or(defaults.dim_brightness);
+            settings.night = match (reader.u8(), reader.u8(), reader.u8()) {
+                (Some(u8::MAX), _, _) => None,
+                (Some(start_hour), Some(end_hour), Some(brightness)) => Some(NightSchedule { start_hour, end_hour, brightness }),
+                _ => defaults.night
+            };
+        }
+        if version >= 3 {
+            preset.settings.volume = reader.u8().map_or(Settings::default().volume, |volume| volume.min(100));
+        }
+        if version >= 4 {
+            if let SessionMode::Interval(program) = &mut preset.mode {
+                for segment in program.round_mut() {
+                    let Some(name) = reader.bytes(SEGMENT_NAME_LEN) else {
+                        break
+                    };
+                    let mut bytes = [0; SEGMENT_NAME_LEN];
+                    bytes.copy_from_slice(name);
+                    segment.name = SegmentName::from_bytes(bytes);
+                }
+            }
+        }
+        if version >= 5 {
+            preset.settings.muted = reader.u8().is_some_and(|muted| muted != 0);
+        }
+        if version >= 6 {
+            let defaults = Palette::default();
+            let color = |raw: Option<u16>, default| raw.map_or(default, |raw| Rgb565::from(RawU16::new(raw)));
+            preset.palette = Palette {
+                work: color(reader.u16(), defaults.work),
+                rest: color(reader.u16(), defaults.rest)
+            };
+        }
+        Ok(preset)
+    }
+
+    // Round segments are named after their kind until version 4 names them, warm-up and cool-down always keep their names
+    fn decode_program(reader: &mut ByteReader) -> IntervalProgram {
+        let defaults = IntervalProgram::default();
+        let rounds = reader.u8().unwrap_or(defaults.rounds);
+        let warm_up = reader.optional_seconds().unwrap_or(defaults.warm_up.map(|segment| segment.duration));
+        let cool_down = reader.optional_seconds().unwrap_or(defaults.cool_down.map(|segment| segment.duration));
+
+        let mut segments: Vec<Segment, MAX_ROUND_SEGMENTS> = Vec::new();
+        let count = reader.u8().unwrap_or(0);
+        for _ in 0..count {
+            let (Some(kind), Some(duration)) = (reader.u8(), reader.seconds()) else {
+                break
+            };
+            let segment = match kind {
+                0 => Segment::new("work", 0, SegmentKind::Work),
+                _ => Segment::new("rest", 0, SegmentKind::Rest)
+            };
+            let _ = segments.push(Segment { duration, ..segment });
+        }
+        if segments.is_empty() {
+            return defaults
+        }
+
+        let mut program = IntervalProgram::new(&segments, rounds);
+        if let Some(duration) = warm_up {
+            program = program.with_warm_up(Segment { duration, ..Segment::new("warm-up", 0, SegmentKind::Rest) });
+        }
+        if let Some(duration) = cool_down {
+            program = program.with_cool_down(Segment { duration, ..Segment::new("cool-down", 0, SegmentKind::Rest) });
+        }
+        program
+    }
+}
+
+struct ByteWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize
+}
+
+// Writes past the end of the buffer are dropped; PRESET_SIZE leaves plenty of room
+impl<'a> ByteWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        let end = (self.len + bytes.len()).min(self.buffer.len());
+        let count = end - self.len;
+        self.buffer[self.len..end].copy_from_slice(&bytes[..count]);
+        self.len = end;
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn seconds(&mut self, duration: Duration) {
+        self.bytes(&(duration.as_secs().min(u64::from(u32::MAX)) as u32).to_le_bytes());
+    }
+
+    // u32::MAX marks None, since zero is a valid duration
+    fn optional_seconds(&mut self, duration: Option<Duration>) {
+        match duration {
+            Some(duration) => self.seconds(duration.min(Duration::from_secs(u64::from(u32::MAX) - 1))),
+            None => self.bytes(&u32::MAX.to_le_bytes())
+        }
+    }
+}
+
+// Every read returns None once the data runs out, so callers fall back to defaults
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn seconds(&mut self) -> Option<Duration> {
+        self.u32().map(|seconds| Duration::from_secs(u64::from(seconds)))
+    }
+
+    fn optional_seconds(&mut self) -> Option<Option<Duration>> {
+        self.u32().map(|seconds| (seconds != u32::MAX).then(|| Duration::from_secs(u64::from(seconds))))
+    }
+}
+
+/*
+ * Presets plus the index of the last one used and the wall clock, on any embedded-storage backend.
+ * Layout: a header ("TS", version, last used, count, 3 spare bytes, UTC seconds) followed by fixed size preset slots.
+ */
+pub struct PresetStore<S> {
+    storage: S
+}
+
+impl<S: Storage> PresetStore<S> {
+    pub const fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    fn header(&mut self) -> Option<(usize, usize)> {
+        let mut header = [0; HEADER_SIZE];
+        self.storage.read(0, &mut header).ok()?;
+        // Any version is readable, see PRESET_VERSION
+        if header[..2] != STORE_MAGIC || header[2] == 0 {
+            return None
+        }
+        Some((usize::from(header[3]), usize::from(header[4]).min(MAX_PRESETS)))
+    }
+
+    // Falls back to the built-in presets when nothing readable was saved
+    pub fn load(&mut self) -> (Vec<Preset, MAX_PRESETS>, usize) {
+        let Some((last_used, count)) = self.header() else {
+            return (Preset::builtin(), 0)
+        };
+
+        let mut presets = Vec::new();
+        for slot in 0..count {
+            let mut bytes = [0; PRESET_SIZE];
+            if self.storage.read((HEADER_SIZE + slot * PRESET_SIZE) as u32, &mut bytes).is_err() {
+                continue
+            }
+            if let Ok(preset) = Preset::decode(&bytes) {
+                let _ = presets.push(preset);
+            }
+        }
+        if presets.is_empty() {
+            return (Preset::builtin(), 0)
+        }
+        let last_used = last_used.min(presets.len() - 1);
+        (presets, last_used)
+    }
+
+    pub fn save(&mut self, presets: &[Preset], last_used: usize) -> Result<(), S::Error> {
+        let count = presets.len().min(MAX_PRESETS);
+        for (slot, preset) in presets.iter().take(count).enumerate() {
+            self.storage.write((HEADER_SIZE + slot * PRESET_SIZE) as u32, &preset.encode())?;
+        }
+        self.write_header(last_used, count)
+    }
+
+    pub fn set_last_used(&mut self, last_used: usize) -> Result<(), S::Error> {
+        match self.header() {
+            Some((_, count)) => self.write_header(last_used, count),
+            // Nothing saved yet, so store the presets that are in use
+            None => self.save(&Preset::builtin(), last_used)
+        }
+    }
+
+    // The wall clock as last saved, erased flash reads as never saved
+    pub fn saved_time(&mut self) -> Option<u64> {
+        self.header()?;
+        let mut bytes = [0; 8];
+        self.storage.read(TIME_OFFSET as u32, &mut bytes).ok()?;
+        let utc_seconds = u64::from_le_bytes(bytes);
+        (utc_seconds != u64::MAX).then_some(utc_seconds)
+    }
+
+    pub fn save_time(&mut self, utc_seconds: u64) -> Result<(), S::Error> {
+        if self.header().is_none() {
+            self.save(&Preset::builtin(), 0)?;
+        }
+        self.storage.write(TIME_OFFSET as u32, &utc_seconds.to_le_bytes())
+    }
+
+    fn write_header(&mut self, last_used: usize, count: usize) -> Result<(), S::Error> {
+        let mut header = [0; TIME_OFFSET];
+        header[..2].copy_from_slice(&STORE_MAGIC);
+        header[2] = PRESET_VERSION;
+        header[3] = last_used as u8;
+        header[4] = count as u8;
+        self.storage.write(0, &header)
+    }
+}
+
+/*
+ * The `N` bytes from `offset` on of a larger storage, like a flash partition.
+ * Keeps the store's offsets from 0 and stops it from writing past its end.
+ */
+pub struct Partition<S, const N: usize> {
+    storage: S,
+    offset: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionError<E> {
+    OutOfBounds,
+    Storage(E)
+}
+
+impl<S, const N: usize> Partition<S, N> {
+    pub const fn new(storage: S, offset: u32) -> Self {
+        Self { storage, offset }
+    }
+
+    fn absolute<E>(&self, offset: u32, len: usize) -> Result<u32, PartitionError<E>> {
+        if offset as usize + len > N {
+            return Err(PartitionError::OutOfBounds)
+        }
+        Ok(self.offset + offset)
+    }
+}
+
+impl<S: ReadStorage, const N: usize> ReadStorage for Partition<S, N> {
+    type Error = PartitionError<S::Error>;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = self.absolute(offset, bytes.len())?;
+        self.storage.read(offset, bytes).map_err(PartitionError::Storage)
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<S: Storage, const N: usize> Storage for Partition<S, N> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = self.absolute(offset, bytes.len())?;
+        self.storage.write(offset, bytes).map_err(PartitionError::Storage)
+    }
+}
+
+// Backend device_loop keeps its presets in, a sector of the flash on the chip
+#[cfg(target_os = "none")]
+pub use crate::board::PresetStorage;
+// Host builds only run the tests
+#[cfg(not(target_os = "none"))]
+pub type PresetStorage = MemoryStorage<STORE_SIZE>;
+
+// RAM backed storage for the tests, starting out erased like flash
+#[cfg(not(target_os = "none"))]
+pub struct MemoryStorage<const N: usize>([u8; N]);
+
+#[cfg(not(target_os = "none"))]
+impl<const N: usize> Default for MemoryStorage<N> {
+    fn default() -> Self {
+        Self([0xFF; N])
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds;
+
+#[cfg(not(target_os = "none"))]
+impl<const N: usize> ReadStorage for MemoryStorage<N> {
+    type Error = OutOfBounds;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let source = self.0.get(start..start + bytes.len()).ok_or(OutOfBounds)?;
+        bytes.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl<const N: usize> Storage for MemoryStorage<N> {
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let target = self.0.get_mut(start..start + bytes.len()).ok_or(OutOfBounds)?;
+        target.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/*
+ * Boot menu listing the presets.
+ * Short press or the encoder moves the selection, long press (or PICKER_TIMEOUT without input) picks it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresetPicker {
+    selected: usize,
+    count: usize,
+    last_input: Instant
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerResult {
+    Browsing,
+    Picked(usize)
+}
+
+impl PresetPicker {
+    pub const fn new(selected: usize, count: usize, now: Instant) -> Self {
+        Self { selected, count, last_input: now }
+    }
+
+    pub const fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_input) > PICKER_TIMEOUT
+    }
+
+    pub fn scroll_by(&mut self, steps: i32) {
+        if self.count > 0 {
+            self.selected = (self.selected as i32 + steps).rem_euclid(self.count as i32) as usize;
+        }
+    }
+
+    pub fn short_press(&mut self) -> PickerResult {
+        self.scroll_by(1);
+        PickerResult::Browsing
+    }
+
+    pub const fn long_press(&self) -> PickerResult {
+        PickerResult::Picked(self.selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::RgbColor;
+
+    use super::*;
+    use crate::constants::{BREAK_COLOR, WORK_COLOR};
+
+    fn sprint() -> Preset {
+        let round = [
+            Segment::new("sprint", 30, SegmentKind::Work),
+            Segment::new("jog", 90, SegmentKind::Rest),
+            Segment::new("walk", 60, SegmentKind::Rest)
+        ];
+        let mut preset = Preset::new("track", SessionMode::Interval(IntervalProgram::new(&round, 6)));
+        preset.precision = DisplayPrecision::Tenths;
+        preset.settings.volume = 40;
+        preset.settings.night = None;
+        preset
+    }
+
+    fn assert_same(decoded: &Preset, preset: &Preset) {
+        assert_eq!(decoded.name, preset.name);
+        assert_eq!(decoded.mode, preset.mode);
+        assert_eq!(decoded.settings, preset.settings);
+        assert_eq!(decoded.precision, preset.precision);
+        assert_eq!(decoded.palette, preset.palette);
+    }
+
+    #[test]
+    fn builtins_round_trip() {
+        for preset in Preset::builtin().iter().chain([&sprint()]) {
+            assert_same(&Preset::decode(&preset.encode()).unwrap(), preset);
+        }
+    }
+
+    #[test]
+    fn every_mode_has_a_builtin() {
+        let presets = Preset::builtin();
+        let has = |matches: fn(&SessionMode) -> bool| presets.iter().any(|preset| matches(&preset.mode));
+        assert!(has(|mode| *mode == SessionMode::DoubleTimer));
+        assert!(has(|mode| *mode == SessionMode::Stopwatch));
+        assert!(has(|mode| matches!(mode, SessionMode::ChessClock(_))));
+        assert!(has(|mode| matches!(mode, SessionMode::Flowtime(_))));
+        assert!(has(|mode| matches!(mode, SessionMode::Interval(_))));
+    }
+
+    #[test]
+    fn segment_names_survive() {
+        let SessionMode::Interval(program) = Preset::decode(&sprint().encode()).unwrap().mode else {
+            panic!("not an interval program")
+        };
+        let names: Vec<&str, MAX_ROUND_SEGMENTS> = program.round().iter().map(|segment| segment.name.as_str()).collect();
+        assert_eq!(names.as_slice(), ["sprint", "jog", "walk"]);
+        assert_eq!(program.warm_up, None);
+    }
+
+    #[test]
+    fn newer_versions_skip_what_they_add() {
+        let mut preset = Preset::new("count up", SessionMode::DoubleTimer);
+        preset.settings.volume = 40;
+        preset.settings.muted = true;
+        let mut bytes = preset.encode();
+        bytes[1] = PRESET_VERSION + 1;
+        // The palette is the last field, the next version appends after it
+        let used = PRESET_SIZE - bytes.iter().rev().position(|byte| *byte != 0).unwrap();
+        bytes[used..used + 4].copy_from_slice(&[0xAB; 4]);
+
+        assert_same(&Preset::decode(&bytes).unwrap(), &preset);
+        bytes[1] = u8::MAX;
+        assert_same(&Preset::decode(&bytes).unwrap(), &preset);
+    }
+
+    #[test]
+    fn bad_records() {
+        let mut bytes = sprint().encode();
+        bytes[1] = 0;
+        assert_eq!(Preset::decode(&bytes).unwrap_err(), PresetError::UnsupportedVersion(0));
+        assert_eq!(Preset::decode(&[0xFF; PRESET_SIZE]).unwrap_err(), PresetError::BadMagic);
+        assert_eq!(Preset::decode(&[PRESET_MAGIC]).unwrap_err(), PresetError::Truncated);
+    }
+
+    #[test]
+    fn truncated_fields_fall_back_to_defaults() {
+        // Cut right after the mode tag of a count up preset
+        let mut preset = Preset::new("short", SessionMode::DoubleTimer);
+        preset.settings.volume = 40;
+        let decoded = Preset::decode(&preset.encode()[..2 + PRESET_NAME_LEN + 1]).unwrap();
+        assert_eq!(decoded.settings, Settings::default());
+        assert_eq!(decoded.precision, DisplayPrecision::default());
+
+        // Without the version 4 names the round segments are named after their kind
+        let mut old = sprint().encode();
+        old[1] = 3;
+        let SessionMode::Interval(program) = Preset::decode(&old).unwrap().mode else {
+            panic!("not an interval program")
+        };
+        let names: Vec<&str, MAX_ROUND_SEGMENTS> = program.round().iter().map(|segment| segment.name.as_str()).collect();
+        assert_eq!(names.as_slice(), ["work", "rest", "rest"]);
+    }
+
+    // A flowtime preset laid out field by field the way firmware of `version` wrote it, zero padded like a slot
+    fn written_by(version: u8) -> [u8; PRESET_SIZE] {
+        let mut bytes = [0; PRESET_SIZE];
+        let mut writer = ByteWriter::new(&mut bytes);
+        writer.u8(PRESET_MAGIC);
+        writer.u8(version);
+        writer.bytes(b"legacy\0\0\0\0\0\0");
+        // Flowtime at 3:1
+        writer.u8(3);
+        writer.u16(3);
+        writer.u16(1);
+        // Undo window, no idle timeout, idle prompt, rollover hour, UTC+2 and no daily goal
+        writer.seconds(Duration::from_secs(20));
+        writer.optional_seconds(None);
+        writer.seconds(Duration::from_secs(45));
+        writer.u8(5);
+        writer.u16(120);
+        writer.optional_seconds(None);
+        // Tenths
+        writer.u8(1);
+        if version >= 2 {
+            writer.u8(60);
+            writer.optional_seconds(Some(Duration::from_secs(90)));
+            writer.u8(10);
+            // No night schedule
+            writer.bytes(&[u8::MAX, 0, 0]);
+        }
+        if version >= 3 {
+            writer.u8(25);
+        }
+        // Version 4 adds nothing for a flowtime preset
+        if version >= 5 {
+            writer.u8(1);
+        }
+        if version >= 6 {
+            writer.u16(RawU16::from(Rgb565::GREEN).into_inner());
+            writer.u16(RawU16::from(Rgb565::MAGENTA).into_inner());
+        }
+        bytes
+    }
+
+    fn legacy_settings() -> Settings {
+        Settings {
+            undo_window: Duration::from_secs(20),
+            idle_timeout: None,
+            idle_prompt: Duration::from_secs(45),
+            rollover_hour: 5,
+            utc_offset_minutes: 120,
+            daily_goal: None,
+            ..Settings::default()
+        }
+    }
+
+    fn assert_legacy(decoded: &Preset, settings: Settings) {
+        assert_eq!(decoded.name, "legacy");
+        assert_eq!(decoded.mode, SessionMode::Flowtime(FlowRatio { work: 3, rest: 1 }));
+        assert_eq!(decoded.precision, DisplayPrecision::Tenths);
+        assert_eq!(decoded.settings, settings);
+    }
+
+    // Older versions keep the colours the timers were drawn in before presets had their own
+    fn assert_legacy_colors(decoded: &Preset) {
+        assert_eq!(decoded.palette, Palette { work: WORK_COLOR, rest: BREAK_COLOR });
+    }
+
+    #[test]
+    fn version_1_gets_the_default_backlight_and_volume() {
+        // The zero padding where the backlight goes would otherwise read as a black screen
+        let decoded = Preset::decode(&written_by(1)).unwrap();
+        assert_legacy(&decoded, legacy_settings());
+        assert_legacy_colors(&decoded);
+    }
+
+    #[test]
+    fn version_2_keeps_its_backlight() {
+        let settings = Settings {
+            brightness: 60,
+            dim_after: Some(Duration::from_secs(90)),
+            dim_brightness: 10,
+            night: None,
+            ..legacy_settings()
+        };
+        assert_legacy(&Preset::decode(&written_by(2)).unwrap(), settings);
+    }
+
+    #[test]
+    fn version_3_keeps_its_volume() {
+        let settings = Settings {
+            brightness: 60,
+            dim_after: Some(Duration::from_secs(90)),
+            dim_brightness: 10,
+            night: None,
+            volume: 25,
+            ..legacy_settings()
+        };
+        assert_legacy(&Preset::decode(&written_by(3)).unwrap(), settings);
+    }
+
+    #[test]
+    fn version_5_keeps_its_mute_and_gets_the_default_colors() {
+        let settings = Settings {
+            brightness: 60,
+            dim_after: Some(Duration::from_secs(90)),
+            dim_brightness: 10,
+            night: None,
+            volume: 25,
+            muted: true,
+            ..legacy_settings()
+        };
+        let decoded = Preset::decode(&written_by(5)).unwrap();
+        assert_legacy(&decoded, settings);
+        assert_legacy_colors(&decoded);
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        let decoded = Preset::decode(&written_by(6)).unwrap();
+        assert_eq!(decoded.palette, Palette { work: Rgb565::GREEN, rest: Rgb565::MAGENTA });
+        assert_eq!(decoded.encode(), written_by(6));
+
+        let mut preset = sprint();
+        preset.palette = Palette { work: Rgb565::new(1, 2, 3), rest: Rgb565::new(31, 63, 31) };
+        assert_same(&Preset::decode(&preset.encode()).unwrap(), &preset);
+    }
+
+    #[test]
+    fn the_current_layout_only_appends() {
+        let mut preset = Preset::decode(&written_by(5)).unwrap();
+        preset.palette = Palette { work: Rgb565::GREEN, rest: Rgb565::MAGENTA };
+        assert_eq!(preset.encode(), written_by(PRESET_VERSION));
+    }
+
+    #[test]
+    fn store_from_version_1_firmware_is_read_and_upgraded() {
+        let mut store = PresetStore::new(MemoryStorage::<STORE_SIZE>::default());
+        let mut header = [0; HEADER_SIZE];
+        header[..5].copy_from_slice(&[b'T', b'S', 1, 0, 1]);
+        store.storage.write(0, &header).unwrap();
+        store.storage.write(HEADER_SIZE as u32, &written_by(1)).unwrap();
+
+        let (presets, last_used) = store.load();
+        assert_eq!((presets.len(), last_used), (1, 0));
+        assert_legacy(&presets[0], legacy_settings());
+
+        store.save(&presets, 0).unwrap();
+        let mut bytes = [0; PRESET_SIZE];
+        store.storage.read(HEADER_SIZE as u32, &mut bytes).unwrap();
+        assert_eq!(bytes[1], PRESET_VERSION);
+        store.storage.read(0, &mut header).unwrap();
+        assert_eq!(header[2], PRESET_VERSION);
+        assert_legacy(&store.load().0[0], legacy_settings());
+    }
+
+    #[test]
+    fn store_falls_back_to_builtins() {
+        let mut store = PresetStore::new(MemoryStorage::<STORE_SIZE>::default());
+        let (presets, last_used) = store.load();
+        assert_eq!(presets.len(), Preset::builtin().len());
+        assert_eq!(last_used, 0);
+
+        assert_eq!(store.saved_time(), None);
+
+        store.set_last_used(2).unwrap();
+        let (presets, last_used) = store.load();
+        assert_eq!(presets.len(), Preset::builtin().len());
+        assert_eq!(last_used, 2);
+        assert_eq!(store.saved_time(), None);
+
+        let mut store = PresetStore::new(MemoryStorage::<STORE_SIZE>::default());
+        store.save_time(1_760_000_000).unwrap();
+        assert_eq!(store.saved_time(), Some(1_760_000_000));
+        assert_eq!(store.load().0.len(), Preset::builtin().len());
+    }
+
+    #[test]
+    fn store_keeps_presets_and_the_last_used() {
+        let mut store = PresetStore::new(MemoryStorage::<STORE_SIZE>::default());
+        store.save(&[Preset::builtin()[0].clone(), sprint()], 1).unwrap();
+        store.save_time(1_760_000_000).unwrap();
+        store.set_last_used(1).unwrap();
+        assert_eq!(store.saved_time(), Some(1_760_000_000));
+        let (presets, last_used) = store.load();
+        assert_eq!(presets.len(), 2);
+        assert_same(&presets[1], &sprint());
+        assert_eq!(last_used, 1);
+
+        // A header from newer firmware is still read
+        let mut header = [0; HEADER_SIZE];
+        store.storage.read(0, &mut header).unwrap();
+        header[2] = PRESET_VERSION + 1;
+        store.storage.write(0, &header).unwrap();
+        assert_eq!(store.load().0.len(), 2);
+    }
+
+    #[test]
+    fn partition_stays_in_its_window() {
+        let mut partition: Partition<_, 16> = Partition::new(MemoryStorage::<64>::default(), 32);
+        partition.write(4, &[1, 2, 3]).unwrap();
+        assert_eq!(partition.write(14, &[0; 3]), Err(PartitionError::OutOfBounds));
+
+        let mut bytes = [0; 3];
+        partition.storage.read(36, &mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+        partition.read(4, &mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+        assert_eq!(partition.read(15, &mut [0; 2]), Err(PartitionError::OutOfBounds));
+        assert_eq!(partition.capacity(), 16);
+    }
+}
@@ -0,0 +1,104 @@
+use embassy_futures::select::{select, Either};
+use embassy_time::{Instant, Timer};
+use esp_hal::{
+    gpio::AnyPin,
+    ledc::{
+        channel::{self, config::PinConfig, ChannelIFace},
+        timer::{self, config::Duty, LSClockSource, TimerIFace},
+        Ledc,
+        LowSpeed
+    },
+    time::RateExtU32
+};
+
+use crate::{backlight::{BacklightNotifier, Fade}, buzzer::BuzzerNotifier, constants::BACKLIGHT_STEP, melody::{Sequencer, ToneCommand}};
+
+// PWM on the backlight pin, stepping through fades every BACKLIGHT_STEP and idle otherwise
+#[embassy_executor::task]
+pub(crate) async fn backlight_loop(notifier: &'static BacklightNotifier, ledc: &'static Ledc<'static>, pin: AnyPin) -> ! {
+    let mut pwm_timer = ledc.timer::<LowSpeed>(timer::Number::Timer0);
+    pwm_timer.configure(timer::config::Config {
+        duty: Duty::Duty10Bit,
+        clock_source: LSClockSource::APBClk,
+        // Above hearing and far above flicker
+        frequency: 24.kHz()
+    }).unwrap();
+    let mut channel = ledc.channel(channel::Number::Channel0, pin);
+    channel.configure(channel::config::Config {
+        timer: &pwm_timer,
+        duty_pct: 100,
+        pin_config: PinConfig::PushPull
+    }).unwrap();
+
+    let mut fade = Fade::steady(100, Instant::now());
+    loop {
+        let now = Instant::now();
+        let _ = channel.set_duty(fade.level(now));
+        let level = if fade.is_done(now) {
+            notifier.wait().await
+        } else {
+            match select(notifier.wait(), Timer::after(BACKLIGHT_STEP)).await {
+                Either::First(level) => level,
+                Either::Second(()) => continue
+            }
+        };
+        fade = fade.towards(level, Instant::now());
+    }
+}
+
+/*
+ * Square wave on a piezo through its own LEDC timer and channel.
+ * The timer can't be retuned while a channel refers to it, so both are set up again for every note.
+ * Neither stops the output when dropped, the wave keeps going until the next command.
+ */
+fn output(ledc: &Ledc<'static>, pin: &mut AnyPin, frequency: u16, duty_pct: u8) {
+    let mut pwm_timer = ledc.timer::<LowSpeed>(timer::Number::Timer1);
+    let configured = pwm_timer.configure(timer::config::Config {
+        duty: Duty::Duty10Bit,
+        clock_source: LSClockSource::APBClk,
+        frequency: u32::from(frequency).Hz()
+    });
+    if configured.is_err() {
+        log::warn!("can't play {} Hz", frequency);
+        return
+    }
+    let mut channel = ledc.channel(channel::Number::Channel1, pin);
+    let _ = channel.configure(channel::config::Config {
+        timer: &pwm_timer,
+        duty_pct,
+        pin_config: PinConfig::PushPull
+    });
+}
+
+// Plays alerts as they come, a new one cuts the current tune short
+#[embassy_executor::task]
+pub(crate) async fn buzzer_loop(notifier: &'static BuzzerNotifier, ledc: &'static Ledc<'static>, mut pin: AnyPin) -> ! {
+    let mut next = None;
+    loop {
+        let alert = match next.take() {
+            Some(alert) => alert,
+            None => notifier.wait().await
+        };
+
+        let mut frequency = 1000;
+        for command in Sequencer::new(alert.tune.melody(), alert.volume) {
+            let duration = match command {
+                ToneCommand::Tone { frequency: tone, volume, duration } => {
+                    frequency = tone;
+                    // Loudest at half duty, so volume scales from 0 to 50%
+                    output(ledc, &mut pin, frequency, volume.min(100) / 2);
+                    duration
+                }
+                ToneCommand::Silence(duration) => {
+                    output(ledc, &mut pin, frequency, 0);
+                    duration
+                }
+            };
+            if let Either::First(alert) = select(notifier.wait(), Timer::after(duration)).await {
+                next = Some(alert);
+                break
+            }
+        }
+        output(ledc, &mut pin, frequency, 0);
+    }
+}
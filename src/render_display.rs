@@ -50,7 +50,7 @@ async fn inner_render_loop(
     notifier: &'static TFTNotifier
 ) -> ! {
     let mut panel = Panel::default();
-    let mut frame_ticker = Ticker::every(Duration::from_hz(FRAME_RATE));
+    let mut frame_ticker = Ticker::every(Duration::from_hz(FRAME_RATE as u64));
     'outer: loop {
 
         // Hybrid Rendering System
@@ -82,7 +82,9 @@ async fn inner_render_loop(
                     notifier.wait()
                 ).await;
 
-            // TODO: call tft render_next_frame on all animated elements
+            if let Either::First(()) = sleep30hz_or_signal {
+                tft.render_next_frame();
+            }
 
             // if a new payload was recieved before the next draw frame (30fps), 
             // start loop with new payload
@@ -1,8 +1,7 @@
 use core::{default, ops::Index};
 
-use embedded_graphics::{pixelcolor::Rgb565, prelude::{PixelColor, RgbColor}, primitives::Rectangle, Drawable};
-use heapless::Vec;
-use crate::{animations::{Animation, AnimationEvent, AnimationState, FrameType}, clickable::ClickableElement};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::{PixelColor, Point, RgbColor}, primitives::Rectangle, Drawable};
+use crate::{constants::MAX_ANIMATIONS, animations::{Animation, AnimationEvent, AnimationState, FrameType}, clickable::ClickableElement};
 
 #[derive(Default, Debug, Clone, Copy)]
 pub enum Scene {
@@ -10,6 +9,7 @@ pub enum Scene {
     ConfigTaro,
     ConfigTaroPlus,
     ConfigCountingUp,
+    ConfigInterval,
 }
 
 pub trait UINode {
@@ -26,6 +26,24 @@ pub enum UIType {
     TextBox
 }
 
+impl UIType {
+    // Menus and text boxes are drawn but can't be tapped
+    pub fn node(&self) -> Option<&dyn UINode> {
+        match self {
+            UIType::Clickable(element) => Some(element),
+            UIType::Digits(element) => Some(element),
+            UIType::Menu() | UIType::TextBox => None
+        }
+    }
+}
+
+// Index of the element a tap lands on, found through each node's own rectangle
+pub fn element_at(elements: &[UIType], point: Point) -> Option<usize> {
+    elements
+        .iter()
+        .position(|element| element.node().is_some_and(|node| node.get_position().contains(point)))
+}
+
 pub enum UIAction {
     Back,
     Select,
@@ -52,8 +70,8 @@ impl SceneManager {
         self.current_scene = new_scene;
     }
 
-    pub fn play_next(&mut self) -> [FrameType; 6] {
-        let mut frames = Vec::<FrameType, 6>::new();
+    pub fn play_next(&mut self) -> [FrameType; MAX_ANIMATIONS] {
+        let mut frames = [FrameType::Empty; MAX_ANIMATIONS];
         for ( index, animation ) in self.animation_queue
             .queue
             .iter_mut()
@@ -81,7 +99,7 @@ impl SceneManager {
                 }
             }
         }
-        frames.into_array().unwrap()
+        frames
     }
 }
 
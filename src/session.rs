@@ -0,0 +1,352 @@
+use embassy_executor::{SpawnError, Spawner};
+use embassy_futures::select::{select, Either};
+use embassy_sync::{channel::Channel, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::Read;
+use esp_hal::{gpio::AnyPin, ledc::Ledc};
+use crate::{backlight::{BacklightLevel, BacklightNotifier}, buzzer::{Alert, BuzzerNotifier}, battery::{BatteryGauge, BatteryVoltage, ChargeState}, board::{BatteryChannel, BoardButton, BoardConsole, BoardEncoder, ChargerPins, TouchPins}, button::{ButtonId, PressDuration}, console::{ConsoleCommand, LineReader}, clock_util::{DeviceOutputs, DeviceState, Outcome, SessionMode, SessionNotice, SessionOuterNotifier, SessionState}, draw_panels::{Panel, PanelPosition, Payload}, power::{PowerControl, SleepGate, WakeSource}, constants::BATTERY_INTERVAL, presets::{PresetStorage, PresetStore}, pwm::{backlight_loop, buzzer_loop}, render_display::{TFTNotifier, TFTRender}, settings::Settings, state_machine::SessionEvent, tft::TFT, time_util::DisplayPrecision, touch::{Ft6206, GestureRecognizer, POLL_INTERVAL}};
+
+/*
+ * The session as the firmware runs it: device_loop owns the DeviceState and
+ * the input tasks feed it notices from the board's buttons, encoder and touch panel.
+ */
+
+pub type SessionNotifier = (SessionOuterNotifier, TFTNotifier, SleepGate, BacklightNotifier, BuzzerNotifier);
+
+#[derive(Clone, Copy)]
+pub struct DoubleTimerSession<'spi>(&'spi SessionOuterNotifier, &'spi SleepGate, &'spi BacklightNotifier, &'spi BuzzerNotifier);
+//{
+//    tft: TFT<'spi>,
+//    work_clock: Duration,
+//    break_clock: Duration,
+//    session_state: SessionState,
+//    spawner: Spawner
+//}
+
+impl<'spi> DoubleTimerSession<'spi> {
+    pub fn new(
+        tft: TFT<'static>,
+        spawner: Spawner,
+        notifier: &'static SessionNotifier,
+        presets: PresetStore<PresetStorage>
+    ) -> Result<Self, SpawnError> {
+        let (outer_notifier, tft_notifier, sleep_gate, backlight_notifier, buzzer_notifier) = notifier;
+        let renderer = TFTRender::new(tft, tft_notifier, spawner)?;
+        let outputs = SessionOutputs { renderer, sleeper: sleep_gate, backlight: backlight_notifier, buzzer: buzzer_notifier };
+        spawner.spawn(device_loop(outer_notifier, outputs, presets))?;
+        Ok(Self(outer_notifier, sleep_gate, backlight_notifier, buzzer_notifier))
+    }
+
+    pub async fn send_event(&self, event: SessionEvent) {
+        self.0.send(SessionNotice::Event(event)).await;
+    }
+
+    // Goes through the button map, so the same press can mean different things per button
+    pub async fn send_press(&self, button: ButtonId, press: PressDuration) {
+        self.0.send(SessionNotice::Press(button, press)).await;
+    }
+
+    pub async fn set_mode(&self, mode: SessionMode) {
+        self.0.send(SessionNotice::SetMode(mode)).await;
+    }
+
+    // Adds (or with negative minutes removes) time from the work or break total
+    pub async fn adjust_timer(&self, timer: SessionState, minutes: i32) {
+        self.0.send(SessionNotice::AdjustTimer(timer, minutes)).await;
+    }
+
+    // Sets the wall clock to UTC seconds since the Unix epoch
+    pub async fn set_wall_clock(&self, utc_seconds: u64) {
+        self.0.send(SessionNotice::SetWallClock(utc_seconds)).await;
+    }
+
+    pub async fn open_clock_settings(&self) {
+        self.0.send(SessionNotice::OpenClockSettings).await;
+    }
+
+    pub async fn open_preset_picker(&self) {
+        self.0.send(SessionNotice::OpenPresetPicker).await;
+    }
+
+    // Resolves once the panel is off and the CPU may be put to sleep
+    pub async fn wait_for_sleep(&self) {
+        self.1.requested().await;
+    }
+
+    // False when an input called the sleep off, the CPU has to stay up then
+    pub async fn arm_wake_sources(&self) -> bool {
+        self.1.arm().await
+    }
+
+    // Once the CPU is back up, or the sleep was called off
+    pub fn disarm_wake_sources(&self) {
+        self.1.disarm();
+    }
+
+    // `missed` is the time Instant didn't count while the CPU slept
+    pub async fn woke(&self, missed: Duration) {
+        self.0.send(SessionNotice::Woke(missed)).await;
+    }
+
+    // Prints the recorded inputs over serial, for attaching to bug reports
+    pub async fn dump_inputs(&self) {
+        self.0.send(SessionNotice::DumpInputs).await;
+    }
+
+    // Logs the daily totals and goal progress as CSV
+    pub async fn export_history(&self) {
+        self.0.send(SessionNotice::ExportHistory).await;
+    }
+
+    pub async fn set_settings(&self, settings: Settings) {
+        self.0.send(SessionNotice::SetSettings(settings)).await;
+    }
+
+    pub async fn set_precision(&self, precision: DisplayPrecision) {
+        self.0.send(SessionNotice::SetPrecision(precision)).await;
+    }
+
+    pub fn attach_encoder(&self, encoder: BoardEncoder, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(encoder_loop(self.0, encoder, self.1.wake_source()))
+    }
+
+    pub fn attach_encoder_switch(&self, switch: BoardButton, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(switch_loop(self.0, switch, self.1.wake_source()))
+    }
+
+    pub fn attach_console(&self, console: BoardConsole, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(console_loop(*self, console))
+    }
+
+    pub fn attach_touch(&self, touch: TouchPins, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(touch_loop(self.0, touch, self.1.wake_source()))
+    }
+
+    pub fn attach_battery(&self, battery: BatteryChannel, charger: Option<ChargerPins>, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(battery_loop(self.0, battery, charger))
+    }
+
+    // The backlight and the buzzer each take their own timer and channel of the one LEDC
+    pub fn attach_backlight(&self, ledc: &'static Ledc<'static>, pin: AnyPin, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(backlight_loop(self.2, ledc, pin))
+    }
+
+    pub fn attach_buzzer(&self, ledc: &'static Ledc<'static>, pin: AnyPin, spawner: Spawner) -> Result<(), SpawnError>
+    where
+        'spi: 'static
+    {
+        spawner.spawn(buzzer_loop(self.3, ledc, pin))
+    }
+
+    #[must_use]
+    pub const fn notifier() -> SessionNotifier {
+        (Channel::new(), TFTRender::notifier(), SleepGate::new(), Signal::new(), Signal::new())
+    }
+
+}
+
+// The render task and the signals device_loop drives
+struct SessionOutputs {
+    renderer: TFTRender<'static>,
+    sleeper: &'static SleepGate,
+    backlight: &'static BacklightNotifier,
+    buzzer: &'static BuzzerNotifier
+}
+
+// Blanks the panel through the render task and hands the CPU over to the task that owns the wake button
+impl PowerControl for SessionOutputs {
+    fn blank_display(&mut self) {
+        self.renderer.render(Panel(PanelPosition::FullScreen, Payload::Sleep));
+        self.backlight.signal(BacklightLevel::OFF);
+    }
+
+    fn light_sleep(&mut self) {
+        self.sleeper.request();
+    }
+
+    fn cancel_sleep(&mut self) {
+        self.sleeper.cancel();
+    }
+}
+
+impl DeviceOutputs for SessionOutputs {
+    fn render(&mut self, panel: Panel) {
+        self.renderer.render(panel);
+    }
+
+    fn backlight(&mut self, level: BacklightLevel) {
+        self.backlight.signal(level);
+    }
+
+    fn alert(&mut self, alert: Alert) {
+        self.buzzer.signal(alert);
+    }
+}
+
+// The same DeviceState::update as SessionReplay, with the timer and the notices coming from the board
+#[embassy_executor::task]
+async fn device_loop(
+    session_notifier: &'static SessionOuterNotifier,
+    mut outputs: SessionOutputs,
+    presets: PresetStore<PresetStorage>) -> !
+{
+    let mut device = DeviceState::new(presets, Instant::now());
+    device.boot(Instant::now());
+
+    loop {
+        let notification = match device.update(&mut outputs, Instant::now()) {
+            Some(sleep_dur) => match select(session_notifier.receive(), Timer::after(sleep_dur)).await {
+                Either::First(notification) => notification,
+                Either::Second(()) => continue
+            },
+            None => session_notifier.receive().await
+        };
+        log_outcome(notification.apply(&mut device, Instant::now()));
+    }
+}
+
+fn log_outcome(outcome: Outcome) {
+    match outcome {
+        Outcome::Handled => (),
+        Outcome::Stepped(step) => log::debug!("{:?} -> {:?}", step.from, step.to),
+        Outcome::Rejected(event, state) => log::debug!("{:?} ignored while {:?}", event, state),
+        Outcome::Undone { from, to } => log::debug!("undo {:?} -> {:?}", from, to),
+        Outcome::NothingToUndo => log::debug!("nothing to undo")
+    }
+}
+
+// Each input task arms its own pins when the CPU is about to sleep, it's the only one that can reach them
+#[embassy_executor::task]
+async fn encoder_loop(
+    session_notifier: &'static SessionOuterNotifier,
+    mut encoder: BoardEncoder,
+    mut wake: WakeSource<'static>) -> !
+{
+    loop {
+        match select(encoder.wait_for_steps(), wake.sleeping()).await {
+            Either::First(steps) => {
+                wake.input();
+                session_notifier.send(SessionNotice::Scroll(steps)).await;
+            }
+            Either::Second(()) => {
+                encoder.wake_on_turn(true);
+                wake.armed().await;
+                encoder.wake_on_turn(false);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn touch_loop(
+    session_notifier: &'static SessionOuterNotifier,
+    touch: TouchPins,
+    mut wake: WakeSource<'static>) -> !
+{
+    let TouchPins { bus, mut interrupt } = touch;
+    let mut controller = Ft6206::new(bus);
+    if controller.hold_interrupt().await.is_err() {
+        log::warn!("touch controller didn't take the interrupt mode, a short tap may not wake the chip");
+    }
+    let mut gestures = GestureRecognizer::new();
+    loop {
+        if let Either::Second(()) = select(Timer::after(POLL_INTERVAL), wake.sleeping()).await {
+            interrupt.wake_on_touch(true);
+            wake.armed().await;
+            interrupt.wake_on_touch(false);
+            continue
+        }
+        // A failed read counts as a lifted finger
+        let touch = controller.read().await.unwrap_or(None);
+        // A finger on the panel is an input well before it makes a gesture
+        if touch.is_some() {
+            wake.input();
+        }
+        if let Some(gesture) = gestures.update(touch, Instant::now()) {
+            session_notifier.send(SessionNotice::Touch(gesture)).await;
+        }
+    }
+}
+
+// Lines typed into the serial console, see ConsoleCommand for what they can be
+#[embassy_executor::task]
+async fn console_loop(session: DoubleTimerSession<'static>, mut console: BoardConsole) -> ! {
+    let mut reader = LineReader::new();
+    let mut bytes = [0; 16];
+    loop {
+        // The USB serial read can't fail
+        let Ok(count) = console.read(&mut bytes).await;
+        for byte in &bytes[..count] {
+            match reader.push(*byte) {
+                Some(ConsoleCommand::SetTime(utc_seconds)) => session.set_wall_clock(utc_seconds).await,
+                Some(ConsoleCommand::OpenClockSettings) => session.open_clock_settings().await,
+                Some(ConsoleCommand::OpenPresetPicker) => session.open_preset_picker().await,
+                Some(ConsoleCommand::ExportHistory) => session.export_history().await,
+                Some(ConsoleCommand::DumpInputs) => session.dump_inputs().await,
+                Some(ConsoleCommand::Event(event)) => session.send_event(event).await,
+                None => ()
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn switch_loop(
+    session_notifier: &'static SessionOuterNotifier,
+    mut switch: BoardButton,
+    mut wake: WakeSource<'static>) -> !
+{
+    loop {
+        match select(switch.press_duration(), wake.sleeping()).await {
+            Either::First(press) => {
+                wake.input();
+                session_notifier.send(SessionNotice::Press(ButtonId::EncoderSwitch, press)).await;
+            }
+            Either::Second(()) => {
+                switch.wake_on_press(true);
+                wake.armed().await;
+                switch.wake_on_press(false);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn battery_loop(
+    session_notifier: &'static SessionOuterNotifier,
+    mut battery: BatteryChannel,
+    charger: Option<ChargerPins>) -> !
+{
+    let mut gauge = BatteryGauge::new();
+    let mut shown = None;
+    loop {
+        let charge = charger.as_ref().map_or(ChargeState::Discharging, ChargerPins::state);
+        if let Some(millivolts) = battery.read_millivolts() {
+            let status = gauge.update(millivolts, charge);
+            // Only wake device_loop when the icon would actually change
+            if shown != Some(status) {
+                shown = Some(status);
+                session_notifier.send(SessionNotice::Battery(status)).await;
+            }
+        }
+        Timer::after(BATTERY_INTERVAL).await;
+    }
+}
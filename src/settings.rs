@@ -0,0 +1,72 @@
+use embassy_time::Duration;
+
+use crate::constants::{BRIGHTNESS, DAILY_GOAL, DIM_AFTER, DIM_BRIGHTNESS, IDLE_PROMPT, IDLE_TIMEOUT, NIGHT_BRIGHTNESS, NIGHT_END_HOUR, NIGHT_START_HOUR, ROLLOVER_HOUR, UNDO_WINDOW, VOLUME};
+
+// Local hours during which the backlight is held low
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NightSchedule {
+    pub start_hour: u8,
+    pub end_hour: u8,
+    pub brightness: u8
+}
+
+impl NightSchedule {
+    // Schedules usually run past midnight, like 22 to 7
+    pub const fn covers(&self, hour: u8) -> bool {
+        if self.start_hour <= self.end_hour {
+            self.start_hour <= hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+// User adjustable behaviour of the session, sent to device_loop as a whole
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    // How far back a transition can still be undone
+    pub undo_window: Duration,
+    // Time without input while working before asking if anyone is there, None turns it off
+    pub idle_timeout: Option<Duration>,
+    // How long the prompt counts down before the session pauses itself
+    pub idle_prompt: Duration,
+    // Local hour at which the day's totals are archived and reset
+    pub rollover_hour: u8,
+    // Fixed offset of local time from UTC
+    pub utc_offset_minutes: i16,
+    // Work time to aim for each day, None hides the progress bar
+    pub daily_goal: Option<Duration>,
+    // Buzzer loudness in percent
+    pub volume: u8,
+    // Silences the buzzer without forgetting the volume
+    pub muted: bool,
+    // Backlight in percent while in use
+    pub brightness: u8,
+    // Time without input before the backlight dims to `dim_brightness`, None keeps it up
+    pub dim_after: Option<Duration>,
+    pub dim_brightness: u8,
+    pub night: Option<NightSchedule>
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            undo_window: UNDO_WINDOW,
+            idle_timeout: Some(IDLE_TIMEOUT),
+            idle_prompt: IDLE_PROMPT,
+            rollover_hour: ROLLOVER_HOUR,
+            utc_offset_minutes: 0,
+            daily_goal: Some(DAILY_GOAL),
+            volume: VOLUME,
+            muted: false,
+            brightness: BRIGHTNESS,
+            dim_after: Some(DIM_AFTER),
+            dim_brightness: DIM_BRIGHTNESS,
+            night: Some(NightSchedule {
+                start_hour: NIGHT_START_HOUR,
+                end_hour: NIGHT_END_HOUR,
+                brightness: NIGHT_BRIGHTNESS
+            })
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use heapless::Vec;
+
+use crate::{button::{ButtonId, PressDuration}, clock_util::SessionState::{self, Break, Paused, Working}};
+use SessionEvent::{IdleTimeout, LongPress, ShortPress, TimerExpired};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    ShortPress,
+    LongPress,
+    // A countdown segment ran out
+    TimerExpired,
+    SerialCommand(SerialCommand),
+    IdleTimeout,
+    // Take back the most recent transition
+    Undo,
+    // Confirms the highlighted choice in menus
+    Select,
+    // Opens the menus, or moves on to the next one
+    Menu
+}
+
+impl From<PressDuration> for SessionEvent {
+    fn from(press: PressDuration) -> Self {
+        match press {
+            PressDuration::Short => Self::ShortPress,
+            PressDuration::Long => Self::LongPress
+        }
+    }
+}
+
+// The events a button's short and long presses stand for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonMapping {
+    pub short: SessionEvent,
+    pub long: SessionEvent
+}
+
+impl ButtonMapping {
+    pub const fn event(&self, press: PressDuration) -> SessionEvent {
+        match press {
+            PressDuration::Short => self.short,
+            PressDuration::Long => self.long
+        }
+    }
+}
+
+/*
+ * By default the main button starts and stops the timers,
+ * while the encoder switch selects in menus and opens them with a long press.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonMap {
+    pub main: ButtonMapping,
+    pub encoder_switch: ButtonMapping
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        Self {
+            main: ButtonMapping { short: ShortPress, long: LongPress },
+            encoder_switch: ButtonMapping { short: SessionEvent::Select, long: SessionEvent::Menu }
+        }
+    }
+}
+
+impl ButtonMap {
+    pub const fn event(&self, button: ButtonId, press: PressDuration) -> SessionEvent {
+        match button {
+            ButtonId::Main => self.main.event(press),
+            ButtonId::EncoderSwitch => self.encoder_switch.event(press)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialCommand {
+    Work,
+    Break,
+    Pause
+}
+
+// Extra condition a transition needs on top of matching state and event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guard {
+    Always,
+    // Only while paused, and only if the pause interrupted this state
+    PausedFrom(SessionState)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionAction {
+    LogHistory,
+    SoundAlert,
+    // Ends editing the totals
+    CloseEdit
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    pub from: SessionState,
+    pub event: SessionEvent,
+    pub guard: Guard,
+    pub to: SessionState,
+    pub actions: &'static [SessionAction]
+}
+
+const fn transition(
+    from: SessionState,
+    event: SessionEvent,
+    guard: Guard,
+    to: SessionState,
+    actions: &'static [SessionAction]) -> Transition
+{
+    Transition { from, event, guard, to, actions }
+}
+
+/*
+ * Rows are checked in order and the first one whose state, event and guard match wins.
+ * Serial commands are handled separately since they apply from every state.
+ * A short press resumes whatever the pause interrupted, a long press always goes to the break.
+ */
+pub const TRANSITIONS: [Transition; 11] = [
+    transition(Working, ShortPress, Guard::Always, Break, &[]),
+    transition(Working, LongPress, Guard::Always, Paused, &[]),
+    transition(Working, TimerExpired, Guard::Always, Break, &[SessionAction::SoundAlert]),
+    transition(Working, IdleTimeout, Guard::Always, Paused, &[SessionAction::SoundAlert]),
+
+    transition(Break, ShortPress, Guard::Always, Working, &[]),
+    transition(Break, LongPress, Guard::Always, Paused, &[]),
+    transition(Break, TimerExpired, Guard::Always, Working, &[SessionAction::SoundAlert]),
+    transition(Break, IdleTimeout, Guard::Always, Paused, &[]),
+
+    transition(Paused, ShortPress, Guard::PausedFrom(Break), Break, &[]),
+    transition(Paused, ShortPress, Guard::Always, Working, &[]),
+    transition(Paused, LongPress, Guard::Always, Break, &[]),
+];
+
+pub const fn on_entry(state: SessionState) -> &'static [SessionAction] {
+    match state {
+        Working | Break | Paused => &[SessionAction::LogHistory]
+    }
+}
+
+// An edit started while a timer was running is about that timer, so it ends when the timer stops
+pub const fn on_exit(state: SessionState) -> &'static [SessionAction] {
+    match state {
+        Working | Break => &[SessionAction::CloseEdit],
+        Paused => &[]
+    }
+}
+
+// Upper bound of exit + transition + entry actions for a single step
+pub const MAX_STEP_ACTIONS: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub from: SessionState,
+    pub to: SessionState,
+    pub actions: Vec<SessionAction, MAX_STEP_ACTIONS>
+}
+
+// Pure state machine; the caller performs the returned actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateMachine {
+    state: SessionState,
+    paused_from: SessionState
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new(SessionState::default())
+    }
+}
+
+impl StateMachine {
+    pub const fn new(state: SessionState) -> Self {
+        Self {
+            state,
+            paused_from: state
+        }
+    }
+
+    pub const fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub const fn paused_from(&self) -> SessionState {
+        self.paused_from
+    }
+
+    // Puts the machine back as it was before a transition, without running any actions
+    pub fn restore(&mut self, state: SessionState, paused_from: SessionState) {
+        self.state = state;
+        self.paused_from = paused_from;
+    }
+
+    fn guard_holds(&self, guard: Guard) -> bool {
+        match guard {
+            Guard::Always => true,
+            Guard::PausedFrom(state) => self.state == Paused && self.paused_from == state
+        }
+    }
+
+    pub fn find(&self, event: SessionEvent) -> Option<Transition> {
+        TRANSITIONS
+            .iter()
+            .find(|row| row.from == self.state && row.event == event && self.guard_holds(row.guard))
+            .copied()
+    }
+
+    // Returns None when the event doesn't apply to the current state
+    pub fn handle(&mut self, event: SessionEvent) -> Option<Step> {
+        if let SessionEvent::SerialCommand(command) = event {
+            let to = match command {
+                SerialCommand::Work => Working,
+                SerialCommand::Break => Break,
+                SerialCommand::Pause => Paused
+            };
+            return (to != self.state).then(|| self.force(to))
+        }
+
+        let transition = self.find(event)?;
+        Some(self.enter(transition.to, transition.actions))
+    }
+
+    // Moves to `to` without looking at the table, still running exit and entry actions
+    pub fn force(&mut self, to: SessionState) -> Step {
+        self.enter(to, &[])
+    }
+
+    fn enter(&mut self, to: SessionState, transition_actions: &[SessionAction]) -> Step {
+        let from = self.state;
+        let mut actions = Vec::new();
+        for action in on_exit(from)
+            .iter()
+            .chain(transition_actions)
+            .chain(on_entry(to)) {
+            let _ = actions.push(*action);
+        }
+
+        if to == Paused {
+            self.paused_from = from;
+        }
+        self.state = to;
+        Step { from, to, actions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Guard, SerialCommand, SessionAction, SessionEvent, StateMachine, Step, TRANSITIONS};
+    use crate::clock_util::SessionState::{self, Break, Paused, Working};
+    use SessionEvent::{IdleTimeout, LongPress, ShortPress, TimerExpired};
+
+    fn step(from: SessionState, event: SessionEvent) -> Option<Step> {
+        StateMachine::new(from).handle(event)
+    }
+
+    #[test]
+    fn buttons_follow_the_table() {
+        let expected = [
+            (Working, ShortPress, Break),
+            (Working, LongPress, Paused),
+            (Break, ShortPress, Working),
+            (Break, LongPress, Paused),
+            (Paused, ShortPress, Working),
+            (Paused, LongPress, Break)
+        ];
+        for (from, event, to) in expected {
+            assert_eq!(step(from, event).map(|step| step.to), Some(to), "{from:?} {event:?}");
+        }
+    }
+
+    #[test]
+    fn pause_resumes_what_it_interrupted() {
+        for interrupted in [Working, Break] {
+            let mut machine = StateMachine::new(interrupted);
+            machine.handle(LongPress).unwrap();
+            assert_eq!(machine.paused_from(), interrupted);
+            let mut resumed = machine;
+            assert_eq!(resumed.handle(ShortPress).unwrap().to, interrupted);
+            assert_eq!(machine.handle(LongPress).unwrap().to, Break);
+        }
+        // A machine that starts out paused has nothing to go back to
+        assert_eq!(step(Paused, ShortPress).unwrap().to, Working);
+    }
+
+    #[test]
+    fn expiry_and_idle_timeout() {
+        assert_eq!(step(Working, TimerExpired).unwrap().to, Break);
+        assert_eq!(step(Break, TimerExpired).unwrap().to, Working);
+        assert_eq!(step(Working, IdleTimeout).unwrap().to, Paused);
+        assert_eq!(step(Break, IdleTimeout).unwrap().to, Paused);
+        // A paused session has nothing running out and nothing to time out
+        assert_eq!(step(Paused, TimerExpired), None);
+        assert_eq!(step(Paused, IdleTimeout), None);
+        // Menu events never reach the table
+        for state in [Working, Break, Paused] {
+            for event in [SessionEvent::Select, SessionEvent::Menu, SessionEvent::Undo] {
+                assert_eq!(step(state, event), None);
+            }
+        }
+    }
+
+    #[test]
+    fn steps_carry_exit_transition_and_entry_actions() {
+        use SessionAction::{CloseEdit, LogHistory, SoundAlert};
+        assert_eq!(step(Working, ShortPress).unwrap().actions, [CloseEdit, LogHistory]);
+        assert_eq!(step(Working, TimerExpired).unwrap().actions, [CloseEdit, SoundAlert, LogHistory]);
+        assert_eq!(step(Working, IdleTimeout).unwrap().actions, [CloseEdit, SoundAlert, LogHistory]);
+        assert_eq!(step(Break, IdleTimeout).unwrap().actions, [CloseEdit, LogHistory]);
+        // Nothing is running while paused, so leaving it only logs
+        assert_eq!(step(Paused, LongPress).unwrap().actions, [LogHistory]);
+    }
+
+    #[test]
+    fn serial_commands_apply_from_every_other_state() {
+        for (command, to) in [(SerialCommand::Work, Working), (SerialCommand::Break, Break), (SerialCommand::Pause, Paused)] {
+            for from in [Working, Break, Paused] {
+                let stepped = step(from, SessionEvent::SerialCommand(command)).map(|step| step.to);
+                assert_eq!(stepped, (from != to).then_some(to), "{from:?} {command:?}");
+            }
+        }
+        // Forced past the table, but still with the exit and entry actions
+        let forced = step(Working, SessionEvent::SerialCommand(SerialCommand::Pause)).unwrap();
+        assert_eq!(forced.actions, [SessionAction::CloseEdit, SessionAction::LogHistory]);
+    }
+
+    #[test]
+    fn table_has_no_unreachable_rows() {
+        // An earlier Always row for the same state and event would shadow a later one
+        for (index, row) in TRANSITIONS.iter().enumerate() {
+            let shadowed = TRANSITIONS[..index]
+                .iter()
+                .any(|earlier| earlier.from == row.from && earlier.event == row.event && earlier.guard == Guard::Always);
+            assert!(!shadowed, "row {index} can never match");
+        }
+    }
+
+    #[test]
+    fn paused_from_guard() {
+        let mut machine = StateMachine::new(Break);
+        assert!(!machine.guard_holds(Guard::PausedFrom(Break)));
+        machine.handle(LongPress).unwrap();
+        assert!(machine.guard_holds(Guard::PausedFrom(Break)));
+        assert!(!machine.guard_holds(Guard::PausedFrom(Working)));
+        machine.restore(Working, Working);
+        assert!(machine.guard_holds(Guard::Always));
+    }
+
+    #[test]
+    fn force_and_restore_skip_the_table() {
+        let mut machine = StateMachine::new(Paused);
+        let forced = machine.force(Paused);
+        assert_eq!((forced.from, forced.to), (Paused, Paused));
+        machine.restore(Break, Working);
+        assert_eq!((machine.state(), machine.paused_from()), (Break, Working));
+        // Restoring a pause brings back what it interrupted, so resuming still goes there
+        machine.restore(Paused, Break);
+        assert_eq!(machine.handle(ShortPress).unwrap().to, Break);
+    }
+}
@@ -0,0 +1,277 @@
+use core::fmt::Write;
+use embassy_time::{Duration, Instant};
+use heapless::{String, Vec};
+
+use crate::{button::PressDuration, clock_util::SessionState, constants::{FRAME_RATE, LAP_ROWS, MAX_LAPS}, draw_panels::{Panel, PanelPosition, Payload}, time_util::{format_duration_with, DisplayPrecision}};
+
+const ZERO: Duration = Duration::from_ticks(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lap {
+    pub number: u16,
+    // Time since the previous lap
+    pub lap: Duration,
+    // Time since the stopwatch was started
+    pub split: Duration
+}
+
+/*
+ * Count-up stopwatch with lap capture.
+ * Short press starts or records a lap, long press stops or resets.
+ */
+#[derive(Debug, Clone)]
+pub struct Stopwatch {
+    elapsed: Duration,
+    last_update: Instant,
+    running: bool,
+    // Oldest laps are dropped once MAX_LAPS is reached
+    laps: Vec<Lap, MAX_LAPS>,
+    lap_count: u16,
+    // Number of laps scrolled back from the newest one
+    scroll: usize
+}
+
+impl Stopwatch {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            elapsed: ZERO,
+            last_update: now,
+            running: false,
+            laps: Vec::new(),
+            lap_count: 0,
+            scroll: 0
+        }
+    }
+
+    pub fn update(&mut self, now: Instant) {
+        if self.running {
+            self.elapsed += now.saturating_duration_since(self.last_update);
+        }
+        self.last_update = now;
+    }
+
+    pub const fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub const fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn laps(&self) -> &[Lap] {
+        &self.laps
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.update(now);
+        self.running = true;
+    }
+
+    pub fn stop(&mut self, now: Instant) {
+        self.update(now);
+        self.running = false;
+    }
+
+    pub fn reset(&mut self, now: Instant) {
+        *self = Self::new(now);
+    }
+
+    pub fn lap(&mut self, now: Instant) {
+        self.update(now);
+        let previous_split = self.laps.last().map_or(ZERO, |lap| lap.split);
+        self.lap_count = self.lap_count.saturating_add(1);
+        let lap = Lap {
+            number: self.lap_count,
+            lap: self.elapsed.checked_sub(previous_split).unwrap_or(ZERO),
+            split: self.elapsed
+        };
+
+        if self.laps.is_full() {
+            self.laps.remove(0);
+        }
+        let _ = self.laps.push(lap);
+        self.scroll = 0;
+    }
+
+    pub fn press(&mut self, press: PressDuration, now: Instant) {
+        match (press, self.running) {
+            (PressDuration::Short, true) => self.lap(now),
+            (PressDuration::Short, false) => self.start(now),
+            (PressDuration::Long, true) => self.stop(now),
+            (PressDuration::Long, false) => self.reset(now)
+        }
+    }
+
+    // Positive steps scroll towards older laps
+    pub fn scroll_by(&mut self, steps: i32) {
+        let max_scroll = self.laps.len().saturating_sub(LAP_ROWS);
+        let scroll = self.scroll as i32 + steps;
+        self.scroll = scroll.clamp(0, max_scroll as i32) as usize;
+    }
+
+    // Newest visible lap first
+    pub fn visible_laps(&self) -> [Option<Lap>; LAP_ROWS] {
+        let mut rows = [None; LAP_ROWS];
+        for (row, lap) in rows
+            .iter_mut()
+            .zip(self.laps.iter().rev().skip(self.scroll)) {
+            *row = Some(*lap);
+        }
+        rows
+    }
+
+    pub fn render(&mut self, now: Instant) -> (Panel, Duration) {
+        self.update(now);
+        let frame = StopwatchFrame {
+            time: format_duration_with(self.elapsed, DisplayPrecision::Hundredths),
+            laps: self.visible_laps(),
+            lap_count: self.lap_count,
+            running: self.running
+        };
+
+        // Hundredths change faster than the panel can redraw, so refresh at the frame rate
+        let sleep_dur = if self.running {
+            Duration::from_hz(FRAME_RATE as u64)
+        } else {
+            Duration::from_secs(1)
+        };
+
+        // The lap list fills the lower panel, the running time stays on the upper one
+        (Panel(PanelPosition::Bottom, Payload::Stopwatch(frame)), sleep_dur)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StopwatchFrame {
+    pub time: [u8; 20],
+    pub laps: [Option<Lap>; LAP_ROWS],
+    pub lap_count: u16,
+    pub running: bool
+}
+
+impl StopwatchFrame {
+    pub fn label(&self) -> String<16> {
+        let mut label = String::new();
+        if self.running {
+            let _ = write!(label, "lap {}", self.lap_count.saturating_add(1));
+        } else {
+            let _ = label.push_str("stopped");
+        }
+        label
+    }
+
+    pub const fn divider_state(&self) -> SessionState {
+        if self.running {
+            SessionState::Working
+        } else {
+            SessionState::Paused
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::*;
+
+    fn running() -> Stopwatch {
+        let mut stopwatch = Stopwatch::new(Instant::from_secs(0));
+        stopwatch.start(Instant::from_secs(0));
+        stopwatch
+    }
+
+    fn numbers(rows: [Option<Lap>; LAP_ROWS]) -> [Option<u16>; LAP_ROWS] {
+        rows.map(|lap| lap.map(|lap| lap.number))
+    }
+
+    #[test]
+    fn laps_are_split_from_the_previous_one() {
+        let mut stopwatch = running();
+        stopwatch.lap(Instant::from_millis(12_340));
+        stopwatch.lap(Instant::from_millis(20_000));
+        // Stopped time counts towards neither
+        stopwatch.stop(Instant::from_secs(25));
+        stopwatch.start(Instant::from_secs(40));
+        stopwatch.lap(Instant::from_secs(45));
+
+        assert_eq!(stopwatch.laps(), [
+            Lap { number: 1, lap: Duration::from_millis(12_340), split: Duration::from_millis(12_340) },
+            Lap { number: 2, lap: Duration::from_millis(7_660), split: Duration::from_secs(20) },
+            Lap { number: 3, lap: Duration::from_secs(10), split: Duration::from_secs(30) }
+        ]);
+        assert_eq!(stopwatch.elapsed(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn the_oldest_lap_makes_room() {
+        let mut stopwatch = running();
+        for second in 1..=MAX_LAPS as u64 + 2 {
+            stopwatch.lap(Instant::from_secs(second));
+        }
+        let laps = stopwatch.laps();
+        assert_eq!(laps.len(), MAX_LAPS);
+        assert_eq!(laps[0].number, 3);
+        // Splits still follow on from the dropped laps
+        assert_eq!(laps[0].lap, Duration::from_secs(1));
+        assert_eq!(laps[MAX_LAPS - 1].split, Duration::from_secs(MAX_LAPS as u64 + 2));
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_ends_of_the_list() {
+        let mut stopwatch = running();
+        assert_eq!(numbers(stopwatch.visible_laps()), [None; LAP_ROWS]);
+        stopwatch.scroll_by(3);
+        assert_eq!(numbers(stopwatch.visible_laps()), [None; LAP_ROWS]);
+
+        for second in 1..=6 {
+            stopwatch.lap(Instant::from_secs(second));
+        }
+        assert_eq!(numbers(stopwatch.visible_laps()), [Some(6), Some(5), Some(4), Some(3)]);
+        stopwatch.scroll_by(10);
+        assert_eq!(numbers(stopwatch.visible_laps()), [Some(4), Some(3), Some(2), Some(1)]);
+        stopwatch.scroll_by(-1);
+        assert_eq!(numbers(stopwatch.visible_laps()), [Some(5), Some(4), Some(3), Some(2)]);
+        stopwatch.scroll_by(-10);
+        assert_eq!(numbers(stopwatch.visible_laps()), [Some(6), Some(5), Some(4), Some(3)]);
+
+        // A new lap scrolls back to the newest
+        stopwatch.scroll_by(2);
+        stopwatch.lap(Instant::from_secs(7));
+        assert_eq!(numbers(stopwatch.visible_laps())[0], Some(7));
+    }
+
+    #[test]
+    fn presses_start_lap_stop_and_reset() {
+        let mut stopwatch = Stopwatch::new(Instant::from_secs(0));
+        stopwatch.press(PressDuration::Short, Instant::from_secs(1));
+        assert!(stopwatch.is_running());
+
+        stopwatch.press(PressDuration::Short, Instant::from_secs(3));
+        assert!(stopwatch.is_running());
+        assert_eq!(stopwatch.laps().len(), 1);
+
+        stopwatch.press(PressDuration::Long, Instant::from_secs(4));
+        assert!(!stopwatch.is_running());
+        assert_eq!(stopwatch.elapsed(), Duration::from_secs(3));
+        assert_eq!(stopwatch.laps().len(), 1);
+
+        stopwatch.press(PressDuration::Long, Instant::from_secs(5));
+        assert!(!stopwatch.is_running());
+        assert_eq!(stopwatch.elapsed(), Duration::from_ticks(0));
+        assert!(stopwatch.laps().is_empty());
+    }
+
+    #[test]
+    fn laps_go_on_the_lower_panel() {
+        let mut stopwatch = running();
+        stopwatch.lap(Instant::from_millis(1_500));
+        let (Panel(position, Payload::Stopwatch(frame)), _) = stopwatch.render(Instant::from_secs(2)) else {
+            panic!("not the stopwatch")
+        };
+        assert!(matches!(position, PanelPosition::Bottom));
+        assert_eq!(&frame.time[..11], b"00:00:02.00");
+        assert_eq!(frame.laps[0].map(|lap| lap.number), Some(1));
+        assert_eq!(frame.label().as_str(), "lap 2");
+    }
+}
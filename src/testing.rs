@@ -0,0 +1,168 @@
+use core::{convert::Infallible, future::Future, pin::pin, task::{Context, Poll, Waker}};
+use std::sync::{Mutex, MutexGuard};
+
+use embassy_time::{Duration, Instant, MockDriver, Timer};
+use embedded_hal::{digital::{ErrorType, InputPin}, i2c::{self, ErrorKind, NoAcknowledgeSource, Operation}};
+use embedded_hal_async::{digital::Wait, i2c::I2c};
+
+// The mock driver's clock is global, so tests that wait on it take turns
+static CLOCK: Mutex<()> = Mutex::new(());
+
+// Gives up on futures that are still waiting after this much simulated time
+const RUN_LIMIT: Duration = Duration::from_secs(600);
+
+fn lock_clock() -> MutexGuard<'static, ()> {
+    CLOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/*
+ * Polls the future to completion, moving the mock clock on a millisecond after every poll that didn't finish.
+ * Returns the output along with how much simulated time it took.
+ */
+pub(crate) fn run_timed<F: Future>(future: F) -> (F::Output, Duration) {
+    let _clock = lock_clock();
+    let driver = MockDriver::get();
+    let started = Instant::now();
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return (output, Instant::now() - started)
+        }
+        assert!(Instant::now() - started < RUN_LIMIT, "still waiting after {:?}", RUN_LIMIT);
+        driver.advance(Duration::from_millis(1));
+    }
+}
+
+/*
+ * Input pin that plays back a fixed list of level changes, in milliseconds from when it was created.
+ * It reads low before the first change.
+ */
+pub(crate) struct ScriptedPin {
+    start: Instant,
+    changes: &'static [(u64, bool)]
+}
+
+impl ScriptedPin {
+    // Has to be created inside run_timed, the script starts from the mock clock's current time
+    pub(crate) fn new(changes: &'static [(u64, bool)]) -> Self {
+        Self { start: Instant::now(), changes }
+    }
+
+    fn level_at(&self, at: Instant) -> bool {
+        self.changes
+            .iter()
+            .take_while(|(ms, _)| self.start + Duration::from_millis(*ms) <= at)
+            .last()
+            .is_some_and(|(_, high)| *high)
+    }
+
+    // The first change after `after` for which `wanted` holds, given the levels before and after it
+    fn next_change(&self, after: Instant, wanted: impl Fn(bool, bool) -> bool) -> Option<Instant> {
+        let mut level = self.level_at(after);
+        for (ms, high) in self.changes {
+            let at = self.start + Duration::from_millis(*ms);
+            if at <= after {
+                continue
+            }
+            if wanted(level, *high) {
+                return Some(at)
+            }
+            level = *high;
+        }
+        None
+    }
+
+    async fn wait_for_change(&mut self, wanted: impl Fn(bool, bool) -> bool) {
+        match self.next_change(Instant::now(), wanted) {
+            Some(at) => Timer::at(at).await,
+            None => core::future::pending().await
+        }
+    }
+
+    async fn wait_for_level(&mut self, high: bool) {
+        if self.level_at(Instant::now()) != high {
+            self.wait_for_change(|_, to| to == high).await;
+        }
+    }
+}
+
+impl ErrorType for ScriptedPin {
+    type Error = Infallible;
+}
+
+impl InputPin for ScriptedPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.level_at(Instant::now()))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.level_at(Instant::now()))
+    }
+}
+
+impl Wait for ScriptedPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_change(|from, to| !from && to).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_change(|from, to| from && !to).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_change(|from, to| from != to).await;
+        Ok(())
+    }
+}
+
+/*
+ * I2C bus that answers each read with the next of a fixed list of replies, None being a device that didn't ack.
+ * Every write is kept along with its address, so tests can check what was asked for.
+ */
+pub(crate) struct MockI2c {
+    replies: std::vec::IntoIter<Option<Vec<u8>>>,
+    pub(crate) writes: Vec<(u8, Vec<u8>)>
+}
+
+impl MockI2c {
+    pub(crate) fn new(replies: &[Option<&[u8]>]) -> Self {
+        let replies: Vec<_> = replies.iter().map(|reply| reply.map(<[u8]>::to_vec)).collect();
+        Self { replies: replies.into_iter(), writes: Vec::new() }
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2c {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => self.writes.push((address, bytes.to_vec())),
+                Operation::Read(buffer) => {
+                    let reply = self.replies
+                        .next()
+                        .flatten()
+                        .ok_or(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))?;
+                    assert_eq!(reply.len(), buffer.len(), "reply doesn't fit the read");
+                    buffer.copy_from_slice(&reply);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
+use core::fmt::Write;
 use embedded_graphics_framebuf::FrameBuf;
+use heapless::String;
 use esp_backtrace as _;
 use esp_hal::gpio::Output;
 use esp_hal::Async;
 use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
 use display_interface_spi::SPIInterface;
-use ili9341::{DisplaySize240x320, Ili9341, Orientation};
-use embedded_graphics::{geometry::Point, mono_font::{MonoTextStyle, MonoTextStyleBuilder}, primitives::{ Polyline, PrimitiveStyle, StyledDrawable, Triangle}, text::{ Alignment, Baseline, TextStyleBuilder}};
+use ili9341::{DisplaySize240x320, Ili9341, ModeState, Orientation};
+use embedded_graphics::{geometry::Point, mono_font::{MonoTextStyle, MonoTextStyleBuilder}, primitives::{ Polyline, PrimitiveStyle, StyledDrawable, Triangle}, text::{ renderer::TextRenderer, Alignment, Baseline, TextStyleBuilder}};
 use esp_backtrace as _;
-use eg_seven_segment::{SevenSegmentStyleBuilder};
+use eg_seven_segment::{SevenSegmentStyle, SevenSegmentStyleBuilder};
+use embassy_time::Instant;
 use esp_hal::{
-    gpio::{GpioPin, Level},
+    gpio::Level,
     delay::Delay,
     peripherals::SPI2,
     spi::{
@@ -26,11 +29,27 @@ use embedded_graphics::{
     primitives::{PrimitiveStyleBuilder, Rectangle, StrokeAlignment},
     text::Text,
 };
-use profont::PROFONT_18_POINT;
+use profont::{PROFONT_14_POINT, PROFONT_18_POINT};
 use tinytga::Tga;
 
-use crate::{animations::{Animation, FrameType}, clock_util::SessionState, draw_panels::{Panel, PanelPosition, Payload}, scenes::SceneManager};
-use crate::constants::MAX_ANIMATIONS;
+use crate::{animations::{Animation, Celebration, FrameType}, battery::{BatteryStatus, ChargeState}, board::DisplayPins, chess_clock::{ChessFrame, ChessSide}, clock_setter::ClockFrame, clock_util::SessionState, draw_panels::{Palette, Panel, PanelPosition, Payload, PresetsFrame, TimersFrame}, goal::GoalProgress, history::StatsFrame, interval::{IntervalFrame, SegmentKind}, scenes::SceneManager, stopwatch::{Lap, StopwatchFrame}, time_util::{format_duration_with, DisplayPrecision}};
+use crate::constants::{BATTERY_LOW, BREAK_COLOR, LAP_ROWS, MAX_ANIMATIONS, PANEL_SLEEP_SETTLE, WORK_COLOR};
+
+const FLAG_COLOR: Rgb565 = Rgb565::RED;
+const GOAL_COLOR: Rgb565 = Rgb565::GREEN;
+// Progress bar between the top timer and the divider
+const GOAL_BAR: Rectangle = Rectangle::new(Point::new(30, 82), Size::new(280, 6));
+// Timer panels are 290px wide; sub-second digits need the compact style to fit
+const PANEL_WIDTH: u32 = 290;
+const FULL_SIZE_CHARS: usize = 8;
+
+// Last text drawn into a timer panel, used to only resend digits that changed
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DrawnSegments {
+    area: Rectangle,
+    style: SevenSegmentStyle<Rgb565>,
+    text: [u8; 20]
+}
 
 pub type TFTSpiDevice<'spi> = 
     ExclusiveDevice<Spi<'spi, Async>, Output<'spi>, NoDelay>;
@@ -41,26 +60,64 @@ pub type TFTSpiInterface<'spi> =
         Output<'spi>
         >;
 
+// The panel's reset line, or nothing when the board ties it to the chip's own reset
+pub struct ResetPin<'spi>(Option<Output<'spi>>);
+
+impl embedded_hal::digital::ErrorType for ResetPin<'_> {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_hal::digital::OutputPin for ResetPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        if let Some(pin) = &mut self.0 {
+            pin.set_low();
+        }
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        if let Some(pin) = &mut self.0 {
+            pin.set_high();
+        }
+        Ok(())
+    }
+}
+
+// Mode, label, clock and battery the divider was last drawn with
+type DrawnDivider = (SessionState, String<16>, Option<(u8, u8)>, Option<BatteryStatus>);
+
 // NOTE: Display Hardware
 pub struct TFT<'spi>
 {
-    pub display: Ili9341<TFTSpiInterface<'spi>, Output<'spi>>,
+    pub display: Ili9341<TFTSpiInterface<'spi>, ResetPin<'spi>>,
     pub playing_animation: bool,
     top_frame_buffer: FrameBuf<Rgb565, [Rgb565; 76800]>,
-    scene_manager: SceneManager
+    scene_manager: SceneManager,
+    // [Top, Bottom]
+    drawn_segments: [Option<DrawnSegments>; 2],
+    drawn_divider: Option<DrawnDivider>,
+    // Time of day shown in the divider, only on the timer screen
+    divider_clock: Option<(u8, u8)>,
+    // Battery icon next to the divider label, only on the timer screen
+    divider_battery: Option<BatteryStatus>,
+    // Colours of the timer screen and its divider, the default palette everywhere else
+    palette: Palette,
+    drawn_presets: Option<(usize, usize)>,
+    drawn_stats: Option<StatsFrame>,
+    drawn_laps: Option<[Option<Lap>; LAP_ROWS]>,
+    // Filled width and whether the goal was reached
+    drawn_goal: Option<(u32, bool)>,
+    // When the panel was put in Sleep In by a Sleep payload
+    asleep: Option<Instant>
 }
 
 impl<'spi> TFT<'spi> {
     pub fn new(
         spi2: SPI2,
-        sclk: GpioPin<6>,
-        miso: GpioPin<5>,
-        mosi: GpioPin<7>,
-        cs: GpioPin<2>,
-        rst: GpioPin<3>,
-        dc: GpioPin<4>
+        pins: DisplayPins
         ) -> TFT<'spi> {
-        let rst_output = Output::new(rst, Level::Low);
+        let DisplayPins { sclk, miso, mosi, cs, dc, rst } = pins;
+        let rst_output = ResetPin(rst.map(|rst| Output::new(rst, Level::Low)));
         let dc_output = Output::new(dc, Level::Low);
         let spi = Spi::new(
             spi2, 
@@ -91,12 +148,57 @@ impl<'spi> TFT<'spi> {
             display,
             playing_animation: false,
             top_frame_buffer: top_fb,
-            scene_manager: SceneManager::default()
+            scene_manager: SceneManager::default(),
+            drawn_segments: [None; 2],
+            drawn_divider: None,
+            divider_clock: None,
+            divider_battery: None,
+            palette: Palette::default(),
+            drawn_presets: None,
+            drawn_stats: None,
+            drawn_laps: None,
+            drawn_goal: None,
+            asleep: None
         }
     }
     
     pub fn clear(&mut self, color: Rgb565) {
         self.display.clear(color).unwrap();
+        self.invalidate();
+    }
+
+    // The render loop hands the same payload over again every second, so this only acts once
+    fn sleep(&mut self) {
+        if self.asleep.is_some() {
+            return
+        }
+        let _ = self.display.display_mode(ModeState::Off);
+        let _ = self.display.sleep_mode(ModeState::On);
+        self.asleep = Some(Instant::now());
+    }
+
+    // The panel keeps its memory through Sleep In, but everything is redrawn in case it changed since
+    fn wake(&mut self, since: Instant) {
+        // A sleep called off right away wakes the panel before it has settled
+        if let Some(left) = PANEL_SLEEP_SETTLE.checked_sub(since.elapsed()) {
+            Delay::new().delay_millis(left.as_millis() as u32);
+        }
+        let _ = self.display.sleep_mode(ModeState::Off);
+        // Sleep Out needs 5ms before the next command
+        Delay::new().delay_millis(5);
+        let _ = self.display.display_mode(ModeState::On);
+        self.asleep = None;
+        self.invalidate();
+    }
+
+    // Forget what is on screen so the next payload is drawn in full
+    pub fn invalidate(&mut self) {
+        self.drawn_segments = [None; 2];
+        self.drawn_divider = None;
+        self.drawn_laps = None;
+        self.drawn_goal = None;
+        self.drawn_presets = None;
+        self.drawn_stats = None;
     }
 
     pub fn initialize_scene(&mut self) {
@@ -108,11 +210,44 @@ impl<'spi> TFT<'spi> {
     pub fn handle_payload(&mut self, panel: &Panel) {
         let frame = &panel.0;
         let payload = panel.1;
+        if matches!(payload, Payload::Sleep) {
+            self.sleep();
+            return
+        }
+        if let Some(since) = self.asleep {
+            self.wake(since);
+        }
         let state = match frame {
             PanelPosition::Top => SessionState::Working,
             PanelPosition::Bottom => SessionState::Break,
             _ => SessionState::Paused
         };
+        self.divider_clock = match payload {
+            Payload::Timers(frame) => frame.time_of_day,
+            _ => None
+        };
+        self.divider_battery = match payload {
+            Payload::Timers(frame) => frame.battery,
+            _ => None
+        };
+        // The goal bar belongs to the timer screen
+        if !matches!(payload, Payload::Timers(_)) {
+            self.clear_goal();
+        }
+        let palette = match payload {
+            Payload::Timers(frame) => frame.palette,
+            _ => Palette::default()
+        };
+        if palette != self.palette {
+            self.palette = palette;
+            self.invalidate();
+        }
+        // The preset list and stats cover the whole screen, so whatever comes next is drawn from scratch
+        let full_screen = matches!(payload, Payload::Presets(_) | Payload::Stats(_));
+        if (self.drawn_presets.is_some() || self.drawn_stats.is_some()) && !full_screen {
+            let _ = self.display.clear(Rgb565::BLACK);
+            self.invalidate();
+        }
 
         match payload {
             Payload::Time(bytes) => {
@@ -125,37 +260,71 @@ impl<'spi> TFT<'spi> {
                 self.render_divider(state);
             },
             Payload::Animate(animation) => {
-                // Only add the animation to the queue if there's space
-                if let Some(index) = self.scene_manager
-                    .animation_queue
-                    .queue
-                    .iter()
-                    .position(|a| matches!(a, Animation::Empty)) {
-
-                        self.scene_manager
-                            .animation_queue
-                            .queue[index] = animation;
-
-                        self.playing_animation = true;
-                        self.render_next_frame();
-                }
+                self.queue_animation(animation);
             }
             Payload::NewScene(new_scene) => {
                 self.scene_manager.initialize_scene(new_scene);
             }
+            Payload::Timers(frame) => {
+                self.render_timers(&frame);
+            }
+            Payload::Chess(frame) => {
+                self.render_chess(&frame);
+            }
+            Payload::Stopwatch(stopwatch) => {
+                self.render_stopwatch(frame, &stopwatch);
+            }
+            Payload::SetClock(frame) => {
+                self.render_clock_setter(&frame);
+            }
+            Payload::Interval(frame) => {
+                self.render_interval(&frame);
+            }
+            Payload::Presets(frame) => {
+                self.render_presets(&frame);
+            }
+            Payload::Stats(frame) => {
+                self.render_stats(&frame);
+            }
             _ => (),
         }
     }
 
+    pub fn queue_animation(&mut self, animation: Animation) {
+        // Only add the animation to the queue if there's space
+        if let Some(index) = self.scene_manager
+            .animation_queue
+            .queue
+            .iter()
+            .position(|a| matches!(a, Animation::Empty)) {
+
+                self.scene_manager
+                    .animation_queue
+                    .queue[index] = animation;
+
+                self.playing_animation = true;
+                self.render_next_frame();
+        }
+    }
+
     pub fn render_next_frame(&mut self) {
         // Reset clear display of any animation elements 
-        // to prepare for next frame
-        let initial_data = self.top_frame_buffer.data;
-        let area = self.display.bounding_box();
-
-        self.display
-            .fill_contiguous(&area, initial_data)
-            .unwrap();
+        // to prepare for next frame.
+        // Only the cursor needs this; other frames are drawn over the current screen.
+        let needs_clear = self.scene_manager
+            .animation_queue
+            .queue
+            .iter()
+            .any(|animation| matches!(animation, Animation::Cursor(_)));
+        if needs_clear {
+            let initial_data = self.top_frame_buffer.data;
+            let area = self.display.bounding_box();
+
+            self.display
+                .fill_contiguous(&area, initial_data)
+                .unwrap();
+            self.invalidate();
+        }
 
         // Grab array of frames to be rendered
         let frame_queue = self.scene_manager.play_next();
@@ -168,6 +337,9 @@ impl<'spi> TFT<'spi> {
         for frame in frame_queue {
             match frame {
                 FrameType::Rectangle(rect) => self.animate_cursor(rect),
+                FrameType::Fill(area, color) => {
+                    let _ = self.display.fill_solid(&area, color);
+                }
                 FrameType::Empty => empty_count += 1
             }
         }
@@ -195,40 +367,369 @@ impl<'spi> TFT<'spi> {
             .unwrap();
     }
     
+    // Both totals are always on screen; whichever one isn't counting (or edited) is dimmed
+    pub fn render_timers(&mut self, frame: &TimersFrame) {
+        let Palette { work, rest } = frame.palette;
+        let (work_color, break_color) = match frame.editing.unwrap_or(frame.state) {
+            SessionState::Working => (work, dimmed(rest)),
+            SessionState::Break => (dimmed(work), rest),
+            SessionState::Paused => (dimmed(work), dimmed(rest))
+        };
+        let work_time = str::from_utf8(&frame.work_time).unwrap_or("error");
+        let break_time = str::from_utf8(&frame.break_time).unwrap_or("error");
+
+        self.render_segmented_colored(&PanelPosition::Top, work_time, work_color);
+        self.render_segmented_colored(&PanelPosition::Bottom, break_time, break_color);
+        match frame.goal {
+            Some(progress) => self.render_goal(&progress),
+            None => self.clear_goal()
+        }
+        if let Some(seconds) = frame.idle_countdown {
+            let mut label: String<16> = String::new();
+            let _ = write!(label, "still there? {}", seconds);
+            self.render_labeled_divider(frame.state, &label);
+            return
+        }
+        match frame.editing {
+            Some(SessionState::Break) => self.render_labeled_divider(SessionState::Break, "edit break"),
+            Some(_) => self.render_labeled_divider(SessionState::Working, "edit work"),
+            None => self.render_divider(frame.state)
+        }
+    }
+
+    // Fills as work accumulates and turns green with a short flash once the goal is reached
+    fn render_goal(&mut self, progress: &GoalProgress) {
+        let reached = progress.reached();
+        let filled = GOAL_BAR.size.width * u32::from(progress.permille()) / 1000;
+        let drawn = Some((filled, reached));
+        if self.drawn_goal == drawn {
+            return
+        }
+        let just_reached = matches!(self.drawn_goal, Some((_, false))) && reached;
+        self.drawn_goal = drawn;
+
+        let (color, background) = if reached {
+            (GOAL_COLOR, GOAL_COLOR)
+        } else {
+            (self.palette.work, dimmed(self.palette.work))
+        };
+        let _ = self.display.fill_solid(&GOAL_BAR, background);
+        let filled_area = Rectangle::new(GOAL_BAR.top_left, Size::new(filled, GOAL_BAR.size.height));
+        let _ = self.display.fill_solid(&filled_area, color);
+
+        if just_reached {
+            let celebration = Celebration::new(GOAL_BAR, GOAL_COLOR, Rgb565::WHITE);
+            self.queue_animation(Animation::Celebrate(celebration));
+        }
+    }
+
+    fn clear_goal(&mut self) {
+        if self.drawn_goal.take().is_some() {
+            let _ = self.display.fill_solid(&GOAL_BAR, Rgb565::BLACK);
+        }
+    }
+
+    pub fn render_chess(&mut self, frame: &ChessFrame) {
+        // Flag fall turns the side that ran out of time red
+        for side in [ChessSide::Top, ChessSide::Bottom] {
+            let (position, bytes) = match side {
+                ChessSide::Top => (PanelPosition::Top, &frame.top),
+                ChessSide::Bottom => (PanelPosition::Bottom, &frame.bottom)
+            };
+            let message = str::from_utf8(bytes).unwrap_or("error");
+            if frame.flagged == Some(side) {
+                self.render_segmented_colored(&position, message, FLAG_COLOR);
+            } else {
+                self.render_segmented(&position, message);
+            }
+        }
+
+        self.render_labeled_divider(frame.divider_state(), &frame.label());
+    }
+
+    // Running time on the upper panel, laps on the one the frame was sent to
+    pub fn render_stopwatch(&mut self, laps_panel: &PanelPosition, frame: &StopwatchFrame) {
+        let style = compact_segment_style(WORK_COLOR);
+        let area = Rectangle::new(PanelPosition::Top.get_rect().top_left, Size::new(300, style.digit_size.height));
+        let message = str::from_utf8(&frame.time).unwrap_or("error");
+        self.draw_segments(&PanelPosition::Top, area, message, style);
+
+        self.render_lap_list(laps_panel.touch_area(), &frame.laps);
+        self.render_labeled_divider(frame.divider_state(), &frame.label());
+    }
+
+    pub fn render_clock_setter(&mut self, frame: &ClockFrame) {
+        let time = str::from_utf8(&frame.time).unwrap_or("error");
+        let value = str::from_utf8(&frame.value).unwrap_or("error");
+        self.render_segmented_colored(&PanelPosition::Top, time, WORK_COLOR);
+        self.render_segmented_colored(&PanelPosition::Bottom, value, BREAK_COLOR);
+        self.render_labeled_divider(SessionState::Working, frame.label());
+    }
+
+    pub fn render_interval(&mut self, frame: &IntervalFrame) {
+        let color = match frame.kind {
+            SegmentKind::Work => WORK_COLOR,
+            SegmentKind::Rest => BREAK_COLOR
+        };
+        let top = str::from_utf8(&frame.top).unwrap_or("error");
+        let bottom = str::from_utf8(&frame.bottom).unwrap_or("error");
+        self.render_segmented_colored(&PanelPosition::Top, top, color);
+        self.render_segmented_colored(&PanelPosition::Bottom, bottom, dimmed(color));
+        self.render_labeled_divider(frame.divider_state(), &frame.label());
+    }
+
+    // Full screen list of preset names with the selected one highlighted
+    pub fn render_presets(&mut self, frame: &PresetsFrame) {
+        let drawn = Some((frame.selected, frame.count));
+        if self.drawn_presets == drawn {
+            return
+        }
+        self.drawn_presets = drawn;
+        self.drawn_stats = None;
+
+        let _ = self.top_frame_buffer.clear(Rgb565::BLACK);
+        let title_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_14_POINT)
+            .text_color(Rgb565::WHITE)
+            .build();
+        Text::with_baseline("choose a preset", Point::new(20, 12), title_style, Baseline::Middle)
+            .draw(&mut self.top_frame_buffer)
+            .unwrap();
+
+        for (index, element) in frame.elements().iter().enumerate() {
+            let Some(node) = element.node() else {
+                continue
+            };
+            let selected = index == frame.selected;
+            let row = *node.get_position();
+            if selected {
+                let _ = row
+                    .into_styled(PrimitiveStyle::with_fill(dimmed(WORK_COLOR)))
+                    .draw(&mut self.top_frame_buffer);
+            }
+            let character_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+                .font(&PROFONT_18_POINT)
+                .text_color(if selected { Rgb565::WHITE } else { WORK_COLOR })
+                .build();
+            Text::with_baseline(frame.name(index), row.top_left + Point::new(10, 14), character_style, Baseline::Middle)
+                .draw(&mut self.top_frame_buffer)
+                .unwrap();
+        }
+
+        let area = self.display.bounding_box();
+        self.display.fill_contiguous(&area, self.top_frame_buffer.data).unwrap();
+    }
+
+    // Today's totals on top, then one row per archived day
+    pub fn render_stats(&mut self, frame: &StatsFrame) {
+        if self.drawn_stats.as_ref() == Some(frame) {
+            return
+        }
+        self.drawn_stats = Some(*frame);
+        self.drawn_presets = None;
+
+        let _ = self.top_frame_buffer.clear(Rgb565::BLACK);
+        let title_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_14_POINT)
+            .text_color(Rgb565::WHITE)
+            .build();
+        let work_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_18_POINT)
+            .text_color(WORK_COLOR)
+            .build();
+        let break_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_18_POINT)
+            .text_color(BREAK_COLOR)
+            .build();
+        let row_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_14_POINT)
+            .text_color(dimmed(Rgb565::WHITE))
+            .build();
+
+        let mut title: String<24> = String::new();
+        let _ = match frame.today.goal_progress() {
+            Some(progress) => write!(title, "today  {}% of goal", progress.permille() / 10),
+            None => write!(title, "today")
+        };
+        Text::with_baseline(&title, Point::new(20, 12), title_style, Baseline::Middle)
+            .draw(&mut self.top_frame_buffer)
+            .unwrap();
+        let work = format_duration_with(frame.today.work, DisplayPrecision::Seconds);
+        let break_time = format_duration_with(frame.today.break_time, DisplayPrecision::Seconds);
+        Text::with_baseline(str::from_utf8(&work[..8]).unwrap_or("error"), Point::new(20, 44), work_style, Baseline::Middle)
+            .draw(&mut self.top_frame_buffer)
+            .unwrap();
+        Text::with_baseline(str::from_utf8(&break_time[..8]).unwrap_or("error"), Point::new(180, 44), break_style, Baseline::Middle)
+            .draw(&mut self.top_frame_buffer)
+            .unwrap();
+
+        for (row, day) in frame.past.iter().enumerate() {
+            let Some(day) = day else { continue };
+            let work = format_duration_with(day.work, DisplayPrecision::Seconds);
+            let break_time = format_duration_with(day.break_time, DisplayPrecision::Seconds);
+
+            let mut line: String<40> = String::new();
+            let _ = write!(
+                line,
+                "{:>2}d  {}  {}",
+                frame.today.day.saturating_sub(day.day),
+                str::from_utf8(&work[..8]).unwrap_or("error"),
+                str::from_utf8(&break_time[..8]).unwrap_or("error"));
+            if let Some(progress) = day.goal_progress() {
+                let _ = write!(line, " {:>3}%", progress.permille() / 10);
+            }
+
+            let position = Point::new(20, 90 + row as i32 * 32);
+            Text::with_baseline(&line, position, row_style, Baseline::Middle)
+                .draw(&mut self.top_frame_buffer)
+                .unwrap();
+        }
+
+        let area = self.display.bounding_box();
+        self.display.fill_contiguous(&area, self.top_frame_buffer.data).unwrap();
+    }
+
+    // One "#n lap split" row per lap, filling `area`
+    fn render_lap_list(&mut self, area: Rectangle, laps: &[Option<Lap>; LAP_ROWS]) {
+        if self.drawn_laps.as_ref() == Some(laps) {
+            return
+        }
+        self.drawn_laps = Some(*laps);
+
+        let _ = self.top_frame_buffer.fill_solid(&self.top_frame_buffer.bounding_box(), Rgb565::BLACK);
+
+        let character_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_14_POINT)
+            .text_color(BREAK_COLOR)
+            .build();
+
+        for (row, lap) in laps.iter().enumerate() {
+            let Some(lap) = lap else { continue };
+            let lap_time = format_duration_with(lap.lap, DisplayPrecision::Hundredths);
+            let split_time = format_duration_with(lap.split, DisplayPrecision::Hundredths);
+
+            let mut line: String<32> = String::new();
+            let _ = write!(
+                line,
+                "#{:<3} {} {}",
+                lap.number,
+                str::from_utf8(&lap_time[..11]).unwrap_or("error"),
+                str::from_utf8(&split_time[..11]).unwrap_or("error"));
+
+            let position = Point::new(20, row as i32 * 24 + 12);
+            Text::with_baseline(&line, position, character_style, Baseline::Middle)
+                .draw(&mut self.top_frame_buffer)
+                .unwrap();
+        }
+
+        self.display.fill_contiguous(&area, self.top_frame_buffer.data).unwrap();
+    }
+
     #[inline]
     pub fn render_segmented(&mut self, frame: &PanelPosition, message: &str) {
+        let color = match frame {
+            PanelPosition::Top => WORK_COLOR,
+            PanelPosition::Bottom => BREAK_COLOR,
+            _ => return
+        };
+        self.render_segmented_colored(frame, message, color);
+    }
+
+    #[inline]
+    pub fn render_segmented_colored(&mut self, frame: &PanelPosition, message: &str, color: Rgb565) {
+        let style = if message.trim_end_matches([' ', '\0']).len() > FULL_SIZE_CHARS {
+            compact_segment_style(color)
+        } else {
+            segment_style(color)
+        };
+
         // Set buffer area to the corresponding timer location.
-        let ( area, color ) = match frame {
-            PanelPosition::Top => {
-                ( Rectangle::new(Point::new(30, 20), self.top_frame_buffer.size()), Rgb565::new(123, 191, 255) )
-            }
-            PanelPosition::Bottom => {
-                ( Rectangle::new(Point::new(30, 170), self.top_frame_buffer.size()), Rgb565::new(255, 148, 150) )
-            }
+        let area = match frame {
+            PanelPosition::Top => Rectangle::new(Point::new(30, 20), Size::new(PANEL_WIDTH, style.digit_size.height)),
+            PanelPosition::Bottom => Rectangle::new(Point::new(30, 170), Size::new(PANEL_WIDTH, style.digit_size.height)),
             _ => return
         };
-        // Reset the buffer to black, but don't draw to the screen yet
-        let _ = &mut self.top_frame_buffer.fill_solid(&self.top_frame_buffer.bounding_box(), Rgb565::BLACK).unwrap();
-
-        let style = SevenSegmentStyleBuilder::new()
-            .digit_size(Size::new(30, 50))
-            .digit_spacing(10)
-            .segment_width(5)
-            .segment_color(color)
-            .build();
+        self.draw_segments(frame, area, message, style);
+    }
+
+    // Only characters that differ from the last frame in this panel are sent over SPI
+    fn draw_segments(&mut self, frame: &PanelPosition, area: Rectangle, message: &str, style: SevenSegmentStyle<Rgb565>) {
+        let slot = match frame {
+            PanelPosition::Top => 0,
+            PanelPosition::Bottom => 1,
+            _ => return
+        };
+        let mut text = [b' '; 20];
+        let len = message.len().min(text.len());
+        text[..len].copy_from_slice(&message.as_bytes()[..len]);
 
-        let center = Point::new(0, 25);
-        let text = Text::with_baseline(message, center, style, Baseline::Middle);
+        let previous = self.drawn_segments[slot].filter(|drawn| drawn.area == area && drawn.style == style);
+        self.drawn_segments[slot] = Some(DrawnSegments { area, style, text });
+
+        let Some(previous) = previous else {
+            self.blit_segments(area, &text, style);
+            return
+        };
+
+        let changed = |(new, old): (&u8, u8)| *new != old;
+        let Some(first) = text.iter().zip(previous.text).position(changed) else {
+            return
+        };
+        let last = text.iter().zip(previous.text).rposition(changed).unwrap_or(first);
 
-        // Write time pixel data to the buffer
-        let _ = text.draw(&mut self.top_frame_buffer).unwrap();
+        // Characters are laid out left to right, so the prefix width gives the changed digit's x
+        let offset = if first == 0 {
+            0
+        } else {
+            segments_width(&text[..first], style) + style.digit_spacing
+        };
+        let changed_area = Rectangle::new(
+            area.top_left + Point::new(offset as i32, 0),
+            Size::new(segments_width(&text[first..=last], style), area.size.height))
+            .intersection(&area);
+        self.blit_segments(changed_area, &text[first..=last], style);
+    }
 
-        // Finally, draw the buffer to the screen
-        let _ = self.display.fill_contiguous(&area, self.top_frame_buffer.data).unwrap();
+    // Draws into the top-left corner of the frame buffer and sends just `area` worth of pixels
+    fn blit_segments(&mut self, area: Rectangle, text: &[u8], style: SevenSegmentStyle<Rgb565>) {
+        if area.is_zero_sized() {
+            return
+        }
+        let region = Rectangle::new(Point::zero(), area.size);
+        let _ = self.top_frame_buffer.fill_solid(&region, Rgb565::BLACK);
+
+        let message = str::from_utf8(text).unwrap_or("error");
+        let _ = Text::with_baseline(message, Point::zero(), style, Baseline::Top)
+            .draw(&mut self.top_frame_buffer);
+
+        let width = area.size.width as usize;
+        let stride = self.top_frame_buffer.width();
+        let data = &self.top_frame_buffer.data;
+        let pixels = (0..area.size.height as usize)
+            .flat_map(|row| data[row * stride..row * stride + width].iter().copied());
+        let _ = self.display.fill_contiguous(&area, pixels);
     }
 
     #[inline]
     pub fn render_divider(&mut self, mode: SessionState) {
+        let label = match mode {
+            SessionState::Working => "working",
+            SessionState::Break => "on break",
+            SessionState::Paused => "paused"
+        };
+        self.render_labeled_divider(mode, label);
+    }
+
+    pub fn render_labeled_divider(&mut self, mode: SessionState, label: &str) {
+        // Timer payloads arrive many times a second; the divider rarely changes
+        let mut drawn_label: String<16> = String::new();
+        let _ = drawn_label.push_str(label);
+        let drawn = Some((mode, drawn_label, self.divider_clock, self.divider_battery));
+        if self.drawn_divider == drawn {
+            return
+        }
+        self.drawn_divider = drawn;
+
         let mut div_fb = FrameBuf::new_with_origin([Rgb565::BLACK; 320 * 40], 320, 40, Point::new(0, 100));
         let area = Rectangle::new(Point::new(0, 100), div_fb.size());
 
@@ -270,7 +771,6 @@ impl<'spi> TFT<'spi> {
                 .draw(&mut div_fb)
                 .unwrap();
 
-            let text = "paused";
             let text_style = TextStyleBuilder::new()
                 .alignment(Alignment::Right)
                 .baseline(Baseline::Middle)
@@ -282,35 +782,34 @@ impl<'spi> TFT<'spi> {
 
             let top_position = Point::new(290, 20);
             Text::with_text_style(
-                text,
+                label,
                 top_position,
                 character_style,
                 text_style)
             .draw(&mut div_fb).unwrap();
+            self.draw_divider_clock(&mut div_fb, mode, Rgb565::WHITE);
+            self.draw_battery(&mut div_fb, Rgb565::WHITE);
 
             let _ = self.display.fill_contiguous(&area, div_fb.data).unwrap();
             return
         }
 
-        let ( color, running_icon, text, line_points ) = match mode {
+        let ( color, running_icon, line_points ) = match mode {
             // Light Blue, Pointing Up
             SessionState::Working => { 
-                (Rgb565::new(123, 191, 255),
+                (self.palette.work,
                  Triangle::new(Point::new(25, 30), Point::new(55, 30), Point::new(40, 10)),
-                 "working",
                  &working_divider_points)
             },
             // Salmon Pink, Pointing Down
             SessionState::Break => { 
-                (Rgb565::new(255, 148, 150),
+                (self.palette.rest,
                  Triangle::new(Point::new(25, 10), Point::new(55, 10), Point::new(40, 30)),
-                 "on break",
                  &break_divider_points)
             },
             SessionState::Paused => { 
                 (Rgb565::WHITE,
                  Triangle::new(Point::new(25, 30), Point::new(55, 30), Point::new(40, 10)),
-                 "paused",
                  &working_divider_points)
             },
         };
@@ -335,16 +834,73 @@ impl<'spi> TFT<'spi> {
 
         let text_position = Point::new(290, 20);
         Text::with_text_style(
-            text,
+            label,
             text_position,
             character_style,
             text_style)
         .draw(&mut div_fb).unwrap();
+        self.draw_divider_clock(&mut div_fb, mode, color);
+        self.draw_battery(&mut div_fb, color);
 
         // Draw buffer to display
         let _ = self.display.fill_contiguous(&area, div_fb.data).unwrap();
     }
 
+    // Small "HH:MM" in the stretch of the divider where the line leaves room for it
+    fn draw_divider_clock<D>(&self, target: &mut D, mode: SessionState, color: Rgb565)
+    where
+        D: DrawTarget<Color = Rgb565>
+    {
+        let Some((hours, minutes)) = self.divider_clock else {
+            return
+        };
+        let y = match mode {
+            SessionState::Working => 27,
+            SessionState::Break => 13,
+            SessionState::Paused => 20
+        };
+
+        let mut text: String<8> = String::new();
+        let _ = write!(text, "{:02}:{:02}", hours, minutes);
+        let text_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        let character_style: MonoTextStyle<'_, Rgb565> = MonoTextStyleBuilder::new()
+            .font(&PROFONT_14_POINT)
+            .text_color(color)
+            .build();
+        let _ = Text::with_text_style(&text, Point::new(115, y), character_style, text_style)
+            .draw(target);
+    }
+
+    // Cell outline right of the label, filled in proportion to the charge left
+    fn draw_battery<D>(&self, target: &mut D, color: Rgb565)
+    where
+        D: DrawTarget<Color = Rgb565>
+    {
+        let Some(status) = self.divider_battery else {
+            return
+        };
+        let fill = match status.charge {
+            ChargeState::Charging | ChargeState::Full => Rgb565::GREEN,
+            ChargeState::Discharging if status.percent <= BATTERY_LOW => Rgb565::RED,
+            ChargeState::Discharging => color
+        };
+
+        let _ = Rectangle::new(Point::new(296, 14), Size::new(18, 12))
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(target);
+        // Positive terminal
+        let _ = Rectangle::new(Point::new(314, 17), Size::new(2, 6))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(target);
+        let width = 14 * u32::from(status.percent.min(100)) / 100;
+        let _ = Rectangle::new(Point::new(298, 16), Size::new(width.max(1), 8))
+            .into_styled(PrimitiveStyle::with_fill(fill))
+            .draw(target);
+    }
+
     pub fn draw_image(&mut self) {
         let data = include_bytes!("../src/assets/background-white.tga");
         let tga: Tga<Rgb565> = Tga::from_slice(data).unwrap();
@@ -352,3 +908,32 @@ impl<'spi> TFT<'spi> {
         image.draw(&mut self.display).unwrap();
     }
 }
+
+fn segment_style(color: Rgb565) -> SevenSegmentStyle<Rgb565> {
+    SevenSegmentStyleBuilder::new()
+        .digit_size(Size::new(30, 50))
+        .digit_spacing(10)
+        .segment_width(5)
+        .segment_color(color)
+        .build()
+}
+
+// Smaller digits so "HH:MM:SS.hh" still fits across the screen
+fn compact_segment_style(color: Rgb565) -> SevenSegmentStyle<Rgb565> {
+    SevenSegmentStyleBuilder::new()
+        .digit_size(Size::new(22, 40))
+        .digit_spacing(6)
+        .segment_width(4)
+        .segment_color(color)
+        .build()
+}
+
+fn segments_width(text: &[u8], style: SevenSegmentStyle<Rgb565>) -> u32 {
+    let message = str::from_utf8(text).unwrap_or("");
+    style.measure_string(message, Point::zero(), Baseline::Top).bounding_box.size.width
+}
+
+// A third of the brightness keeps the digits readable without competing with the running timer
+fn dimmed(color: Rgb565) -> Rgb565 {
+    Rgb565::new(color.r() / 3, color.g() / 3, color.b() / 3)
+}
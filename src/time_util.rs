@@ -1,7 +1,6 @@
-use core::ops::AddAssign;
 use embassy_time::{Duration, Instant};
 
-use crate::clock_util::SessionState;
+use crate::{clock_util::SessionState, draw_panels::{Palette, TimersFrame}};
 
 struct SingleTime {
     last_update: Instant,
@@ -9,101 +8,206 @@ struct SingleTime {
     is_running: bool
 }
 
-// Duration to be rendered on display
+impl SingleTime {
+    // Folds the time since the last update into the total and keeps counting
+    fn run(&mut self, now: Instant) {
+        if self.is_running {
+            self.seconds_running += now - self.last_update;
+        }
+        self.last_update = now;
+        self.is_running = true;
+    }
+
+    fn stop(&mut self, now: Instant) {
+        if self.is_running {
+            self.seconds_running += now - self.last_update;
+            self.is_running = false;
+        }
+    }
+
+    // Removes up to `amount` from the total and returns how much was actually removed
+    fn take(&mut self, amount: Duration, now: Instant) -> Duration {
+        if self.is_running {
+            self.seconds_running += now - self.last_update;
+        }
+        self.last_update = now;
+
+        let taken = amount.min(self.seconds_running);
+        self.seconds_running -= taken;
+        taken
+    }
+}
+
+// How many fractional digits follow the seconds
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayPrecision {
+    #[default]
+    Seconds,
+    Tenths,
+    Hundredths
+}
+
+impl DisplayPrecision {
+    pub const ALL: [Self; 3] = [Self::Seconds, Self::Tenths, Self::Hundredths];
+
+    pub const fn units_per_second(self) -> u64 {
+        match self {
+            Self::Seconds => 1,
+            Self::Tenths => 10,
+            Self::Hundredths => 100
+        }
+    }
+
+    // The display only needs to wake up when its last digit can change
+    pub const fn refresh_interval(self) -> Duration {
+        Duration::from_millis(1000 / self.units_per_second())
+    }
+}
+
+/*
+ * Duration to be rendered on display.
+ * Every method that counts takes the current Instant, so a recorded session can be replayed on synthetic time.
+ */
 pub struct Time {
-    offset: Duration,
     work_time: SingleTime,
     break_time: SingleTime,
-    paused: bool
+    paused: bool,
+    precision: DisplayPrecision
 }
 
-impl Default for Time {
-    fn default() -> Self {
+impl Time {
+    pub const fn new(now: Instant) -> Self {
         let work_time = SingleTime {
-            last_update: Instant::now(),
+            last_update: now,
             seconds_running: Duration::from_secs(0),
             is_running: true
         };
 
         let break_time = SingleTime {
-            last_update: Instant::now(),
+            last_update: now,
             seconds_running: Duration::from_secs(0),
             is_running: false
         };
 
         Self {
-            offset: Duration::from_millis(0),
             work_time,
             break_time,
-            paused: false
+            paused: false,
+            precision: DisplayPrecision::Seconds
         }
     }
-}
 
-impl Time {
-    #[inline]
-    pub fn now(&self) -> Duration {
-        let ms = Instant::now().as_millis() + self.offset.as_millis();
-        Duration::from_millis(ms)
+    pub fn set_precision(&mut self, precision: DisplayPrecision) {
+        self.precision = precision;
     }
 
-    // NOTE: This should take another argument of type SessionState.
-    //       Update individual Self Duration fields based on this.
-    //       Paused shouldn't increment?
-    #[inline]
-    pub fn sleep_for_work(&mut self) -> ([u8; 20], Duration) {
-        let now = self.now();
-        let sleep_duration = Self::until_next(now, Duration::from_secs(1));
+    pub const fn precision(&self) -> DisplayPrecision {
+        self.precision
+    }
 
-        self.break_time.is_running = false;
-        self.paused = false;
-        if !self.work_time.is_running {
-            self.work_time.last_update = Instant::now();
-            self.work_time.is_running = true;
+    pub fn work_elapsed(&self) -> Duration {
+        self.work_time.seconds_running
+    }
+
+    pub fn break_elapsed(&self) -> Duration {
+        self.break_time.seconds_running
+    }
+
+    fn timer_mut(&mut self, state: SessionState) -> Option<&mut SingleTime> {
+        match state {
+            SessionState::Working => Some(&mut self.work_time),
+            SessionState::Break => Some(&mut self.break_time),
+            SessionState::Paused => None
+        }
+    }
+
+    // Credits `amount` that was counted while in `counted` to the timer of `intended` instead.
+    // Pauses have no timer, so moving from one only adds and moving to one only removes.
+    pub fn reassign(&mut self, counted: SessionState, intended: SessionState, amount: Duration, now: Instant) {
+        let moved = match self.timer_mut(counted) {
+            Some(timer) => timer.take(amount, now),
+            None => amount
+        };
+        if let Some(timer) = self.timer_mut(intended) {
+            timer.seconds_running += moved;
         }
-        let elapsed = Instant::now() - self.work_time.last_update;
-        self.work_time.seconds_running += elapsed;
-        self.work_time.last_update = Instant::now();
+    }
 
-        let seconds_now = self.work_time.seconds_running.as_secs();
-        let hours = seconds_now / 3600;
-        let minutes = (seconds_now % 3600) / 60;
-        let seconds = seconds_now % 60;
+    /*
+     * Starts both totals over and returns what they were at the day boundary.
+     * A timer that is still running keeps the part counted after the boundary.
+     */
+    pub fn rollover(&mut self, since_boundary: Duration, now: Instant) -> (Duration, Duration) {
+        let mut archived = [Duration::from_ticks(0); 2];
+        for (timer, archived) in [&mut self.work_time, &mut self.break_time].into_iter().zip(&mut archived) {
+            let total = timer.take(Duration::MAX, now);
+            let kept = if timer.is_running { total.min(since_boundary) } else { Duration::from_ticks(0) };
+            timer.seconds_running = kept;
+            *archived = total - kept;
+        }
+        (archived[0], archived[1])
+    }
 
-        let time_arr = format_time(hours, minutes, seconds);
-        ( time_arr, sleep_duration )
+    // Adds or removes whole minutes from a total, never going below zero.
+    // Returns the change in seconds that was actually applied.
+    pub fn adjust(&mut self, timer: SessionState, minutes: i32, now: Instant) -> i64 {
+        let Some(timer) = self.timer_mut(timer) else {
+            return 0
+        };
+        let amount = Duration::from_secs(60 * u64::from(minutes.unsigned_abs()));
+        if minutes < 0 {
+            -(timer.take(amount, now).as_secs() as i64)
+        } else {
+            timer.seconds_running += amount;
+            amount.as_secs() as i64
+        }
     }
 
     #[inline]
-    pub fn sleep_for_break(&mut self) -> ([u8; 20], Duration) {
-        let now = self.now();
-        let sleep_duration = Self::until_next(now, Duration::from_secs(1));
+    pub fn sleep_for_work(&mut self, now: Instant) -> (TimersFrame, Duration) {
+        let sleep_duration = Self::until_next(since_boot(now), self.precision.refresh_interval());
 
-        self.work_time.is_running = false;
+        self.break_time.stop(now);
         self.paused = false;
-        if !self.break_time.is_running {
-            self.break_time.last_update = Instant::now();
-            self.break_time.is_running = true;
-        }
-        let elapsed = Instant::now() - self.break_time.last_update;
-        self.break_time.seconds_running += elapsed;
-        self.break_time.last_update = Instant::now();
+        self.work_time.run(now);
 
-        let seconds_now = self.break_time.seconds_running.as_secs();
-        let hours = seconds_now / 3600;
-        let minutes = (seconds_now % 3600) / 60;
-        let seconds = seconds_now % 60;
+        ( self.frame(SessionState::Working), sleep_duration )
+    }
+
+    #[inline]
+    pub fn sleep_for_break(&mut self, now: Instant) -> (TimersFrame, Duration) {
+        let sleep_duration = Self::until_next(since_boot(now), self.precision.refresh_interval());
 
-        let time_arr = format_time(hours, minutes, seconds);
-        ( time_arr, sleep_duration )
+        self.work_time.stop(now);
+        self.paused = false;
+        self.break_time.run(now);
+
+        ( self.frame(SessionState::Break), sleep_duration )
     }
 
+    // Both totals stay on screen, frozen at the moment of pausing
     #[inline]
-    pub fn sleep_for_pause(&mut self) -> ([u8; 20], Duration) {
-        self.work_time.is_running = false;
-        self.break_time.is_running = false;
+    pub fn sleep_for_pause(&mut self, now: Instant) -> (TimersFrame, Duration) {
+        self.work_time.stop(now);
+        self.break_time.stop(now);
         self.paused = true;
-        ([0; 20], Duration::from_secs(1))
+        ( self.frame(SessionState::Paused), Duration::from_secs(1) )
+    }
+
+    // Everything on the timer screen is derived from the two totals,
+    // so a full redraw from this frame always matches what was shown before
+    pub fn frame(&self, state: SessionState) -> TimersFrame {
+        TimersFrame {
+            work_time: format_duration_with(self.work_time.seconds_running, self.precision),
+            break_time: format_duration_with(self.break_time.seconds_running, self.precision),
+            state,
+            editing: None,
+            idle_countdown: None,
+            time_of_day: None,
+            goal: None,
+            battery: None,
+            palette: Palette::default()
+        }
     }
 
     #[inline]
@@ -113,32 +217,234 @@ impl Time {
     }
 }
 
-impl AddAssign<Duration> for Time {
-    fn add_assign(&mut self, rhs: Duration) {
-        let ms = self.offset.as_millis() + rhs.as_millis();
-        self.offset = Duration::from_millis(ms)
+// Refreshes line up with whole units since boot, like the timers do
+const fn since_boot(now: Instant) -> Duration {
+    Duration::from_ticks(now.as_ticks())
+}
+
+pub(crate) fn format_duration(duration: Duration) -> [u8; 20] {
+    format_duration_with(duration, DisplayPrecision::Seconds)
+}
+
+// Countdowns show the unit that is still being used up, so 0.4s left reads 00:00:01 in seconds
+pub(crate) fn rounded_up(remaining: Duration, precision: DisplayPrecision) -> Duration {
+    let unit = precision.refresh_interval().as_ticks();
+    Duration::from_ticks(remaining.as_ticks().div_ceil(unit).saturating_mul(unit))
+}
+
+// Truncates to the precision like a stopwatch would, so 59.99s never shows as 1:00
+pub(crate) fn format_duration_with(duration: Duration, precision: DisplayPrecision) -> [u8; 20] {
+    let per_second = precision.units_per_second();
+    let units_now = duration.as_millis() / (1000 / per_second);
+
+    format_elapsed(units_now / per_second, precision, units_now % per_second)
+}
+
+pub(crate) const SECONDS_PER_DAY: u64 = 24 * 3600;
+// Largest hour count that still fits the two hour digits of "HH:MM:SS"
+const MAX_CLOCK_HOURS: u64 = 99;
+// "9999d 23:59" is the widest day layout that still shows the time of day
+const MAX_DAYS_WITH_CLOCK: u64 = 9999;
+
+/*
+ * Picks the layout from the magnitude of the elapsed time:
+ *   up to 99 hours   "HH:MM:SS" plus the fractional digits
+ *   up to 9999 days  "Dd HH:MM"
+ *   anything longer  "Dd"
+ * Every layout fits the 20 byte buffer for the whole u64 range.
+ */
+pub(crate) fn format_elapsed(seconds_now: u64, precision: DisplayPrecision, fraction: u64) -> [u8; 20] {
+    let hours = seconds_now / 3600;
+    let minutes = (seconds_now % 3600) / 60;
+    let seconds = seconds_now % 60;
+    if hours <= MAX_CLOCK_HOURS {
+        return format_time(hours, minutes, seconds, precision, fraction)
+    }
+
+    let days = seconds_now / SECONDS_PER_DAY;
+    let mut writer = DigitWriter::new();
+    writer.push_number(days);
+    writer.push(b'd');
+    if days <= MAX_DAYS_WITH_CLOCK {
+        writer.push(b' ');
+        writer.push_two_digits(hours % 24);
+        writer.push(b':');
+        writer.push_two_digits(minutes);
     }
+    writer.buffer
 }
 
-fn format_time(hours: u64, mins: u64, seconds: u64) -> [u8; 20] {
-    let mut buffer = [b' '; 20]; // Initialize with spaces
-    
-    // Convert numbers to digits
-    let h1 = (hours / 10) as u8 + b'0';
-    let h2 = (hours % 10) as u8 + b'0';
-    let m1 = (mins / 10) as u8 + b'0';
-    let m2 = (mins % 10) as u8 + b'0';
-    let s1 = (seconds / 10) as u8 + b'0';
-    let s2 = (seconds % 10) as u8 + b'0';
-    
+fn format_time(hours: u64, mins: u64, seconds: u64, precision: DisplayPrecision, fraction: u64) -> [u8; 20] {
+    let mut writer = DigitWriter::new();
+
     // Format: "HH:MM:SS.hh"
-    buffer[0] = h1;
-    buffer[1] = h2;
-    buffer[2] = b':';
-    buffer[3] = m1;
-    buffer[4] = m2;
-    buffer[5] = b':';
-    buffer[6] = s1;
-    buffer[7] = s2;
-    buffer
+    writer.push_two_digits(hours);
+    writer.push(b':');
+    writer.push_two_digits(mins);
+    writer.push(b':');
+    writer.push_two_digits(seconds);
+    match precision {
+        DisplayPrecision::Seconds => (),
+        DisplayPrecision::Tenths => {
+            writer.push(b'.');
+            writer.push_digit(fraction);
+        }
+        DisplayPrecision::Hundredths => {
+            writer.push(b'.');
+            writer.push_two_digits(fraction);
+        }
+    }
+    writer.buffer
+}
+
+// Writes ASCII into the display buffer and silently drops anything past its end
+struct DigitWriter {
+    buffer: [u8; 20],
+    len: usize
+}
+
+impl DigitWriter {
+    const fn new() -> Self {
+        Self {
+            buffer: [b' '; 20], // Initialize with spaces
+            len: 0
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if let Some(slot) = self.buffer.get_mut(self.len) {
+            *slot = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_digit(&mut self, value: u64) {
+        self.push((value % 10) as u8 + b'0');
+    }
+
+    fn push_two_digits(&mut self, value: u64) {
+        self.push_digit(value / 10);
+        self.push_digit(value);
+    }
+
+    fn push_number(&mut self, mut value: u64) {
+        // u64::MAX has 20 decimal digits
+        let mut digits = [0u8; 20];
+        let mut count = 0;
+        loop {
+            digits[count] = (value % 10) as u8 + b'0';
+            count += 1;
+            value /= 10;
+            if value == 0 {
+                break
+            }
+        }
+        for digit in digits[..count].iter().rev() {
+            self.push(*digit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::{format_duration_with, format_elapsed, rounded_up, DisplayPrecision, Time, SECONDS_PER_DAY};
+    use crate::clock_util::SessionState;
+
+    fn shown(duration: Duration, precision: DisplayPrecision) -> String {
+        String::from_utf8(format_duration_with(duration, precision).to_vec()).unwrap().trim_end().to_owned()
+    }
+
+    #[test]
+    fn fractions_truncate_instead_of_rounding() {
+        let almost = Duration::from_micros(59_999_999);
+        assert_eq!(shown(almost, DisplayPrecision::Seconds), "00:00:59");
+        assert_eq!(shown(almost, DisplayPrecision::Tenths), "00:00:59.9");
+        assert_eq!(shown(almost, DisplayPrecision::Hundredths), "00:00:59.99");
+        assert_eq!(shown(Duration::from_millis(1_050), DisplayPrecision::Tenths), "00:00:01.0");
+        assert_eq!(shown(Duration::from_millis(1_009), DisplayPrecision::Hundredths), "00:00:01.00");
+    }
+
+    #[test]
+    fn fractions_carry_into_the_clock() {
+        assert_eq!(shown(Duration::from_secs(60), DisplayPrecision::Tenths), "00:01:00.0");
+        assert_eq!(shown(Duration::from_millis(3_599_990), DisplayPrecision::Hundredths), "00:59:59.99");
+        assert_eq!(shown(Duration::from_secs(3_600), DisplayPrecision::Hundredths), "01:00:00.00");
+        assert_eq!(shown(Duration::from_millis(359_999_990), DisplayPrecision::Hundredths), "99:59:59.99");
+        // Past the clock layout there's no room for the fraction
+        assert_eq!(shown(Duration::from_secs(360_000), DisplayPrecision::Hundredths), "4d 04:00");
+    }
+
+    fn elapsed(seconds: u64) -> String {
+        String::from_utf8(format_elapsed(seconds, DisplayPrecision::Hundredths, 0).to_vec()).unwrap().trim_end().to_owned()
+    }
+
+    #[test]
+    fn day_layout_takes_over_after_99_hours() {
+        assert_eq!(elapsed(99 * 3600 + 59 * 60 + 59), "99:59:59.00");
+        assert_eq!(elapsed(100 * 3600), "4d 04:00");
+        assert_eq!(elapsed(SECONDS_PER_DAY * 5 - 1), "4d 23:59");
+        assert_eq!(elapsed(SECONDS_PER_DAY * 9999 + 23 * 3600 + 59 * 60), "9999d 23:59");
+        assert_eq!(elapsed(SECONDS_PER_DAY * 10_000), "10000d");
+    }
+
+    #[test]
+    fn every_magnitude_fits_the_buffer() {
+        assert_eq!(elapsed(u64::MAX), "213503982334601d");
+        assert_eq!(shown(Duration::MAX, DisplayPrecision::Hundredths), "213503982d");
+        // Every power of ten and the value just below it, up to the top of the range
+        let mut magnitude = 1u64;
+        while let Some(next) = magnitude.checked_mul(10) {
+            for seconds in [magnitude - 1, magnitude, next - 1] {
+                let text = elapsed(seconds);
+                assert!(!text.is_empty() && text.len() <= 20, "{seconds}: {text:?}");
+                assert!(text.bytes().all(|byte| byte.is_ascii_digit() || b"d :.".contains(&byte)), "{seconds}: {text:?}");
+            }
+            magnitude = next;
+        }
+    }
+
+    #[test]
+    fn countdowns_round_up_to_the_shown_unit() {
+        let seconds = DisplayPrecision::Seconds;
+        assert_eq!(rounded_up(Duration::from_ticks(0), seconds), Duration::from_ticks(0));
+        assert_eq!(rounded_up(Duration::from_ticks(1), seconds), Duration::from_secs(1));
+        assert_eq!(rounded_up(Duration::from_millis(400), seconds), Duration::from_secs(1));
+        assert_eq!(rounded_up(Duration::from_secs(59), seconds), Duration::from_secs(59));
+        assert_eq!(rounded_up(Duration::from_millis(1_210), DisplayPrecision::Tenths), Duration::from_millis(1_300));
+        assert_eq!(rounded_up(Duration::from_millis(1_200), DisplayPrecision::Tenths), Duration::from_millis(1_200));
+        assert_eq!(rounded_up(Duration::from_micros(1_001), DisplayPrecision::Hundredths), Duration::from_millis(10));
+        assert_eq!(rounded_up(Duration::MAX, seconds), Duration::MAX);
+    }
+
+    #[test]
+    fn totals_count_on_the_given_time() {
+        let start = Instant::from_secs(100);
+        let mut time = Time::new(start);
+        time.sleep_for_work(start + Duration::from_secs(90));
+        time.sleep_for_break(start + Duration::from_secs(90));
+        time.sleep_for_pause(start + Duration::from_secs(120));
+        // Paused, so the hour in between doesn't count
+        time.sleep_for_break(start + Duration::from_secs(3_720));
+        let (frame, _) = time.sleep_for_break(start + Duration::from_secs(3_730));
+        assert_eq!(time.work_elapsed(), Duration::from_secs(90));
+        assert_eq!(time.break_elapsed(), Duration::from_secs(40));
+        assert_eq!(&frame.break_time[..8], b"00:00:40");
+
+        time.reassign(SessionState::Break, SessionState::Working, Duration::from_secs(15), start + Duration::from_secs(3_740));
+        assert_eq!(time.work_elapsed(), Duration::from_secs(105));
+        assert_eq!(time.break_elapsed(), Duration::from_secs(35));
+    }
+
+    #[test]
+    fn refresh_follows_the_last_digit() {
+        assert_eq!(DisplayPrecision::Seconds.refresh_interval(), Duration::from_secs(1));
+        assert_eq!(DisplayPrecision::Tenths.refresh_interval(), Duration::from_millis(100));
+        assert_eq!(DisplayPrecision::Hundredths.refresh_interval(), Duration::from_millis(10));
+
+        let tenth = DisplayPrecision::Tenths.refresh_interval();
+        assert_eq!(Time::until_next(Duration::from_millis(1_234), tenth), Duration::from_millis(66));
+        assert_eq!(Time::until_next(Duration::from_millis(1_200), tenth), tenth);
+    }
 }
@@ -0,0 +1,55 @@
+use embassy_time::{Duration, Instant};
+
+use crate::{button::PressDuration, clock_util::SessionState};
+
+// Editing ends on its own after this long without input
+pub const EDIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+ * "Edit timers" mode of the double timer screen.
+ * Encoder steps add or remove whole minutes from the selected total,
+ * a short press switches between the work and break totals and a long press is done.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerEdit {
+    timer: SessionState,
+    last_input: Instant
+}
+
+impl TimerEdit {
+    // Starts on whichever timer is counting, or the work timer while paused
+    pub const fn new(state: SessionState, now: Instant) -> Self {
+        let timer = match state {
+            SessionState::Break => SessionState::Break,
+            SessionState::Working | SessionState::Paused => SessionState::Working
+        };
+        Self { timer, last_input: now }
+    }
+
+    pub const fn timer(&self) -> SessionState {
+        self.timer
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+
+    // Returns false once editing is finished
+    pub fn press(&mut self, press: PressDuration, now: Instant) -> bool {
+        self.touch(now);
+        match press {
+            PressDuration::Short => {
+                self.timer = match self.timer {
+                    SessionState::Working => SessionState::Break,
+                    _ => SessionState::Working
+                };
+                true
+            }
+            PressDuration::Long => false
+        }
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_input) > EDIT_TIMEOUT
+    }
+}
@@ -0,0 +1,256 @@
+use embassy_time::{Duration, Instant};
+use embedded_graphics::prelude::Point;
+use embedded_hal_async::i2c::I2c;
+
+// FT6206 capacitive touch controller on the ER-TFT028-4
+const FT6206_ADDRESS: u8 = 0x38;
+// TD_STATUS, followed by the first touch point's XH, XL, YH and YL registers
+const TOUCH_STATUS: u8 = 0x02;
+// G_MODE, 0 keeps INT low for as long as the panel is touched instead of pulsing it once per report
+const INTERRUPT_MODE: u8 = 0xA4;
+const INTERRUPT_POLLING: u8 = 0x00;
+const PANEL_HEIGHT: i32 = 240;
+
+const LONG_PRESS: Duration = Duration::from_millis(800);
+// Further than this between touching down and lifting off is a swipe rather than a tap
+const SWIPE_DISTANCE: u32 = 60;
+pub const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct Ft6206<I> {
+    i2c: I
+}
+
+impl<I: I2c> Ft6206<I> {
+    pub const fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    // So the INT line can wake the chip, a level is still there by the time it's sampled where a pulse may not be
+    pub async fn hold_interrupt(&mut self) -> Result<(), I::Error> {
+        self.i2c.write(FT6206_ADDRESS, &[INTERRUPT_MODE, INTERRUPT_POLLING]).await
+    }
+
+    // The first touch point in screen coordinates, or None when nothing is touching the panel
+    pub async fn read(&mut self) -> Result<Option<Point>, I::Error> {
+        let mut registers = [0; 5];
+        self.i2c.write_read(FT6206_ADDRESS, &[TOUCH_STATUS], &mut registers).await?;
+        Ok(Self::decode(&registers))
+    }
+
+    /*
+     * The controller reports portrait coordinates, the display is driven in landscape.
+     * A status of 0 or more than 2 touches (noise) reads as no touch.
+     */
+    pub fn decode(registers: &[u8; 5]) -> Option<Point> {
+        let touches = registers[0] & 0x0F;
+        if touches == 0 || touches > 2 {
+            return None
+        }
+        let raw_x = i32::from(registers[1] & 0x0F) << 8 | i32::from(registers[2]);
+        let raw_y = i32::from(registers[3] & 0x0F) << 8 | i32::from(registers[4]);
+        Some(Point::new(raw_y, PANEL_HEIGHT - 1 - raw_x))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchGesture {
+    Tap(Point),
+    LongPress(Point),
+    Swipe(SwipeDirection)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    start: Point,
+    last: Point,
+    since: Instant,
+    // A long press is only reported once per touch
+    held: bool
+}
+
+/*
+ * Turns polled touch points into gestures.
+ * Takes the poll's Instant so it can be driven with synthetic samples.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GestureRecognizer {
+    contact: Option<Contact>
+}
+
+impl GestureRecognizer {
+    pub const fn new() -> Self {
+        Self { contact: None }
+    }
+
+    fn moved(contact: &Contact) -> Point {
+        contact.last - contact.start
+    }
+
+    fn is_swipe(offset: Point) -> bool {
+        offset.x.unsigned_abs().max(offset.y.unsigned_abs()) >= SWIPE_DISTANCE
+    }
+
+    pub fn update(&mut self, touch: Option<Point>, now: Instant) -> Option<TouchGesture> {
+        match (touch, &mut self.contact) {
+            (Some(point), None) => {
+                self.contact = Some(Contact { start: point, last: point, since: now, held: false });
+                None
+            }
+            (Some(point), Some(contact)) => {
+                contact.last = point;
+                let long = now.saturating_duration_since(contact.since) >= LONG_PRESS;
+                if long && !contact.held && !Self::is_swipe(Self::moved(contact)) {
+                    contact.held = true;
+                    return Some(TouchGesture::LongPress(contact.start))
+                }
+                None
+            }
+            (None, Some(contact)) => {
+                let contact = *contact;
+                self.contact = None;
+                let offset = Self::moved(&contact);
+                if Self::is_swipe(offset) {
+                    let direction = if offset.x.abs() >= offset.y.abs() {
+                        if offset.x < 0 { SwipeDirection::Left } else { SwipeDirection::Right }
+                    } else if offset.y < 0 {
+                        SwipeDirection::Up
+                    } else {
+                        SwipeDirection::Down
+                    };
+                    Some(TouchGesture::Swipe(direction))
+                } else if contact.held {
+                    None
+                } else {
+                    Some(TouchGesture::Tap(contact.start))
+                }
+            }
+            (None, None) => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+    use embedded_graphics::prelude::Point;
+
+    use super::*;
+    use crate::testing::{run_timed, MockI2c};
+
+    // TD_STATUS and the first point's registers for a portrait touch at (x, y), with the event flags set on XH
+    const fn registers(touches: u8, x: u16, y: u16) -> [u8; 5] {
+        [touches, 0x80 | (x >> 8) as u8, x as u8, (y >> 8) as u8, y as u8]
+    }
+
+    #[test]
+    fn portrait_points_turn_into_landscape() {
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(1, 0, 0)), Some(Point::new(0, 239)));
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(1, 239, 319)), Some(Point::new(319, 0)));
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(2, 120, 300)), Some(Point::new(300, 119)));
+    }
+
+    #[test]
+    fn no_touch_and_noise_read_as_nothing() {
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(0, 120, 160)), None);
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(3, 120, 160)), None);
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(0x0F, 120, 160)), None);
+        // The upper nibble of TD_STATUS isn't part of the count
+        assert_eq!(Ft6206::<MockI2c>::decode(&registers(0x41, 0, 0)), Some(Point::new(0, 239)));
+    }
+
+    #[test]
+    fn reads_the_status_registers_over_i2c() {
+        let touch = registers(1, 100, 200);
+        let bus = MockI2c::new(&[Some(&touch), Some(&registers(0, 0, 0)), None]);
+        let mut controller = Ft6206::new(bus);
+        let (reads, _) = run_timed(async {
+            [controller.read().await, controller.read().await, controller.read().await]
+        });
+        assert_eq!(reads[0], Ok(Some(Point::new(200, 139))));
+        assert_eq!(reads[1], Ok(None));
+        assert!(reads[2].is_err());
+        assert!(controller.i2c.writes.iter().all(|write| *write == (FT6206_ADDRESS, vec![TOUCH_STATUS])));
+        assert_eq!(controller.i2c.writes.len(), 3);
+    }
+
+    #[test]
+    fn interrupt_is_switched_to_a_level() {
+        let mut controller = Ft6206::new(MockI2c::new(&[]));
+        let (result, _) = run_timed(controller.hold_interrupt());
+        assert_eq!(result, Ok(()));
+        assert_eq!(controller.i2c.writes, [(FT6206_ADDRESS, vec![INTERRUPT_MODE, INTERRUPT_POLLING])]);
+    }
+
+    // Feeds a sample every POLL_INTERVAL and collects the gestures
+    fn gestures(samples: &[Option<(i32, i32)>]) -> Vec<TouchGesture> {
+        let mut recognizer = GestureRecognizer::new();
+        let mut now = Instant::from_secs(1);
+        let mut found = Vec::new();
+        for sample in samples {
+            now += POLL_INTERVAL;
+            if let Some(gesture) = recognizer.update(sample.map(|(x, y)| Point::new(x, y)), now) {
+                found.push(gesture);
+            }
+        }
+        found
+    }
+
+    fn held(point: (i32, i32), polls: usize) -> Vec<Option<(i32, i32)>> {
+        vec![Some(point); polls]
+    }
+
+    #[test]
+    fn short_touch_is_a_tap_where_it_started() {
+        let mut samples = held((100, 50), 3);
+        samples.push(Some((110, 55)));
+        samples.push(None);
+        assert_eq!(gestures(&samples), [TouchGesture::Tap(Point::new(100, 50))]);
+        assert_eq!(gestures(&[None, None]), []);
+    }
+
+    #[test]
+    fn holding_still_is_one_long_press() {
+        let polls = (LONG_PRESS.as_ticks() / POLL_INTERVAL.as_ticks()) as usize;
+        let mut samples = held((20, 200), polls + 10);
+        samples.push(None);
+        assert_eq!(gestures(&samples), [TouchGesture::LongPress(Point::new(20, 200))]);
+
+        let mut short = held((20, 200), polls - 1);
+        short.push(None);
+        assert_eq!(gestures(&short), [TouchGesture::Tap(Point::new(20, 200))]);
+    }
+
+    #[test]
+    fn swipes_go_the_way_the_finger_moved_most() {
+        for (end, direction) in [
+            ((100, 120), SwipeDirection::Left),
+            ((220, 100), SwipeDirection::Right),
+            ((170, 40), SwipeDirection::Up),
+            ((150, 200), SwipeDirection::Down)
+        ] {
+            let samples = [Some((160, 120)), Some(((160 + end.0) / 2, (120 + end.1) / 2)), Some(end), None];
+            assert_eq!(gestures(&samples), [TouchGesture::Swipe(direction)], "{:?}", end);
+        }
+        // Just short of the swipe distance is still a tap
+        let samples = [Some((160, 120)), Some((160 + SWIPE_DISTANCE as i32 - 1, 120)), None];
+        assert_eq!(gestures(&samples), [TouchGesture::Tap(Point::new(160, 120))]);
+    }
+
+    #[test]
+    fn a_slow_swipe_is_not_a_long_press() {
+        let polls = (LONG_PRESS.as_ticks() / POLL_INTERVAL.as_ticks()) as usize;
+        let mut samples = held((40, 120), 5);
+        samples.extend(held((200, 120), polls));
+        samples.push(None);
+        assert_eq!(gestures(&samples), [TouchGesture::Swipe(SwipeDirection::Right)]);
+        assert!(Duration::from_ticks(POLL_INTERVAL.as_ticks() * polls as u64) >= LONG_PRESS);
+    }
+}
@@ -0,0 +1,168 @@
+use embassy_time::{Duration, Instant};
+
+use crate::{constants::BUILD_TIMESTAMP, time_util::SECONDS_PER_DAY};
+
+/*
+ * Time of day, anchored to an Instant when it was last set.
+ * Kept in UTC seconds since the Unix epoch; local time applies a fixed offset (no DST).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallClock {
+    utc_at_anchor: u64,
+    anchor: Instant
+}
+
+impl WallClock {
+    pub const fn new(utc_seconds: u64, now: Instant) -> Self {
+        Self {
+            utc_at_anchor: utc_seconds,
+            anchor: now
+        }
+    }
+
+    // Best guess when nothing better is known: the moment the firmware was built
+    pub fn from_build_time(now: Instant) -> Self {
+        Self::new(BUILD_TIMESTAMP.parse().unwrap_or(0), now)
+    }
+
+    // A saved time is behind by however long the device was off, but still closer than the build time
+    pub fn from_saved(saved: Option<u64>, now: Instant) -> Self {
+        let build = Self::from_build_time(now);
+        match saved {
+            Some(utc_seconds) if utc_seconds > build.utc_at_anchor => Self::new(utc_seconds, now),
+            _ => build
+        }
+    }
+
+    pub fn set(&mut self, utc_seconds: u64, now: Instant) {
+        *self = Self::new(utc_seconds, now);
+    }
+
+    // Moves the clock on by time Instant didn't count, like light sleep with the timer stopped
+    pub fn skip(&mut self, missed: Duration) {
+        match self.anchor.checked_sub(missed) {
+            Some(anchor) => self.anchor = anchor,
+            None => self.utc_at_anchor += missed.as_secs()
+        }
+    }
+
+    pub fn utc_seconds(&self, now: Instant) -> u64 {
+        self.utc_at_anchor + now.saturating_duration_since(self.anchor).as_secs()
+    }
+
+    pub fn local_seconds(&self, now: Instant, utc_offset_minutes: i16) -> u64 {
+        let offset = i64::from(utc_offset_minutes) * 60;
+        self.utc_seconds(now).saturating_add_signed(offset)
+    }
+}
+
+// Days are counted from one rollover to the next, so 03:59 still belongs to yesterday with a 04:00 rollover.
+// The number is only used to notice a new day, so the one day shift doesn't matter.
+pub const fn day_number(local_seconds: u64, rollover_hour: u8) -> u64 {
+    shifted(local_seconds, rollover_hour) / SECONDS_PER_DAY
+}
+
+// Time already spent in the current day
+pub const fn since_rollover(local_seconds: u64, rollover_hour: u8) -> Duration {
+    Duration::from_secs(shifted(local_seconds, rollover_hour) % SECONDS_PER_DAY)
+}
+
+pub const fn until_rollover(local_seconds: u64, rollover_hour: u8) -> Duration {
+    Duration::from_secs(SECONDS_PER_DAY - shifted(local_seconds, rollover_hour) % SECONDS_PER_DAY)
+}
+
+// Moves the rollover to midnight; a day is added first so times before the first rollover don't underflow
+const fn shifted(local_seconds: u64, rollover_hour: u8) -> u64 {
+    local_seconds + SECONDS_PER_DAY - (rollover_hour as u64 % 24) * 3600
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, Instant};
+
+    use super::{day_number, since_rollover, until_rollover, WallClock};
+    use crate::time_util::SECONDS_PER_DAY;
+
+    // 2025-01-01 00:00:00 UTC
+    const NEW_YEAR: u64 = 1_735_689_600;
+
+    const fn hms(hours: u64, minutes: u64, seconds: u64) -> u64 {
+        hours * 3600 + minutes * 60 + seconds
+    }
+
+    #[test]
+    fn midnight_rollover() {
+        let before = NEW_YEAR - 1;
+        assert_eq!(day_number(before, 0) + 1, day_number(NEW_YEAR, 0));
+        assert_eq!(since_rollover(NEW_YEAR, 0), Duration::from_secs(0));
+        assert_eq!(until_rollover(before, 0), Duration::from_secs(1));
+        // Right at the boundary the next one is a whole day away
+        assert_eq!(until_rollover(NEW_YEAR, 0), Duration::from_secs(SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn early_morning_belongs_to_yesterday() {
+        let evening = NEW_YEAR - hms(2, 0, 0);
+        let after_midnight = NEW_YEAR + hms(3, 59, 59);
+        let rollover = NEW_YEAR + hms(4, 0, 0);
+        assert_eq!(day_number(evening, 4), day_number(after_midnight, 4));
+        assert_eq!(day_number(after_midnight, 4) + 1, day_number(rollover, 4));
+        assert_eq!(since_rollover(after_midnight, 4), Duration::from_secs(hms(23, 59, 59)));
+        assert_eq!(until_rollover(after_midnight, 4), Duration::from_secs(1));
+        assert_eq!(since_rollover(rollover, 4), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn times_before_the_first_rollover() {
+        assert_eq!(day_number(0, 4), day_number(hms(3, 59, 59), 4));
+        assert_eq!(day_number(hms(4, 0, 0), 4), day_number(0, 4) + 1);
+        assert_eq!(until_rollover(0, 4), Duration::from_secs(hms(4, 0, 0)));
+        // Hours past 23 wrap instead of underflowing
+        assert_eq!(day_number(hms(1, 0, 0), 25), day_number(hms(1, 0, 0), 1));
+    }
+
+    #[test]
+    fn offsets_move_local_midnight() {
+        let start = Instant::from_secs(10);
+        let clock = WallClock::new(NEW_YEAR - hms(0, 30, 0), start);
+        // 23:30 UTC is already the next day in India and still the evening in Newfoundland
+        assert_eq!(clock.local_seconds(start, 330) % SECONDS_PER_DAY, hms(5, 0, 0));
+        assert_eq!(clock.local_seconds(start, -210) % SECONDS_PER_DAY, hms(20, 0, 0));
+        assert_eq!(clock.local_seconds(start, 0) % SECONDS_PER_DAY, hms(23, 30, 0));
+        assert!(day_number(clock.local_seconds(start, 330), 0) > day_number(clock.local_seconds(start, 0), 0));
+
+        // Half an hour later midnight has passed in UTC as well
+        let later = start + Duration::from_secs(hms(0, 30, 0));
+        assert_eq!(since_rollover(clock.local_seconds(later, 0), 0), Duration::from_secs(0));
+        assert_eq!(until_rollover(clock.local_seconds(later, -210), 0), Duration::from_secs(hms(3, 30, 0)));
+    }
+
+    #[test]
+    fn negative_offset_near_the_epoch_saturates() {
+        let clock = WallClock::new(60, Instant::from_secs(0));
+        assert_eq!(clock.local_seconds(Instant::from_secs(0), -600), 0);
+    }
+
+    #[test]
+    fn skip_carries_the_clock_over_sleep() {
+        let mut clock = WallClock::new(NEW_YEAR, Instant::from_secs(100));
+        clock.skip(Duration::from_secs(60));
+        assert_eq!(clock.utc_seconds(Instant::from_secs(100)), NEW_YEAR + 60);
+        // Missed time longer than the anchor's distance from boot moves the UTC side instead
+        clock.skip(Duration::from_secs(3600));
+        assert_eq!(clock.utc_seconds(Instant::from_secs(100)), NEW_YEAR + 3660);
+
+        clock.set(NEW_YEAR, Instant::from_secs(200));
+        assert_eq!(clock.utc_seconds(Instant::from_secs(260)), NEW_YEAR + 60);
+    }
+
+    #[test]
+    fn saved_time_only_wins_when_newer_than_the_build() {
+        let now = Instant::from_secs(5);
+        let build = WallClock::from_build_time(now).utc_seconds(now);
+        assert_eq!(WallClock::from_saved(None, now).utc_seconds(now), build);
+        assert_eq!(WallClock::from_saved(Some(build - 60), now).utc_seconds(now), build);
+        let clock = WallClock::from_saved(Some(build + 86_400), now);
+        assert_eq!(clock.utc_seconds(now + Duration::from_secs(10)), build + 86_410);
+    }
+}